@@ -0,0 +1,346 @@
+//! `#[derive(Packet)]` for the packet structs of `open_mc_rust`.
+//!
+//! ```ignore
+//! #[derive(Packet)]
+//! #[packet(id = 0x01, state = Login, direction = Clientbound)]
+//! pub struct ClientboundEncryptionRequestPacket {
+//!     pub server_id: String,
+//!     #[length_prefixed]
+//!     pub public_key: Vec<u8>,
+//!     #[length_prefixed]
+//!     pub verify_token: Vec<u8>,
+//! }
+//! ```
+//!
+//! Fields are written and read in declaration order. Without attributes a field uses its
+//! `Serializable` and `Decode` impls, the attributes pick another wire encoding:
+//!
+//! - `#[varint]`: `i32` as VarInt, `i64` as VarLong
+//! - `#[fixed]`: `i32` and `i64` as big-endian fixed-width integers
+//! - `#[length_prefixed]`: a `Vec` prefixed with its length, a VarInt unless given as
+//!   `#[length_prefixed(byte)]`, `(short)`, `(int)` or `(varint)`
+//! - `#[rest]`: a `Vec` without prefix that takes everything up to the end of the packet
+//! - `#[option]`: an `Option` prefixed with a bool telling whether the value is present
+//!
+//! `#[length_prefixed]`, `#[rest]` and `#[option]` can be combined with `#[varint]` or `#[fixed]`,
+//! which then applies to the elements, e.g. `#[length_prefixed(short)] #[fixed] Vec<i32>`.
+//!
+//! `#[derive(Serializable, Decode)]` implements the traits of the same name for structs nested
+//! in packets, with the same field attributes but without id and packet metadata.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, LitInt};
+
+#[proc_macro_derive(Packet, attributes(packet, varint, fixed, length_prefixed, rest, option))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_packet(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(Serializable, attributes(varint, fixed, length_prefixed, rest, option))]
+pub fn derive_serializable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_serializable(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(Decode, attributes(varint, fixed, length_prefixed, rest, option))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_decode(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// The wire format of a field or of the elements of a container
+enum Encoding {
+    Default,
+    VarInt,
+    Fixed,
+    LengthPrefixed(Ident, Box<Encoding>),
+    Rest(Box<Encoding>),
+    Option(Box<Encoding>),
+}
+
+struct PacketAttr {
+    id: LitInt,
+    state: Ident,
+    direction: Ident,
+}
+
+/// Code appending every field to `buffer` and the expression reading them back from `cursor`
+struct FieldCode {
+    encode: TokenStream2,
+    decode: TokenStream2,
+}
+
+fn field_code(input: &DeriveInput) -> syn::Result<FieldCode> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(input, "only structs can be derived")),
+    };
+    let fields = match fields {
+        Fields::Named(fields) => fields.named.iter().collect::<Vec<_>>(),
+        Fields::Unit => return Ok(FieldCode{encode: quote!(), decode: quote!(#name)}),
+        Fields::Unnamed(_) => return Err(syn::Error::new_spanned(input, "only structs with named fields can be derived")),
+    };
+
+    let mut encodes = Vec::new();
+    let mut decodes = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let encoding = field_encoding(&field.attrs)?;
+        encodes.push(encode(&encoding, &quote!(&self.#ident), 0));
+        let decode = decode(&encoding);
+        decodes.push(quote!(#ident: #decode));
+    }
+    Ok(FieldCode{
+        encode: quote!(#(#encodes)*),
+        decode: quote!(#name { #(#decodes),* }),
+    })
+}
+
+fn expand_packet(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let attr = packet_attr(&input)?;
+    let FieldCode{encode, decode} = field_code(&input)?;
+
+    let id = &attr.id;
+    let state = &attr.state;
+    let direction = &attr.direction;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics crate::utils::packets::PacketInfo for #name #ty_generics #where_clause {
+            const ID: i32 = #id;
+            const STATE: crate::utils::other::State = crate::utils::other::State::#state;
+            const DIRECTION: crate::utils::other::Direction = crate::utils::other::Direction::#direction;
+            const NAME: &'static str = stringify!(#name);
+        }
+
+        impl #impl_generics crate::utils::packets::Packet for #name #ty_generics #where_clause {
+            fn serialize_payload(&self) -> Vec<u8> {
+                #[allow(unused_imports)]
+                use crate::utils::packets::serialization::{Serializable, VarIntEncoded, FixedEncoded};
+                let mut buffer = Serializable::serialize(&<Self as crate::utils::packets::PacketInfo>::ID);
+                #encode
+                buffer
+            }
+
+            fn new(data: Vec<u8>) -> Result<Self, crate::utils::error::ProtocolError> {
+                #[allow(unused_imports)]
+                use crate::utils::packets::serialization::{self, Decode, VarIntEncoded, FixedEncoded};
+                #[allow(unused_mut)]
+                let mut cursor = std::io::Cursor::new(data.as_slice());
+                #[allow(unused_variables)]
+                let cursor = &mut cursor;
+                let packet = #decode;
+                // Anything left over means the fields don't match what the client sent
+                let unread = data.len() - cursor.position() as usize;
+                if unread > 0 {
+                    return Err(crate::utils::error::ProtocolError::InvalidField(
+                        format!("{} unread bytes after {}", unread, stringify!(#name)),
+                    ));
+                }
+                Ok(packet)
+            }
+        }
+    })
+}
+
+fn expand_serializable(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let FieldCode{encode, ..} = field_code(&input)?;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics crate::utils::packets::serialization::Serializable for #name #ty_generics #where_clause {
+            fn serialize(&self) -> Vec<u8> {
+                #[allow(unused_imports)]
+                use crate::utils::packets::serialization::{Serializable, VarIntEncoded, FixedEncoded};
+                #[allow(unused_mut)]
+                let mut buffer = Vec::new();
+                #encode
+                buffer
+            }
+        }
+    })
+}
+
+fn expand_decode(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let FieldCode{decode, ..} = field_code(&input)?;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics crate::utils::packets::serialization::Decode for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn decode(cursor: &mut std::io::Cursor<&[u8]>) -> Result<Self, crate::utils::error::ProtocolError> {
+                #[allow(unused_imports)]
+                use crate::utils::packets::serialization::{self, Decode, VarIntEncoded, FixedEncoded};
+                Ok(#decode)
+            }
+        }
+    })
+}
+
+fn packet_attr(input: &DeriveInput) -> syn::Result<PacketAttr> {
+    let Some(attr) = input.attrs.iter().find(|attr| attr.path().is_ident("packet")) else {
+        return Err(syn::Error::new_spanned(input, "missing #[packet(id = .., state = .., direction = ..)]"));
+    };
+    let (mut id, mut state, mut direction) = (None, None, None);
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("id") {
+            id = Some(meta.value()?.parse::<LitInt>()?);
+        } else if meta.path.is_ident("state") {
+            let ident: Ident = meta.value()?.parse()?;
+            match ident.to_string().as_str() {
+                "Handshake" | "Status" | "Login" | "Configuration" | "Play" => {}
+                _ => return Err(meta.error("expected Handshake, Status, Login, Configuration or Play")),
+            }
+            // State's variants are upper case
+            state = Some(Ident::new(&ident.to_string().to_uppercase(), ident.span()));
+        } else if meta.path.is_ident("direction") {
+            let ident: Ident = meta.value()?.parse()?;
+            if ident != "Clientbound" && ident != "Serverbound" {
+                return Err(meta.error("expected Clientbound or Serverbound"));
+            }
+            direction = Some(ident);
+        } else {
+            return Err(meta.error("unknown packet attribute"));
+        }
+        Ok(())
+    })?;
+    match (id, state, direction) {
+        (Some(id), Some(state), Some(direction)) => Ok(PacketAttr{id, state, direction}),
+        _ => Err(syn::Error::new_spanned(attr, "#[packet] needs id, state and direction")),
+    }
+}
+
+fn field_encoding(attrs: &[Attribute]) -> syn::Result<Encoding> {
+    let mut scalar = None;
+    let mut container = None;
+    let mut option = false;
+    for attr in attrs {
+        let path = attr.path();
+        if path.is_ident("varint") || path.is_ident("fixed") {
+            if scalar.is_some() {
+                return Err(syn::Error::new_spanned(attr, "only one of #[varint] and #[fixed] is allowed"));
+            }
+            attr.meta.require_path_only()?;
+            scalar = Some(if path.is_ident("varint") { Encoding::VarInt } else { Encoding::Fixed });
+        } else if path.is_ident("length_prefixed") || path.is_ident("rest") {
+            if container.is_some() {
+                return Err(syn::Error::new_spanned(attr, "only one of #[length_prefixed] and #[rest] is allowed"));
+            }
+            container = Some(if path.is_ident("rest") {
+                attr.meta.require_path_only()?;
+                None
+            } else {
+                Some(length_prefix(attr)?)
+            });
+        } else if path.is_ident("option") {
+            attr.meta.require_path_only()?;
+            option = true;
+        }
+    }
+
+    let mut encoding = scalar.unwrap_or(Encoding::Default);
+    encoding = match container {
+        Some(Some(prefix)) => Encoding::LengthPrefixed(prefix, Box::new(encoding)),
+        Some(None) => Encoding::Rest(Box::new(encoding)),
+        None => encoding,
+    };
+    if option {
+        encoding = Encoding::Option(Box::new(encoding));
+    }
+    Ok(encoding)
+}
+
+/// The `LengthPrefix` variant for `#[length_prefixed]` or `#[length_prefixed(kind)]`
+fn length_prefix(attr: &Attribute) -> syn::Result<Ident> {
+    if attr.meta.require_path_only().is_ok() {
+        return Ok(Ident::new("VarInt", Span::call_site()));
+    }
+    let kind: Ident = attr.parse_args()?;
+    let variant = match kind.to_string().as_str() {
+        "varint" => "VarInt",
+        "byte" => "Byte",
+        "short" => "Short",
+        "int" => "Int",
+        _ => return Err(syn::Error::new_spanned(kind, "expected varint, byte, short or int")),
+    };
+    Ok(Ident::new(variant, kind.span()))
+}
+
+/// Code appending `value`, a reference, to `buffer`
+fn encode(encoding: &Encoding, value: &TokenStream2, depth: usize) -> TokenStream2 {
+    // Nested containers each need their own element variable
+    let element = format_ident!("element{}", depth);
+    match encoding {
+        Encoding::Default => quote!(buffer.extend(Serializable::serialize(#value));),
+        Encoding::VarInt => quote!(buffer.extend(VarIntEncoded::serialize_varint(#value));),
+        Encoding::Fixed => quote!(buffer.extend(FixedEncoded::serialize_fixed(#value));),
+        Encoding::LengthPrefixed(prefix, inner) => {
+            let inner = encode(inner, &quote!(#element), depth + 1);
+            quote! {
+                crate::utils::packets::serialization::write_length(&mut buffer, (#value).len(), crate::utils::packets::serialization::LengthPrefix::#prefix);
+                for #element in (#value).iter() {
+                    #inner
+                }
+            }
+        }
+        Encoding::Rest(inner) => {
+            let inner = encode(inner, &quote!(#element), depth + 1);
+            quote! {
+                for #element in (#value).iter() {
+                    #inner
+                }
+            }
+        }
+        Encoding::Option(inner) => {
+            let inner = encode(inner, &quote!(#element), depth + 1);
+            quote! {
+                match #value {
+                    Some(#element) => {
+                        buffer.push(0x01);
+                        #inner
+                    }
+                    None => buffer.push(0x00),
+                }
+            }
+        }
+    }
+}
+
+/// An expression reading a value from `cursor`, returning early on errors
+fn decode(encoding: &Encoding) -> TokenStream2 {
+    match encoding {
+        Encoding::Default => quote!(Decode::decode(cursor)?),
+        Encoding::VarInt => quote!(VarIntEncoded::decode_varint(cursor)?),
+        Encoding::Fixed => quote!(FixedEncoded::decode_fixed(cursor)?),
+        Encoding::LengthPrefixed(prefix, inner) => {
+            let inner = decode(inner);
+            quote!({
+                let len = serialization::read_length(cursor, serialization::LengthPrefix::#prefix)?;
+                // Every element takes at least a byte, so a bogus length can't make us allocate more than the packet
+                let mut elements = Vec::with_capacity(len.min(serialization::remaining(cursor)));
+                for _ in 0..len {
+                    elements.push(#inner);
+                }
+                elements
+            })
+        }
+        Encoding::Rest(inner) => {
+            let inner = decode(inner);
+            quote!({
+                let mut elements = Vec::new();
+                while serialization::remaining(cursor) > 0 {
+                    elements.push(#inner);
+                }
+                elements
+            })
+        }
+        Encoding::Option(inner) => {
+            let inner = decode(inner);
+            quote!(if <bool as Decode>::decode(cursor)? { Some(#inner) } else { None })
+        }
+    }
+}
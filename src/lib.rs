@@ -0,0 +1,8 @@
+//! A Minecraft server speaking the 1.8 protocol, with newer clients translated at the connection.
+//! [`ServerBuilder`] starts one inside any tokio runtime.
+
+pub mod server;
+pub mod utils;
+
+pub use server::{ServerBuilder, ServerHandle};
+pub use utils::properties::ServerProperties;
@@ -0,0 +1,1116 @@
+//! The game server itself, see [`ServerBuilder`] to start one
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn, Instrument};
+
+use crate::utils::anvil::{self, Anvil, AnvilError, PlayerData, PlayerFiles};
+use crate::utils::connection::{self, ignore, ConnectionId, DisconnectReason, Outbox, ServerMessage, Settings, SERVER_INBOX_CAPACITY};
+use crate::utils::encryption::ServerKey;
+use crate::utils::error::ProtocolError;
+use crate::utils::keep_alive::{KeepAlive, KeepAliveAction, KeepAliveSettings};
+use crate::utils::other::{self, State};
+use crate::utils::packets::clientbound::{
+    ClientboundBlockChangePacket, ClientboundChunkDataPacket, ClientboundJoinGamePacket, ClientboundKeepAlivePacket,
+    ClientboundMapChunkBulkPacket, ClientboundMultiBlockChangePacket, ClientboundPlayDisconnectPacket,
+    ClientboundPlayerListItemPacket, ClientboundPlayerPositionAndLookPacket, ClientboundPluginMessagePacket,
+};
+use crate::utils::packets::serialization::{self, Serializable};
+use crate::utils::packets::serverbound::{
+    ServerboundKeepAlivePacket, ServerboundChatMessagePacket, ServerboundUseEntityPacket, ServerboundPlayerPacket,
+    ServerboundPlayerPositionPacket, ServerboundPlayerLookPacket, ServerboundPlayerPositionAndLookPacket,
+    ServerboundPlayerDiggingPacket, ServerboundPlayerBlockPlacementPacket, ServerboundHeldItemChangePacket,
+    ServerboundAnimationPacket, ServerboundEntityActionPacket, ServerboundSteerVehiclePacket,
+    ServerboundCloseWindowPacket, ServerboundClickWindowPacket, ServerboundConfirmTransactionPacket,
+    ServerboundCreativeInventoryActionPacket, ServerboundEnchantItemPacket, ServerboundUpdateSignPacket,
+    ServerboundPlayerAbilitiesPacket, ServerboundTabCompletePacket, ServerboundClientSettingsPacket,
+    ServerboundClientStatusPacket, ServerboundPluginMessagePacket, ServerboundSpectatePacket,
+    ServerboundResourcePackStatusPacket,
+};
+use crate::utils::packets::{decode, Packet, PacketInfo};
+use crate::utils::properties::ServerProperties;
+use crate::utils::registry::Registries;
+use crate::utils::session::{GameProfile, HttpSessionService, SessionService};
+use crate::utils::packets::serialization::Position;
+use crate::utils::smpmap::Block;
+use crate::utils::status::{self, OnlinePlayers, PlayerSample, StatusHook, StatusRequest, StatusResponse};
+use crate::utils::types::{BlockChangeRecord, PlayerListAction, PlayerListLatency};
+use crate::utils::world::{ChunkChanges, ChunkPos, World};
+
+/// Players who don't answer a keep alive within the timeout are kicked.
+pub const DEFAULT_KEEP_ALIVE: KeepAliveSettings = KeepAliveSettings{
+    interval: Duration::from_secs(15),
+    timeout: Duration::from_secs(30),
+};
+/// How often the server checks whether keep alives are due.
+const TICK: Duration = Duration::from_secs(1);
+/// Vanilla's message when the server stops
+pub const DEFAULT_SHUTDOWN_MESSAGE: &str = "Server closed";
+/// How long a stopping server waits for connections to write their Disconnect and close
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+/// Columns around the spawn chunk sent to joining players
+const SPAWN_RADIUS: i32 = 2;
+/// Vanilla never puts more columns into a single Map Chunk Bulk
+const MAX_BULK_COLUMNS: usize = 10;
+/// From this many changed blocks on vanilla resends the changed sections instead of a Multi Block Change
+const MAX_MULTI_BLOCK_CHANGES: usize = 64;
+
+/// Called when a player joined the game
+pub type JoinHook = Arc<dyn Fn(&GameProfile) + Send + Sync>;
+/// Called once for every player that joined, after it left the game
+pub type DisconnectHook = Arc<dyn Fn(&GameProfile, &DisconnectReason) + Send + Sync>;
+
+/// Configures a server before it is started:
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// use open_mc_rust::{ServerBuilder, ServerProperties};
+///
+/// let server = ServerBuilder::new(ServerProperties::default())
+///     .address("127.0.0.1:0")
+///     .on_join(|profile| println!("Welcome {}", profile.name))
+///     .start()
+///     .await?;
+/// println!("{} players online", server.online_players().await.count);
+/// server.stop().await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServerBuilder {
+    properties: ServerProperties,
+    /// Overrides the address of the properties
+    address: Option<String>,
+    favicon: Option<String>,
+    registries: Registries,
+    keep_alive: KeepAliveSettings,
+    session_service: Option<Arc<dyn SessionService>>,
+    status_hook: Option<StatusHook>,
+    on_join: Option<JoinHook>,
+    on_disconnect: Option<DisconnectHook>,
+    shutdown_message: String,
+    shutdown_timeout: Duration,
+    world: World,
+    anvil: Option<Anvil>,
+}
+
+impl ServerBuilder {
+    pub fn new(properties: ServerProperties) -> ServerBuilder {
+        ServerBuilder{
+            registries: Registries::supported(properties.online_mode),
+            properties,
+            address: None,
+            favicon: None,
+            keep_alive: DEFAULT_KEEP_ALIVE,
+            session_service: None,
+            status_hook: None,
+            on_join: None,
+            on_disconnect: None,
+            shutdown_message: DEFAULT_SHUTDOWN_MESSAGE.to_owned(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            world: World::default(),
+            anvil: None,
+        }
+    }
+
+    /// Port 0 picks a free port, see [`ServerHandle::local_addr`]
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+
+    /// A data URL, see [`status::favicon_from_png`]
+    pub fn favicon(mut self, favicon: String) -> Self {
+        self.favicon = Some(favicon);
+        self
+    }
+
+    /// The protocol versions clients can join with, all supported ones by default
+    pub fn registries(mut self, registries: Registries) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    pub fn keep_alive(mut self, keep_alive: KeepAliveSettings) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Only used in online mode, Mojang's session server by default
+    pub fn session_service(mut self, session_service: Arc<dyn SessionService>) -> Self {
+        self.session_service = Some(session_service);
+        self
+    }
+
+    pub fn status_hook(mut self, hook: impl Fn(&StatusRequest, &mut StatusResponse) + Send + Sync + 'static) -> Self {
+        self.status_hook = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_join(mut self, hook: impl Fn(&GameProfile) + Send + Sync + 'static) -> Self {
+        self.on_join = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_disconnect(mut self, hook: impl Fn(&GameProfile, &DisconnectReason) + Send + Sync + 'static) -> Self {
+        self.on_disconnect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Shown to everybody still connected when the server stops
+    pub fn shutdown_message(mut self, message: impl Into<String>) -> Self {
+        self.shutdown_message = message.into();
+        self
+    }
+
+    /// Connections still open this long after the server was told to stop are closed without a goodbye
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// The world players join, empty by default
+    pub fn world(mut self, world: World) -> Self {
+        self.world = world;
+        self
+    }
+
+    /// Where the world is loaded from and saved to, nothing is saved without one
+    pub fn anvil(mut self, anvil: Anvil) -> Self {
+        self.anvil = Some(anvil);
+        self
+    }
+
+    /// Binds the address and starts accepting players in the background
+    pub async fn start(self) -> io::Result<ServerHandle> {
+        let address = self.address.unwrap_or_else(|| self.properties.address());
+        let listener = TcpListener::bind(&address).await?;
+        let session_service = self.session_service;
+        let session_service = self.properties.online_mode
+            .then(|| session_service.unwrap_or_else(|| Arc::new(HttpSessionService::default())));
+        let (shutdown, shutdown_receiver) = watch::channel(None);
+        let settings = Arc::new(Settings{
+            compression_threshold: self.properties.network_compression_threshold,
+            key: ServerKey::generate(),
+            session_service,
+            registries: self.registries,
+            // Shown in the server list, nobody is turned away yet
+            max_players: self.properties.max_players,
+            motd: other::chat_text_value(&self.properties.motd),
+            favicon: self.favicon,
+            status_hook: self.status_hook,
+            shutdown: shutdown_receiver,
+        });
+
+        let (world, anvil) = match self.anvil {
+            Some(mut anvil) => {
+                let mut world = self.world;
+                // Columns already in the world are kept
+                let missing: Vec<_> = spawn_area().filter(|&(x, z)| world.column(x, z).is_none()).collect();
+                tokio::task::spawn_blocking(move || {
+                    let loaded = anvil.load_into(&mut world, missing);
+                    info!("Loaded {loaded} columns around the spawn");
+                    (world, Some(anvil))
+                }).await?
+            }
+            None => (self.world, None),
+        };
+
+        let (sender, inbox) = mpsc::channel::<ServerMessage>(SERVER_INBOX_CAPACITY);
+        let server = Server{
+            connections: HashMap::new(),
+            players: HashMap::new(),
+            properties: self.properties,
+            keep_alive: self.keep_alive,
+            on_join: self.on_join,
+            on_disconnect: self.on_disconnect,
+            shutdown,
+            shutdown_message: self.shutdown_message,
+            shutdown_timeout: self.shutdown_timeout,
+            player_files: anvil.as_ref().map(Anvil::players),
+            world,
+            anvil,
+        };
+        let game = tokio::spawn(server.run(inbox).instrument(tracing::info_span!("server")));
+        let (stop_accepting, stop) = oneshot::channel();
+        let handle = ServerHandle{
+            address: listener.local_addr()?,
+            server: sender.clone(),
+            stop_accepting,
+            acceptor: tokio::spawn(accept(listener, settings, sender, stop)),
+            game,
+        };
+        Ok(handle)
+    }
+}
+
+/// Accepts connections until told to stop
+async fn accept(listener: TcpListener, settings: Arc<Settings>, server: mpsc::Sender<ServerMessage>, mut stop: oneshot::Receiver<()>) {
+    let mut next_id = 0;
+    loop {
+        let (socket, address) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                // E.g. out of file descriptors, the next connection may work again
+                Err(err) => {
+                    warn!("Error accepting connection: {}", err);
+                    continue;
+                }
+            },
+            _ = &mut stop => return,
+        };
+
+        // Every connection runs in its own task and only talks to the server through messages
+        tokio::spawn(connection::handle_connection(ConnectionId(next_id), socket, address, settings.clone(), server.clone()));
+        next_id += 1;
+    }
+}
+
+/// A running server
+pub struct ServerHandle {
+    address: SocketAddr,
+    server: mpsc::Sender<ServerMessage>,
+    stop_accepting: oneshot::Sender<()>,
+    acceptor: JoinHandle<()>,
+    game: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// The address the server listens on, with the actual port if port 0 was bound
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The same players a status request shows
+    pub async fn online_players(&self) -> OnlinePlayers {
+        let (reply, players) = oneshot::channel();
+        if self.server.send(ServerMessage::Status{reply}).await.is_err() {
+            return OnlinePlayers::default();
+        }
+        players.await.unwrap_or_default()
+    }
+
+    /// Changes a block like [`World::set_block`], players see it with the next tick.
+    /// Returns whether the block changed, `false` once the server stopped.
+    pub async fn set_block(&self, x: i32, y: i32, z: i32, block: Block) -> bool {
+        let (reply, changed) = oneshot::channel();
+        if self.server.send(ServerMessage::SetBlock{x, y, z, block, reply}).await.is_err() {
+            return false;
+        }
+        changed.await.unwrap_or(false)
+    }
+
+    /// Stops accepting connections and disconnects everybody with the shutdown message.
+    /// Returns once every connection is closed and the game is saved.
+    pub async fn stop(self) {
+        let _ = self.stop_accepting.send(());
+        let _ = self.acceptor.await;
+        let _ = self.server.send(ServerMessage::Stop).await;
+        let _ = self.game.await;
+    }
+}
+
+struct Player {
+    profile: GameProfile,
+    outbox: Outbox,
+    keep_alive: KeepAlive,
+    /// Saved when the player leaves
+    data: PlayerData,
+    /// The columns the client was sent, it only gets the block changes in these
+    columns: HashSet<ChunkPos>,
+}
+
+/// Everything known about a connection that hasn't necessarily joined yet
+struct ConnectionInfo {
+    address: SocketAddr,
+    opened: Instant,
+}
+
+/// The shared game state. It lives in its own task and is only reached through [`ServerMessage`]s.
+struct Server {
+    /// Every open connection, from its Open to its Close message
+    connections: HashMap<ConnectionId, ConnectionInfo>,
+    players: HashMap<ConnectionId, Player>,
+    properties: ServerProperties,
+    keep_alive: KeepAliveSettings,
+    on_join: Option<JoinHook>,
+    on_disconnect: Option<DisconnectHook>,
+    /// Tells connections that aren't playing yet to disconnect
+    shutdown: watch::Sender<Option<String>>,
+    shutdown_message: String,
+    shutdown_timeout: Duration,
+    world: World,
+    anvil: Option<Anvil>,
+    player_files: Option<PlayerFiles>,
+}
+
+impl Server {
+
+    async fn run(mut self, mut inbox: mpsc::Receiver<ServerMessage>) {
+        let mut ticks = tokio::time::interval(TICK);
+        // Set once the server is stopping
+        let mut deadline = None;
+        loop {
+            let message = tokio::select! {
+                message = inbox.recv() => message,
+                _ = ticks.tick() => {
+                    self.tick_keep_alives().await;
+                    self.send_block_changes().await;
+                    continue;
+                }
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() => {
+                    warn!("Giving up on {} connections that didn't close in time", self.connections.len());
+                    break;
+                }
+            };
+            let Some(message) = message else {
+                break;
+            };
+            match message {
+                ServerMessage::Open{id, address} => {
+                    debug!(connection = %id, %address, "Connection opened");
+                    self.connections.insert(id, ConnectionInfo{address, opened: Instant::now()});
+                }
+                ServerMessage::Join{outbox, ..} if deadline.is_some() => {
+                    // Too late to join, dropping the outbox closes the connection
+                    let _ = outbox.send(Box::new(ClientboundPlayDisconnectPacket{
+                        reason: other::chat_text(&self.shutdown_message),
+                    })).await;
+                }
+                ServerMessage::Join{id, profile, outbox} => self.handle_join(id, profile, outbox).await,
+                ServerMessage::Packet{id, data} => {
+                    let span = tracing::info_span!("player", connection = %id);
+                    if let Err(error) = self.handle_raw_packet(data, id).instrument(span).await {
+                        self.handle_error(id, error).await;
+                    }
+                }
+                ServerMessage::Error{id, error} => self.handle_error(id, error).await,
+                ServerMessage::Status{reply} => {
+                    let _ = reply.send(self.online_players());
+                }
+                ServerMessage::SetBlock{x, y, z, block, reply} => {
+                    let _ = reply.send(self.world.set_block(x, y, z, block));
+                }
+                ServerMessage::Close{id, reason} => self.handle_close(id, reason).await,
+                ServerMessage::Stop => {
+                    self.shut_down().await;
+                    deadline = Some(tokio::time::Instant::now() + self.shutdown_timeout);
+                }
+            }
+            if deadline.is_some() && self.connections.is_empty() {
+                break;
+            }
+        }
+        self.save().await;
+        info!("Server stopped");
+    }
+
+    /// Disconnects everybody, the server stops once all connections are closed
+    async fn shut_down(&mut self) {
+        info!("Stopping the server");
+        let message = self.shutdown_message.clone();
+        let _ = self.shutdown.send(Some(message.clone()));
+        let ids: Vec<ConnectionId> = self.players.keys().copied().collect();
+        for id in ids {
+            self.kick(id, &message).await;
+        }
+    }
+
+    /// Writes everything that has to survive a restart, players are saved as they leave
+    async fn save(self) {
+        let (Some(mut anvil), mut world) = (self.anvil, self.world) else {
+            return;
+        };
+        match blocking(move || anvil.save_dirty(&mut world)).await {
+            Ok(saved) => info!("Saved {saved} columns"),
+            Err(err) => warn!("Can't save the world: {err}"),
+        }
+    }
+
+    /// Forgets everything about a connection, players that were still online leave the game
+    async fn handle_close(&mut self, id: ConnectionId, reason: DisconnectReason) {
+        if let Some(info) = self.connections.remove(&id) {
+            debug!(connection = %id, address = %info.address, duration = ?info.opened.elapsed(), %reason, "Connection closed");
+        }
+        // Kicked players are already gone
+        if let Some(player) = self.players.remove(&id) {
+            self.handle_disconnect(player, reason).await;
+        }
+    }
+
+    /// Called exactly once for every player that joined, after it was removed from the game
+    async fn handle_disconnect(&mut self, player: Player, reason: DisconnectReason) {
+        match &reason {
+            DisconnectReason::Kicked(message) => info!("{} was kicked: {}", player.profile.name, message),
+            _ => info!("{} left the game", player.profile.name),
+        }
+        if let Some(hook) = &self.on_disconnect {
+            hook(&player.profile, &reason);
+        }
+        if let Some(files) = self.player_files.clone() {
+            let (id, data) = (player.profile.id, player.data.clone());
+            if let Err(err) = blocking(move || files.save(id, &data)).await {
+                warn!("Can't save {}: {err}", player.profile.name);
+            }
+        }
+        let action = PlayerListAction::RemovePlayer(vec![player.profile.id]);
+        self.broadcast(|| ClientboundPlayerListItemPacket{action: action.clone()}).await;
+    }
+
+    async fn handle_raw_packet(&mut self, data: Vec<u8>, id: ConnectionId) -> Result<(), ProtocolError> {
+        let (pid, data) = serialization::split_packet_id(&data)?;
+        match pid {
+            ServerboundKeepAlivePacket::ID => self.handle_keep_alive(id, decode::<ServerboundKeepAlivePacket>(data)?).await,
+            ServerboundChatMessagePacket::ID => ignore::<ServerboundChatMessagePacket>(data),
+            ServerboundUseEntityPacket::ID => ignore::<ServerboundUseEntityPacket>(data),
+            ServerboundPlayerPacket::ID => {
+                let packet = decode::<ServerboundPlayerPacket>(data)?;
+                self.move_player(id, |data| data.on_ground = packet.on_ground)
+            }
+            ServerboundPlayerPositionPacket::ID => {
+                let packet = decode::<ServerboundPlayerPositionPacket>(data)?;
+                self.move_player(id, |data| {
+                    data.pos = [packet.x, packet.feet_y, packet.z];
+                    data.on_ground = packet.on_ground;
+                })
+            }
+            ServerboundPlayerLookPacket::ID => {
+                let packet = decode::<ServerboundPlayerLookPacket>(data)?;
+                self.move_player(id, |data| {
+                    data.rotation = [packet.yaw, packet.pitch];
+                    data.on_ground = packet.on_ground;
+                })
+            }
+            ServerboundPlayerPositionAndLookPacket::ID => {
+                let packet = decode::<ServerboundPlayerPositionAndLookPacket>(data)?;
+                self.move_player(id, |data| {
+                    data.pos = [packet.x, packet.feet_y, packet.z];
+                    data.rotation = [packet.yaw, packet.pitch];
+                    data.on_ground = packet.on_ground;
+                })
+            }
+            ServerboundPlayerDiggingPacket::ID => ignore::<ServerboundPlayerDiggingPacket>(data),
+            ServerboundPlayerBlockPlacementPacket::ID => ignore::<ServerboundPlayerBlockPlacementPacket>(data),
+            ServerboundHeldItemChangePacket::ID => ignore::<ServerboundHeldItemChangePacket>(data),
+            ServerboundAnimationPacket::ID => ignore::<ServerboundAnimationPacket>(data),
+            ServerboundEntityActionPacket::ID => ignore::<ServerboundEntityActionPacket>(data),
+            ServerboundSteerVehiclePacket::ID => ignore::<ServerboundSteerVehiclePacket>(data),
+            ServerboundCloseWindowPacket::ID => ignore::<ServerboundCloseWindowPacket>(data),
+            ServerboundClickWindowPacket::ID => ignore::<ServerboundClickWindowPacket>(data),
+            ServerboundConfirmTransactionPacket::ID => ignore::<ServerboundConfirmTransactionPacket>(data),
+            ServerboundCreativeInventoryActionPacket::ID => ignore::<ServerboundCreativeInventoryActionPacket>(data),
+            ServerboundEnchantItemPacket::ID => ignore::<ServerboundEnchantItemPacket>(data),
+            ServerboundUpdateSignPacket::ID => ignore::<ServerboundUpdateSignPacket>(data),
+            ServerboundPlayerAbilitiesPacket::ID => ignore::<ServerboundPlayerAbilitiesPacket>(data),
+            ServerboundTabCompletePacket::ID => ignore::<ServerboundTabCompletePacket>(data),
+            ServerboundClientSettingsPacket::ID => ignore::<ServerboundClientSettingsPacket>(data),
+            ServerboundClientStatusPacket::ID => ignore::<ServerboundClientStatusPacket>(data),
+            ServerboundPluginMessagePacket::ID => ignore::<ServerboundPluginMessagePacket>(data),
+            ServerboundSpectatePacket::ID => ignore::<ServerboundSpectatePacket>(data),
+            ServerboundResourcePackStatusPacket::ID => ignore::<ServerboundResourcePackStatusPacket>(data),
+            pid => Err(ProtocolError::UnknownPacket{state: State::PLAY, id: pid}),
+        }
+    }
+
+    /// The client moves on its own, the server only remembers where it went
+    fn move_player(&mut self, id: ConnectionId, update: impl FnOnce(&mut PlayerData)) -> Result<(), ProtocolError> {
+        if let Some(player) = self.players.get_mut(&id) {
+            update(&mut player.data);
+        }
+        Ok(())
+    }
+
+    fn online_players(&self) -> OnlinePlayers {
+        OnlinePlayers{
+            count: self.players.len(),
+            sample: self.players.values()
+                .take(status::MAX_SAMPLE_PLAYERS)
+                .map(|player| PlayerSample{name: player.profile.name.clone(), id: player.profile.id})
+                .collect(),
+        }
+    }
+
+    /// Sends the keep alives that are due and kicks whoever didn't answer the last one
+    async fn tick_keep_alives(&mut self) {
+        let now = Instant::now();
+        let actions: Vec<(ConnectionId, KeepAliveAction)> = self.players.iter_mut()
+            .map(|(id, player)| (*id, player.keep_alive.poll(now)))
+            .collect();
+        for (id, action) in actions {
+            match action {
+                KeepAliveAction::Wait => {}
+                KeepAliveAction::Send(keep_alive) => self.send_packet(id, ClientboundKeepAlivePacket{id: keep_alive}).await,
+                KeepAliveAction::TimedOut => self.kick(id, "Timed out").await,
+            }
+        }
+    }
+
+    /// Tells the players that have a column about its blocks that changed since the last tick
+    async fn send_block_changes(&mut self) {
+        for changes in self.world.take_changes() {
+            let chunk = (changes.chunk_x, changes.chunk_z);
+            match changes.changes.as_slice() {
+                [change] => {
+                    let (location, block_id) = (Position{x: change.x, y: change.y, z: change.z}, change.block.state() as i32);
+                    self.send_to_column_viewers(chunk, || ClientboundBlockChangePacket{location, block_id}).await;
+                }
+                changed if changed.len() < MAX_MULTI_BLOCK_CHANGES => {
+                    self.send_to_column_viewers(chunk, || multi_block_change(&changes)).await;
+                }
+                // The whole column, 1.18+ clients can't take only some of its sections
+                _ => {
+                    let Some(column) = self.world.column(changes.chunk_x, changes.chunk_z) else {
+                        continue;
+                    };
+                    self.send_to_column_viewers(chunk, || ClientboundChunkDataPacket::from_column(changes.chunk_x, changes.chunk_z, column.clone(), true)).await;
+                }
+            }
+        }
+    }
+
+    async fn send_to_column_viewers<P: Packet + Send + 'static>(&self, chunk: ChunkPos, packet: impl Fn() -> P) {
+        let ids: Vec<ConnectionId> = self.players.iter()
+            .filter(|(_, player)| player.columns.contains(&chunk))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in ids {
+            self.send_packet(id, packet()).await;
+        }
+    }
+
+    async fn handle_keep_alive(&mut self, id: ConnectionId, packet: ServerboundKeepAlivePacket) -> Result<(), ProtocolError> {
+        let Some(player) = self.players.get_mut(&id) else {
+            return Ok(());
+        };
+        player.keep_alive.receive(packet.id, Instant::now())?;
+        // Everybody sees the new ping in the tab list
+        let action = PlayerListAction::UpdateLatency(vec![PlayerListLatency{
+            uuid: player.profile.id,
+            ping: player.keep_alive.latency().as_millis() as i32,
+        }]);
+        self.broadcast(|| ClientboundPlayerListItemPacket{action: action.clone()}).await;
+        Ok(())
+    }
+
+    async fn broadcast<P: Packet + Send + 'static>(&self, packet: impl Fn() -> P) {
+        let ids: Vec<ConnectionId> = self.players.keys().copied().collect();
+        for id in ids {
+            self.send_packet(id, packet()).await;
+        }
+    }
+
+    /// Only the offending player is kicked, everybody else keeps playing
+    async fn handle_error(&mut self, id: ConnectionId, error: ProtocolError) {
+        warn!(connection = %id, "Protocol error: {}", error);
+        self.kick(id, &error.to_string()).await;
+    }
+
+    async fn kick(&mut self, id: ConnectionId, reason: &str) {
+        self.send_packet(id, ClientboundPlayDisconnectPacket{
+            reason: other::chat_text(reason),
+        }).await;
+        // Dropping the outbox lets the connection write the queued packets and close
+        if let Some(player) = self.players.remove(&id) {
+            self.handle_disconnect(player, DisconnectReason::Kicked(reason.to_owned())).await;
+        }
+    }
+
+    async fn send_packet(&self, id: ConnectionId, packet: impl Packet + Send + 'static) {
+        if let Some(player) = self.players.get(&id) {
+            // Waits for room in the outbox instead of dropping the packet
+            let _ = player.outbox.send(Box::new(packet)).await;
+        }
+    }
+
+    async fn handle_join(&mut self, id: ConnectionId, profile: GameProfile, outbox: Outbox) {
+        info!(connection = %id, uuid = %profile.id, "{} joined the game", profile.name);
+        if let Some(hook) = &self.on_join {
+            hook(&profile);
+        }
+        let data = match self.load_player(&profile).await {
+            Some(data) => data,
+            None => self.spawn_point(),
+        };
+        let position = ClientboundPlayerPositionAndLookPacket{
+            x: data.pos[0],
+            y: data.pos[1],
+            z: data.pos[2],
+            yaw: data.rotation[0],
+            pitch: data.rotation[1],
+            flags: 0,
+        };
+        self.players.insert(id, Player{
+            profile,
+            outbox,
+            keep_alive: KeepAlive::new(self.keep_alive, Instant::now()),
+            data,
+            columns: spawn_area().collect(),
+        });
+        self.send_packet(id, ClientboundJoinGamePacket{
+            id: id.0 as i32,
+            gamemode: self.properties.gamemode_id(),
+            dimension: 0,
+            difficulty: self.properties.difficulty.id(),
+            // Only used to size the tab list
+            max_players: self.properties.max_players.min(u8::MAX as u32) as u8,
+            level_type: self.properties.level_type.clone(),
+            reduced_debug_info: self.properties.reduced_debug_info,
+        }).await;
+        self.send_packet(id, ClientboundPluginMessagePacket{
+            channel: "MC|Brand".to_owned(),
+            data: connection::SERVER_BRAND.to_owned().serialize(),
+        }).await;
+        // Columns that aren't loaded are sent empty so the client doesn't wait for them
+        let columns: Vec<_> = spawn_area()
+            .map(|(x, z)| (x, z, self.world.column(x, z).cloned().unwrap_or_default()))
+            .collect();
+        for bulk in columns.chunks(MAX_BULK_COLUMNS) {
+            self.send_packet(id, ClientboundMapChunkBulkPacket::from_columns(true, bulk.to_vec())).await;
+        }
+        self.send_packet(id, position).await;
+    }
+
+    /// Where the player was when it last left, `None` for new players
+    async fn load_player(&self, profile: &GameProfile) -> Option<PlayerData> {
+        let files = self.player_files.clone()?;
+        let id = profile.id;
+        blocking(move || files.load(id)).await.unwrap_or_else(|err| {
+            warn!("Can't load {}, starting over at the spawn: {err}", profile.name);
+            None
+        })
+    }
+
+    /// New players start on top of the block at 0, 0
+    fn spawn_point(&self) -> PlayerData {
+        let y = self.world.column(0, 0).map_or(0, |column| anvil::height_map(column)[0]);
+        PlayerData{pos: [0.5, y as f64, 0.5], rotation: [0.0, 0.0], on_ground: true}
+    }
+}
+
+/// Runs file I/O on a thread where blocking is fine
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> Result<T, AnvilError> + Send + 'static) -> Result<T, AnvilError> {
+    tokio::task::spawn_blocking(work).await.unwrap_or_else(|err| Err(AnvilError::Io(io::Error::other(err))))
+}
+
+/// The columns sent to joining players
+fn spawn_area() -> impl Iterator<Item = ChunkPos> {
+    (-SPAWN_RADIUS..=SPAWN_RADIUS).flat_map(|x| (-SPAWN_RADIUS..=SPAWN_RADIUS).map(move |z| (x, z)))
+}
+
+fn multi_block_change(changes: &ChunkChanges) -> ClientboundMultiBlockChangePacket {
+    ClientboundMultiBlockChangePacket{
+        chunk_x: changes.chunk_x,
+        chunk_z: changes.chunk_z,
+        records: changes.changes.iter()
+            .map(|change| BlockChangeRecord{
+                horizontal_position: (change.x.rem_euclid(16) << 4 | change.z.rem_euclid(16)) as u8,
+                y: change.y as u8,
+                block_id: change.block.state() as i32,
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use rsa::pkcs8::DecodePublicKey;
+    use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+    use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+    use uuid::Uuid;
+    use crate::utils::encryption::{new_ciphers, EncryptedReader, EncryptedWriter};
+    use crate::utils::packets::clientbound::{ClientboundDisconnectPacket, ClientboundEncryptionRequestPacket, ClientboundLoginSuccesPacket};
+    use crate::utils::packets::serialization::VarIntEncoded;
+    use crate::utils::packets::serverbound::{ServerboundEncryptionResponsePacket, ServerboundLoginStartPacket};
+    use crate::utils::session::{self, MockSessionService};
+
+    fn frame(packet: Vec<u8>) -> Vec<u8> {
+        [(packet.len() as i32).serialize_varint(), packet].concat()
+    }
+
+    /// A 1.8 client without compression, encrypted once [`Client::encrypt`] was called
+    struct Client {
+        reader: EncryptedReader<OwnedReadHalf>,
+        writer: EncryptedWriter<OwnedWriteHalf>,
+    }
+
+    impl Client {
+        /// Connects and sends the handshake and Login Start
+        async fn login(address: SocketAddr, name: &str) -> Client {
+            let (reader, writer) = TcpStream::connect(address).await.unwrap().into_split();
+            let mut client = Client{reader: EncryptedReader::new(reader), writer: EncryptedWriter::new(writer)};
+            let handshake = [
+                0.serialize_varint(),
+                47.serialize_varint(),
+                "localhost".to_owned().serialize(),
+                address.port().serialize(),
+                2.serialize_varint(),
+            ].concat();
+            client.send(handshake).await;
+            client.send(ServerboundLoginStartPacket{name: name.to_owned()}.serialize_payload()).await;
+            client
+        }
+
+        async fn send(&mut self, packet: Vec<u8>) {
+            self.writer.write_all(&frame(packet)).await.unwrap();
+        }
+
+        /// Packet id + fields of the next packet
+        async fn read(&mut self) -> Vec<u8> {
+            let mut length = 0;
+            for shift in (0..35).step_by(7) {
+                let byte = self.reader.read_u8().await.unwrap();
+                length |= ((byte & 0x7F) as usize) << shift;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            let mut packet = vec![0; length];
+            self.reader.read_exact(&mut packet).await.unwrap();
+            packet
+        }
+
+        /// Skips everything else, e.g. chunks and keep alives
+        async fn read_packet<P: Packet + PacketInfo>(&mut self) -> P {
+            loop {
+                let packet = self.read().await;
+                if packet[0] as i32 == P::ID {
+                    return P::new(packet[1..].to_vec()).unwrap();
+                }
+            }
+        }
+
+        /// Answers the Encryption Request like a vanilla client and returns the server hash it joins with
+        async fn encrypt(&mut self) -> String {
+            let request: ClientboundEncryptionRequestPacket = self.read_packet().await;
+            let public_key = RsaPublicKey::from_public_key_der(&request.public_key).unwrap();
+            let shared_secret = rand::random::<[u8; 16]>();
+            let mut rng = rand::thread_rng();
+            self.send(ServerboundEncryptionResponsePacket{
+                shared_secret: public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &shared_secret).unwrap(),
+                verify_token: public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &request.verify_token).unwrap(),
+            }.serialize_payload()).await;
+            let (encryptor, decryptor) = new_ciphers(&shared_secret).unwrap();
+            self.reader.enable(decryptor);
+            self.writer.enable(encryptor);
+            session::server_hash("", &shared_secret, &request.public_key)
+        }
+    }
+
+    fn online_properties() -> ServerProperties {
+        ServerProperties{online_mode: true, ..offline_properties()}
+    }
+
+    /// The test client doesn't do compression
+    fn offline_properties() -> ServerProperties {
+        ServerProperties{network_compression_threshold: -1, ..ServerProperties::default()}
+    }
+
+    /// Asks the server until `done` holds, players leave asynchronously
+    async fn wait_for_players(server: &ServerHandle, done: impl Fn(&OnlinePlayers) -> bool) -> OnlinePlayers {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let players = server.online_players().await;
+                if done(&players) {
+                    return players;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap()
+    }
+
+    #[test]
+    fn block_changes_are_relative_to_their_column() {
+        let mut world = World::new();
+        world.set_block(-1, 70, -16, Block::new(35, 14));
+        world.set_block(-16, 0, -1, Block::new(1, 0));
+        let changes = world.take_changes();
+        let packet = multi_block_change(&changes[0]);
+        assert_eq!((packet.chunk_x, packet.chunk_z), (-1, -1));
+        assert_eq!(packet.records, [
+            BlockChangeRecord{horizontal_position: 0xF0, y: 70, block_id: 35 << 4 | 14},
+            BlockChangeRecord{horizontal_position: 0x0F, y: 0, block_id: 1 << 4},
+        ]);
+    }
+
+    #[tokio::test]
+    async fn players_see_blocks_set_through_the_handle() {
+        let server = ServerBuilder::new(offline_properties())
+            .address("127.0.0.1:0")
+            .start()
+            .await
+            .unwrap();
+        let mut client = Client::login(server.local_addr(), "Tester").await;
+        let _: ClientboundPlayerPositionAndLookPacket = client.read_packet().await;
+
+        assert!(server.set_block(-1, 64, 2, Block::new(1, 0)).await);
+        assert!(!server.set_block(-1, 64, 2, Block::new(1, 0)).await);
+        let change: ClientboundBlockChangePacket = client.read_packet().await;
+        assert_eq!((change.location, change.block_id), (Position{x: -1, y: 64, z: 2}, 1 << 4));
+
+        // A tick just sent the Block Change, so both land in the next one
+        assert!(server.set_block(16, 10, 0, Block::new(35, 14)).await);
+        assert!(server.set_block(17, 11, 1, Block::new(20, 0)).await);
+        let changes: ClientboundMultiBlockChangePacket = client.read_packet().await;
+        assert_eq!((changes.chunk_x, changes.chunk_z), (1, 0));
+        assert_eq!(changes.records, [
+            BlockChangeRecord{horizontal_position: 0x00, y: 10, block_id: 35 << 4 | 14},
+            BlockChangeRecord{horizontal_position: 0x11, y: 11, block_id: 20 << 4},
+        ]);
+
+        // The client was never sent this column. Had it been told, that would come a tick before the next change
+        assert!(server.set_block(1000, 64, 0, Block::new(1, 0)).await);
+        tokio::time::sleep(TICK * 3 / 2).await;
+        assert!(server.set_block(0, 64, 0, Block::new(1, 0)).await);
+        let change: ClientboundBlockChangePacket = client.read_packet().await;
+        assert_eq!(change.location, Position{x: 0, y: 64, z: 0});
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn embedded_servers_answer_status_requests_until_stopped() {
+        let hook_called = Arc::new(AtomicBool::new(false));
+        let called = hook_called.clone();
+        let properties = ServerProperties{motd: "Embedded".to_owned(), ..ServerProperties::default()};
+        let server = ServerBuilder::new(properties)
+            .address("127.0.0.1:0")
+            .status_hook(move |_, response| {
+                called.store(true, Ordering::SeqCst);
+                response.players.max = 5;
+            })
+            .start()
+            .await
+            .unwrap();
+        assert_eq!(server.online_players().await, OnlinePlayers::default());
+
+        let address = server.local_addr();
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let handshake = [
+            0.serialize_varint(),
+            47.serialize_varint(),
+            "localhost".to_owned().serialize(),
+            address.port().serialize(),
+            1.serialize_varint(),
+        ].concat();
+        stream.write_all(&[frame(handshake), frame(0.serialize_varint())].concat()).await.unwrap();
+        let mut response = Vec::new();
+        let mut buffer = [0; 1024];
+        // The JSON ends the packet, read until it is complete
+        let json = |response: &[u8]| response.iter()
+            .position(|&byte| byte == b'{')
+            .and_then(|start| serde_json::from_slice::<serde_json::Value>(&response[start..]).ok());
+        while json(&response).is_none() {
+            let read = stream.read(&mut buffer).await.unwrap();
+            assert_ne!(read, 0, "connection closed early");
+            response.extend_from_slice(&buffer[..read]);
+        }
+        let status = json(&response).unwrap();
+        assert_eq!(status["description"]["text"], "Embedded");
+        assert_eq!(status["players"]["max"], 5);
+        assert!(hook_called.load(Ordering::SeqCst));
+
+        server.stop().await;
+        assert!(TcpStream::connect(address).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stopping_disconnects_logins_with_the_shutdown_message() {
+        let server = ServerBuilder::new(ServerProperties::default())
+            .address("127.0.0.1:0")
+            .shutdown_message("Back soon")
+            .start()
+            .await
+            .unwrap();
+        let address = server.local_addr();
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let handshake = [
+            0.serialize_varint(),
+            47.serialize_varint(),
+            "localhost".to_owned().serialize(),
+            address.port().serialize(),
+            2.serialize_varint(),
+        ].concat();
+        stream.write_all(&frame(handshake)).await.unwrap();
+        // Gives the connection time to read the handshake, there is no answer to wait for
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        server.stop().await;
+        let mut disconnect = Vec::new();
+        stream.read_to_end(&mut disconnect).await.unwrap();
+        let reason = other::chat_text("Back soon").serialize();
+        assert_eq!(disconnect, frame([0.serialize_varint(), reason].concat()));
+    }
+
+    #[tokio::test]
+    async fn online_mode_logs_in_players_the_session_server_knows() {
+        let sessions = Arc::new(MockSessionService::new());
+        let joined = Arc::new(std::sync::Mutex::new(None));
+        let on_join = joined.clone();
+        let server = ServerBuilder::new(online_properties())
+            .address("127.0.0.1:0")
+            .session_service(sessions.clone())
+            .on_join(move |profile| *on_join.lock().unwrap() = Some(profile.clone()))
+            .start()
+            .await
+            .unwrap();
+        let profile = GameProfile{id: Uuid::new_v4(), name: "Tester".to_owned(), properties: Vec::new()};
+
+        let mut client = Client::login(server.local_addr(), "Tester").await;
+        let hash = client.encrypt().await;
+        sessions.join(profile.clone(), &hash);
+        let success: ClientboundLoginSuccesPacket = client.read_packet().await;
+        assert_eq!(success.uuid, profile.id.hyphenated().to_string());
+        let _: ClientboundJoinGamePacket = client.read_packet().await;
+        assert_eq!(joined.lock().unwrap().as_ref(), Some(&profile));
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn online_mode_rejects_players_the_session_server_doesnt_know() {
+        let server = ServerBuilder::new(online_properties())
+            .address("127.0.0.1:0")
+            .session_service(Arc::new(MockSessionService::new()))
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = Client::login(server.local_addr(), "Tester").await;
+        client.encrypt().await;
+        let disconnect: ClientboundDisconnectPacket = client.read_packet().await;
+        assert_eq!(disconnect.reason, other::chat_text("Failed to verify username!"));
+        assert_eq!(server.online_players().await, OnlinePlayers::default());
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn players_join_and_leave_through_the_server() {
+        let server = ServerBuilder::new(offline_properties())
+            .address("127.0.0.1:0")
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = Client::login(server.local_addr(), "Tester").await;
+        let success: ClientboundLoginSuccesPacket = client.read_packet().await;
+        let join: ClientboundJoinGamePacket = client.read_packet().await;
+        let players = wait_for_players(&server, |players| players.count == 1).await;
+        assert_eq!(players.sample, [PlayerSample{name: "Tester".to_owned(), id: Uuid::parse_str(&success.uuid).unwrap()}]);
+
+        // A second player gets its own entity id
+        let mut other = Client::login(server.local_addr(), "Other").await;
+        let other_join: ClientboundJoinGamePacket = other.read_packet().await;
+        assert_ne!(other_join.id, join.id);
+        wait_for_players(&server, |players| players.count == 2).await;
+
+        drop(client);
+        let players = wait_for_players(&server, |players| players.count == 1).await;
+        assert_eq!(players.sample[0].name, "Other");
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn players_come_back_where_they_left() {
+        let dir = std::env::temp_dir().join(format!("open_mc_rust-{}-server-players", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let server = ServerBuilder::new(offline_properties())
+            .address("127.0.0.1:0")
+            .anvil(Anvil::open(&dir))
+            .start()
+            .await
+            .unwrap();
+
+        let mut client = Client::login(server.local_addr(), "Tester").await;
+        let success: ClientboundLoginSuccesPacket = client.read_packet().await;
+        let spawn: ClientboundPlayerPositionAndLookPacket = client.read_packet().await;
+        assert_eq!((spawn.x, spawn.y, spawn.z), (0.5, 0.0, 0.5));
+        client.send(ServerboundPlayerPositionAndLookPacket{
+            x: 10.5,
+            feet_y: 70.0,
+            z: -4.5,
+            yaw: 45.0,
+            pitch: 10.0,
+            on_ground: false,
+        }.serialize_payload()).await;
+        drop(client);
+        wait_for_players(&server, |players| players.count == 0).await;
+        let saved = Anvil::open(&dir).players().load(Uuid::parse_str(&success.uuid).unwrap()).unwrap().unwrap();
+        assert_eq!(saved, PlayerData{pos: [10.5, 70.0, -4.5], rotation: [45.0, 10.0], on_ground: false});
+
+        let mut client = Client::login(server.local_addr(), "Tester").await;
+        let position: ClientboundPlayerPositionAndLookPacket = client.read_packet().await;
+        assert_eq!((position.x, position.y, position.z, position.yaw, position.pitch), (10.5, 70.0, -4.5, 45.0, 10.0));
+
+        server.stop().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn on_disconnect_fires_once_for_every_player_that_left() {
+        let left = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let on_disconnect = left.clone();
+        let server = ServerBuilder::new(offline_properties())
+            .address("127.0.0.1:0")
+            .on_disconnect(move |profile, reason| on_disconnect.lock().unwrap().push((profile.name.clone(), reason.to_string())))
+            .start()
+            .await
+            .unwrap();
+
+        let mut quitter = Client::login(server.local_addr(), "Quitter").await;
+        let _: ClientboundJoinGamePacket = quitter.read_packet().await;
+        let mut stayer = Client::login(server.local_addr(), "Stayer").await;
+        let _: ClientboundJoinGamePacket = stayer.read_packet().await;
+        drop(quitter);
+        wait_for_players(&server, |players| players.count == 1).await;
+        // Whether reading or writing noticed first decides the reason
+        let names = |left: &[(String, String)]| left.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&left.lock().unwrap()), ["Quitter"]);
+
+        // Players still online when the server stops are kicked
+        server.stop().await;
+        let left = left.lock().unwrap();
+        assert_eq!(left.len(), 2);
+        assert_eq!(left[1], ("Stayer".to_owned(), DisconnectReason::Kicked(DEFAULT_SHUTDOWN_MESSAGE.to_owned()).to_string()));
+    }
+
+    /// Never answers, like a session server that hangs
+    struct HangingSessionService;
+
+    #[async_trait::async_trait]
+    impl SessionService for HangingSessionService {
+        async fn has_joined(&self, _: &str, _: &str) -> Result<Option<GameProfile>, session::SessionError> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn connections_are_closed_once_the_shutdown_timeout_passed() {
+        let server = ServerBuilder::new(online_properties())
+            .address("127.0.0.1:0")
+            .session_service(Arc::new(HangingSessionService))
+            .shutdown_timeout(Duration::from_millis(100))
+            .start()
+            .await
+            .unwrap();
+        let mut client = Client::login(server.local_addr(), "Tester").await;
+        client.encrypt().await;
+        // Gives the connection time to get stuck asking the session server
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        tokio::time::timeout(Duration::from_secs(5), server.stop()).await.unwrap();
+        let mut rest = Vec::new();
+        let closed = tokio::time::timeout(Duration::from_secs(5), client.reader.read_to_end(&mut rest)).await;
+        assert_eq!(closed.unwrap().unwrap(), 0);
+    }
+}
@@ -0,0 +1,768 @@
+//! Anvil worlds as saved by vanilla 1.2 to 1.12: `region/r.<x>.<z>.mca` files of 32x32 chunks,
+//! every chunk a compressed NBT compound holding its sections, biomes, entities and tile entities.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::nbt::{self, Compound, NbtError, Tag};
+use super::smpmap::{ChunkColumn, ChunkSection, NibbleArray, BIOME_AREA, COLUMN_HEIGHT, SECTIONS_PER_COLUMN, SECTION_VOLUME, SECTION_WIDTH};
+use super::world::{ChunkPos, World};
+
+const SECTOR_BYTES: usize = 4096;
+/// Chunks along each side of a region
+pub const REGION_WIDTH: i32 = 32;
+/// The location and timestamp tables
+const HEADER_SECTORS: usize = 2;
+/// A chunk's sector count is a single byte
+const MAX_CHUNK_SECTORS: usize = 255;
+
+const GZIP: u8 = 1;
+const ZLIB: u8 = 2;
+/// Only written by 1.15.1+, read anyway
+const UNCOMPRESSED: u8 = 3;
+
+#[derive(Debug)]
+pub enum AnvilError {
+    Io(io::Error),
+    Nbt(NbtError),
+    /// The region file or a chunk in it is broken
+    Corrupt(String),
+}
+
+impl fmt::Display for AnvilError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnvilError::Io(err) => write!(f, "{err}"),
+            AnvilError::Nbt(err) => write!(f, "{err}"),
+            AnvilError::Corrupt(msg) => write!(f, "Corrupt region: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for AnvilError {}
+
+impl From<io::Error> for AnvilError {
+    fn from(err: io::Error) -> Self {
+        AnvilError::Io(err)
+    }
+}
+
+impl From<NbtError> for AnvilError {
+    fn from(err: NbtError) -> Self {
+        AnvilError::Nbt(err)
+    }
+}
+
+/// A single `.mca` file. Chunks are addressed relative to the region, from 0 to 31.
+#[derive(Debug)]
+pub struct RegionFile {
+    file: File,
+    /// Offset in sectors in the upper 24 bits, length in sectors in the lower 8, 0 for missing chunks
+    locations: Vec<u32>,
+    /// Seconds since the epoch of the last save
+    timestamps: Vec<u32>,
+    /// Which sectors are taken, the header included
+    used: Vec<bool>,
+}
+
+fn chunk_index(x: usize, z: usize) -> usize {
+    assert!(x < REGION_WIDTH as usize && z < REGION_WIDTH as usize, "chunk {x}, {z} is outside of the region");
+    x + z * REGION_WIDTH as usize
+}
+
+impl RegionFile {
+    /// Creates the file with an empty header if it doesn't exist
+    pub fn open(path: &Path) -> Result<RegionFile, AnvilError> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let mut length = file.metadata()?.len() as usize;
+        if length < HEADER_SECTORS * SECTOR_BYTES {
+            file.set_len((HEADER_SECTORS * SECTOR_BYTES) as u64)?;
+            length = HEADER_SECTORS * SECTOR_BYTES;
+        }
+        let mut header = vec![0; HEADER_SECTORS * SECTOR_BYTES];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+        let table = |start: usize| -> Vec<u32> {
+            header[start..start + SECTOR_BYTES].chunks_exact(4)
+                .map(|entry| u32::from_be_bytes(entry.try_into().unwrap()))
+                .collect()
+        };
+        let locations = table(0);
+        let timestamps = table(SECTOR_BYTES);
+
+        let mut used = vec![false; length.div_ceil(SECTOR_BYTES)];
+        used[..HEADER_SECTORS].fill(true);
+        for location in &locations {
+            let (offset, count) = ((location >> 8) as usize, (location & 0xFF) as usize);
+            // Chunks pointing outside the file are treated as missing when read
+            if let Some(sectors) = used.get_mut(offset..offset + count) {
+                sectors.fill(true);
+            }
+        }
+        Ok(RegionFile{file, locations, timestamps, used})
+    }
+
+    pub fn has_chunk(&self, x: usize, z: usize) -> bool {
+        self.locations[chunk_index(x, z)] != 0
+    }
+
+    /// When the chunk was last saved, in seconds since the epoch
+    pub fn timestamp(&self, x: usize, z: usize) -> u32 {
+        self.timestamps[chunk_index(x, z)]
+    }
+
+    /// The chunk's root compound, `None` if it was never saved
+    pub fn read_chunk(&mut self, x: usize, z: usize) -> Result<Option<Compound>, AnvilError> {
+        let location = self.locations[chunk_index(x, z)];
+        if location == 0 {
+            return Ok(None);
+        }
+        let (offset, count) = ((location >> 8) as usize, (location & 0xFF) as usize);
+        if offset < HEADER_SECTORS || offset + count > self.used.len() {
+            return Err(AnvilError::Corrupt(format!("chunk {x}, {z} points outside of the file")));
+        }
+        if count == 0 {
+            return Err(AnvilError::Corrupt(format!("chunk {x}, {z} takes no sectors")));
+        }
+        let mut sectors = vec![0; count * SECTOR_BYTES];
+        self.file.seek(SeekFrom::Start((offset * SECTOR_BYTES) as u64))?;
+        self.file.read_exact(&mut sectors)?;
+
+        let length = u32::from_be_bytes(sectors[..4].try_into().unwrap()) as usize;
+        if length == 0 || length + 4 > sectors.len() {
+            return Err(AnvilError::Corrupt(format!("chunk {x}, {z} has a length of {length} in {count} sectors")));
+        }
+        let compressed = &sectors[5..4 + length];
+        let mut data = Vec::new();
+        match sectors[4] {
+            GZIP => { GzDecoder::new(compressed).read_to_end(&mut data)?; }
+            ZLIB => { ZlibDecoder::new(compressed).read_to_end(&mut data)?; }
+            UNCOMPRESSED => data.extend_from_slice(compressed),
+            compression => return Err(AnvilError::Corrupt(format!("chunk {x}, {z} has unknown compression {compression}"))),
+        }
+        match nbt::read_named(&data)? {
+            (_, Tag::Compound(root)) => Ok(Some(root)),
+            _ => Err(AnvilError::Corrupt(format!("chunk {x}, {z} isn't a compound"))),
+        }
+    }
+
+    /// Saves the chunk zlib compressed, in place if it still fits into its sectors
+    pub fn write_chunk(&mut self, x: usize, z: usize, root: &Compound) -> Result<(), AnvilError> {
+        let index = chunk_index(x, z);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&nbt::write_named("", &Tag::Compound(root.clone())))?;
+        let compressed = encoder.finish()?;
+
+        let mut data = Vec::with_capacity(compressed.len() + 5);
+        data.extend((compressed.len() as u32 + 1).to_be_bytes());
+        data.push(ZLIB);
+        data.extend(compressed);
+        let count = data.len().div_ceil(SECTOR_BYTES);
+        if count > MAX_CHUNK_SECTORS {
+            return Err(AnvilError::Corrupt(format!("chunk {x}, {z} takes {count} sectors, at most {MAX_CHUNK_SECTORS} fit")));
+        }
+        data.resize(count * SECTOR_BYTES, 0);
+
+        let offset = self.allocate(self.locations[index], count);
+        self.file.seek(SeekFrom::Start((offset * SECTOR_BYTES) as u64))?;
+        self.file.write_all(&data)?;
+
+        self.locations[index] = (offset as u32) << 8 | count as u32;
+        self.timestamps[index] = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() as u32);
+        self.file.seek(SeekFrom::Start((index * 4) as u64))?;
+        self.file.write_all(&self.locations[index].to_be_bytes())?;
+        self.file.seek(SeekFrom::Start((SECTOR_BYTES + index * 4) as u64))?;
+        self.file.write_all(&self.timestamps[index].to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Frees the sectors of `location` and finds `count` free sectors in a row, growing the file if there are none.
+    /// A location pointing into the header or past the end is broken and nothing is freed for it.
+    fn allocate(&mut self, location: u32, count: usize) -> usize {
+        let (offset, current) = ((location >> 8) as usize, (location & 0xFF) as usize);
+        if offset >= HEADER_SECTORS && offset + current <= self.used.len() {
+            self.used[offset..offset + current].fill(false);
+            if count <= current {
+                self.used[offset..offset + count].fill(true);
+                return offset;
+            }
+        }
+        let mut run = 0;
+        let mut start = self.used.len();
+        for (sector, &used) in self.used.iter().enumerate() {
+            run = if used { 0 } else { run + 1 };
+            if run == count {
+                start = sector + 1 - count;
+                break;
+            }
+        }
+        if start + count > self.used.len() {
+            self.used.resize(start + count, false);
+        }
+        self.used[start..start + count].fill(true);
+        start
+    }
+}
+
+/// A chunk as saved in a region file. Only the blocks are kept in the [`ChunkColumn`],
+/// entities and tile entities stay NBT since the server doesn't simulate them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnvilChunk {
+    pub x: i32,
+    pub z: i32,
+    pub column: ChunkColumn,
+    /// One above the highest block that isn't air for each x/z, indexed by `z * 16 + x`
+    pub height_map: Vec<i32>,
+    pub entities: Vec<Compound>,
+    pub tile_entities: Vec<Compound>,
+    /// Every other tag of the Level compound, like TileTicks, so saving doesn't lose it
+    pub other: Compound,
+}
+
+impl AnvilChunk {
+    pub fn new(x: i32, z: i32, column: ChunkColumn) -> AnvilChunk {
+        AnvilChunk{
+            x,
+            z,
+            height_map: height_map(&column),
+            column,
+            entities: Vec::new(),
+            tile_entities: Vec::new(),
+            other: Compound::from([
+                ("LightPopulated".to_owned(), Tag::Byte(1)),
+                ("TerrainPopulated".to_owned(), Tag::Byte(1)),
+                ("InhabitedTime".to_owned(), Tag::Long(0)),
+                ("LastUpdate".to_owned(), Tag::Long(0)),
+            ]),
+        }
+    }
+
+    /// Replaces the blocks, the height map is recalculated for them
+    pub fn set_column(&mut self, column: ChunkColumn) {
+        self.height_map = height_map(&column);
+        self.column = column;
+    }
+
+    pub fn from_nbt(root: &Compound) -> Result<AnvilChunk, AnvilError> {
+        let mut level = match root.get("Level") {
+            Some(Tag::Compound(level)) => level.clone(),
+            _ => return Err(AnvilError::Corrupt("chunk without a Level compound".to_owned())),
+        };
+        let mut coordinate = |name: &str| match level.remove(name).and_then(|tag| tag.as_i64()) {
+            Some(value) => Ok(value as i32),
+            None => Err(AnvilError::Corrupt(format!("chunk without {name}"))),
+        };
+        let (x, z) = (coordinate("xPos")?, coordinate("zPos")?);
+
+        let mut column = ChunkColumn::default();
+        if let Some(Tag::List(sections)) = level.remove("Sections") {
+            for section in &sections {
+                let section = section.as_compound()
+                    .ok_or_else(|| AnvilError::Corrupt(format!("section of chunk {x}, {z} isn't a compound")))?;
+                read_section(&mut column, section).map_err(|msg| AnvilError::Corrupt(format!("chunk {x}, {z}: {msg}")))?;
+            }
+        }
+        if let Some(Tag::ByteArray(biomes)) = level.remove("Biomes") {
+            if biomes.len() == BIOME_AREA {
+                column.biomes = biomes;
+            }
+        }
+        let height_map = match level.remove("HeightMap") {
+            Some(Tag::IntArray(height_map)) if height_map.len() == BIOME_AREA => height_map,
+            _ => height_map(&column),
+        };
+        let mut compounds = |name: &str| -> Vec<Compound> {
+            match level.remove(name) {
+                Some(Tag::List(tags)) => tags.into_iter()
+                    .filter_map(|tag| match tag {
+                        Tag::Compound(compound) => Some(compound),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+        let entities = compounds("Entities");
+        let tile_entities = compounds("TileEntities");
+        Ok(AnvilChunk{x, z, column, height_map, entities, tile_entities, other: level})
+    }
+
+    pub fn to_nbt(&self) -> Compound {
+        let mut level = self.other.clone();
+        level.insert("xPos".to_owned(), Tag::Int(self.x));
+        level.insert("zPos".to_owned(), Tag::Int(self.z));
+        level.insert("Sections".to_owned(), Tag::List(
+            self.column.sections.iter().enumerate()
+                .filter_map(|(y, section)| Some(Tag::Compound(write_section(y, section.as_deref()?))))
+                .collect(),
+        ));
+        level.insert("Biomes".to_owned(), Tag::ByteArray(self.column.biomes.clone()));
+        level.insert("HeightMap".to_owned(), Tag::IntArray(self.height_map.clone()));
+        let compounds = |compounds: &[Compound]| Tag::List(compounds.iter().cloned().map(Tag::Compound).collect());
+        level.insert("Entities".to_owned(), compounds(&self.entities));
+        level.insert("TileEntities".to_owned(), compounds(&self.tile_entities));
+        Compound::from([("Level".to_owned(), Tag::Compound(level))])
+    }
+}
+
+fn read_section(column: &mut ChunkColumn, section: &Compound) -> Result<(), String> {
+    let y = section.get("Y").and_then(Tag::as_i64).ok_or("section without Y")?;
+    let y = usize::try_from(y).ok().filter(|y| *y < SECTIONS_PER_COLUMN).ok_or(format!("section at y {y}"))?;
+    let array = |name: &str, len: usize| match section.get(name).and_then(Tag::as_bytes) {
+        Some(bytes) if bytes.len() == len => Ok(bytes),
+        Some(bytes) => Err(format!("{name} of section {y} has {} bytes", bytes.len())),
+        None => Err(format!("section {y} without {name}")),
+    };
+    let nibbles = |name: &str| array(name, SECTION_VOLUME / 2).map(|bytes| NibbleArray::from_bytes(bytes.to_vec()).unwrap());
+    let blocks = array("Blocks", SECTION_VOLUME)?;
+    let data = nibbles("Data")?;
+    let add = section.contains_key("Add").then(|| nibbles("Add")).transpose()?;
+    let block_light = nibbles("BlockLight")?;
+    // Missing in dimensions without a sky
+    let sky_light = nibbles("SkyLight").unwrap_or_else(|_| NibbleArray::filled(0));
+
+    let target = column.section_mut(y);
+    for (index, state) in target.states_mut().iter_mut().enumerate() {
+        let id = blocks[index] as u16 | add.as_ref().map_or(0, |add| (add.get(index) as u16) << 8);
+        *state = id << 4 | data.get(index) as u16;
+    }
+    target.block_light = block_light;
+    target.sky_light = sky_light;
+    Ok(())
+}
+
+fn write_section(y: usize, section: &ChunkSection) -> Compound {
+    let mut blocks = vec![0; SECTION_VOLUME];
+    let mut data = NibbleArray::filled(0);
+    let mut add = NibbleArray::filled(0);
+    for (index, state) in section.states().iter().enumerate() {
+        let id = state >> 4;
+        blocks[index] = id as u8;
+        add.set(index, (id >> 8) as u8);
+        data.set(index, (state & 0x0F) as u8);
+    }
+    let mut compound = Compound::from([
+        ("Y".to_owned(), Tag::Byte(y as i8)),
+        ("Blocks".to_owned(), Tag::ByteArray(blocks)),
+        ("Data".to_owned(), Tag::ByteArray(data.as_bytes().to_vec())),
+        ("BlockLight".to_owned(), Tag::ByteArray(section.block_light.as_bytes().to_vec())),
+        ("SkyLight".to_owned(), Tag::ByteArray(section.sky_light.as_bytes().to_vec())),
+    ]);
+    // Only blocks above id 255 need it
+    if add.as_bytes().iter().any(|&byte| byte != 0) {
+        compound.insert("Add".to_owned(), Tag::ByteArray(add.as_bytes().to_vec()));
+    }
+    compound
+}
+
+/// One above the highest block that isn't air for each x/z, 0 where there is none
+pub fn height_map(column: &ChunkColumn) -> Vec<i32> {
+    let mut height_map = vec![0; BIOME_AREA];
+    for z in 0..SECTION_WIDTH {
+        for x in 0..SECTION_WIDTH {
+            let top = (0..COLUMN_HEIGHT).rev().find(|&y| column.get_block(x, y, z).id != 0);
+            height_map[z * SECTION_WIDTH + x] = top.map_or(0, |y| y as i32 + 1);
+        }
+    }
+    height_map
+}
+
+/// The region files of a world directory, opened as they are needed
+#[derive(Debug)]
+pub struct Anvil {
+    region_dir: PathBuf,
+    regions: HashMap<(i32, i32), RegionFile>,
+    players: PlayerFiles,
+}
+
+impl Anvil {
+    /// `world_dir` is the directory with `level.dat`, the region files are in its `region` directory
+    pub fn open(world_dir: impl AsRef<Path>) -> Anvil {
+        Anvil{
+            region_dir: world_dir.as_ref().join("region"),
+            regions: HashMap::new(),
+            players: PlayerFiles{dir: world_dir.as_ref().join("playerdata")},
+        }
+    }
+
+    /// The player files of the world, they don't share any state with the region files
+    pub fn players(&self) -> PlayerFiles {
+        self.players.clone()
+    }
+
+    /// Region files are only created when `create` is set, so loading doesn't leave empty files behind
+    fn region(&mut self, chunk_x: i32, chunk_z: i32, create: bool) -> Result<Option<&mut RegionFile>, AnvilError> {
+        let key = (chunk_x.div_euclid(REGION_WIDTH), chunk_z.div_euclid(REGION_WIDTH));
+        if !self.regions.contains_key(&key) {
+            let path = self.region_dir.join(format!("r.{}.{}.mca", key.0, key.1));
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            fs::create_dir_all(&self.region_dir)?;
+            self.regions.insert(key, RegionFile::open(&path)?);
+        }
+        Ok(self.regions.get_mut(&key))
+    }
+
+    pub fn load_chunk(&mut self, chunk_x: i32, chunk_z: i32) -> Result<Option<AnvilChunk>, AnvilError> {
+        let Some(region) = self.region(chunk_x, chunk_z, false)? else {
+            return Ok(None);
+        };
+        let (x, z) = (chunk_x.rem_euclid(REGION_WIDTH) as usize, chunk_z.rem_euclid(REGION_WIDTH) as usize);
+        region.read_chunk(x, z)?.map(|root| AnvilChunk::from_nbt(&root)).transpose()
+    }
+
+    pub fn save_chunk(&mut self, chunk: &AnvilChunk) -> Result<(), AnvilError> {
+        let region = self.region(chunk.x, chunk.z, true)?.expect("region files are created");
+        let (x, z) = (chunk.x.rem_euclid(REGION_WIDTH) as usize, chunk.z.rem_euclid(REGION_WIDTH) as usize);
+        region.write_chunk(x, z, &chunk.to_nbt())
+    }
+
+    /// Loads the saved columns among `positions` into the world, returns how many there were.
+    /// Chunks that can't be read are logged and left out, like vanilla regenerates them.
+    pub fn load_into(&mut self, world: &mut World, positions: impl IntoIterator<Item = ChunkPos>) -> usize {
+        let mut loaded = 0;
+        for (x, z) in positions {
+            match self.load_chunk(x, z) {
+                Ok(Some(chunk)) => {
+                    world.insert_column(x, z, chunk.column);
+                    loaded += 1;
+                }
+                Ok(None) => {}
+                Err(err) => warn!("Can't load chunk {x}, {z}: {err}"),
+            }
+        }
+        loaded
+    }
+
+    /// Saves the columns changed since the last save. Entities and tile entities already saved
+    /// for a column are kept. Returns how many columns were saved.
+    pub fn save_dirty(&mut self, world: &mut World) -> Result<usize, AnvilError> {
+        let dirty = world.take_dirty();
+        for (saved, &(x, z)) in dirty.iter().enumerate() {
+            if let Err(err) = self.save_column(world, x, z) {
+                // Whatever wasn't saved is tried again next time
+                for &(x, z) in &dirty[saved..] {
+                    world.column_mut(x, z);
+                }
+                return Err(err);
+            }
+        }
+        Ok(dirty.len())
+    }
+
+    fn save_column(&mut self, world: &World, x: i32, z: i32) -> Result<(), AnvilError> {
+        let Some(column) = world.column(x, z) else {
+            return Ok(());
+        };
+        let chunk = match self.load_chunk(x, z) {
+            Ok(Some(mut chunk)) => {
+                chunk.set_column(column.clone());
+                chunk
+            }
+            // A broken chunk is overwritten rather than never saved again
+            Ok(None) | Err(AnvilError::Corrupt(_) | AnvilError::Nbt(_)) => AnvilChunk::new(x, z, column.clone()),
+            Err(err) => return Err(err),
+        };
+        self.save_chunk(&chunk)
+    }
+}
+
+/// The part of a vanilla player file the server keeps track of
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PlayerData {
+    /// Where the feet are
+    pub pos: [f64; 3],
+    /// Yaw and pitch in degrees
+    pub rotation: [f32; 2],
+    pub on_ground: bool,
+}
+
+/// The `playerdata/<uuid>.dat` files of a world, gzip compressed like vanilla's
+#[derive(Debug, Clone)]
+pub struct PlayerFiles {
+    dir: PathBuf,
+}
+
+impl PlayerFiles {
+    fn path(&self, id: Uuid) -> PathBuf {
+        self.dir.join(format!("{}.dat", id.hyphenated()))
+    }
+
+    /// `None` for players that never played in this world
+    pub fn load(&self, id: Uuid) -> Result<Option<PlayerData>, AnvilError> {
+        match fs::read(self.path(id)) {
+            Ok(data) => Ok(Some(nbt::from_bytes(&data)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Everything else vanilla saved for the player, like the inventory, is kept. The file is replaced
+    /// in one go so a crash while saving doesn't lose it.
+    pub fn save(&self, id: Uuid, data: &PlayerData) -> Result<(), AnvilError> {
+        let path = self.path(id);
+        let mut root = match fs::read(&path) {
+            // Like broken chunks, a broken file is overwritten
+            Ok(file) => match nbt::read_compressed(&file) {
+                Ok((_, Tag::Compound(root))) => root,
+                _ => Compound::new(),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Compound::new(),
+            // The file may be fine, it's only replaced once it could be read
+            Err(err) => return Err(err.into()),
+        };
+        if let Tag::Compound(fields) = nbt::to_tag(data)? {
+            root.extend(fields);
+        }
+        fs::create_dir_all(&self.dir)?;
+        let temporary = path.with_extension("dat.tmp");
+        nbt::write_file(&temporary, "", &Tag::Compound(root), nbt::Compression::Gzip)?;
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::smpmap::Block;
+
+    /// A fresh directory per test, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir().join(format!("open_mc_rust-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn column() -> ChunkColumn {
+        let mut column = ChunkColumn::default();
+        column.set_block(1, 2, 3, Block::new(35, 14));
+        column.set_block(15, 100, 15, Block::new(1000, 7));
+        column.set_block_light(1, 3, 3, 12);
+        column.set_biome(4, 5, 21);
+        column
+    }
+
+    #[test]
+    fn chunks_round_trip_through_region_files() {
+        let dir = TempDir::new("round-trip");
+        let mut anvil = Anvil::open(&dir.0);
+        assert!(anvil.load_chunk(-1, 40).unwrap().is_none());
+        assert!(!dir.0.join("region").exists());
+
+        let mut chunk = AnvilChunk::new(-1, 40, column());
+        chunk.entities.push(Compound::from([("id".to_owned(), Tag::String("Pig".to_owned()))]));
+        chunk.other.insert("TileTicks".to_owned(), Tag::List(Vec::new()));
+        anvil.save_chunk(&chunk).unwrap();
+        assert!(dir.0.join("region/r.-1.1.mca").exists());
+
+        let loaded = Anvil::open(&dir.0).load_chunk(-1, 40).unwrap().unwrap();
+        assert_eq!(loaded, chunk);
+        assert_eq!(loaded.column.get_block(15, 100, 15), Block::new(1000, 7));
+        assert_eq!(loaded.height_map[15 * 16 + 15], 101);
+        assert_eq!(loaded.height_map[3 * 16 + 1], 3);
+    }
+
+    #[test]
+    fn sections_use_the_vanilla_layout() {
+        let compound = AnvilChunk::new(0, 0, column()).to_nbt();
+        let level = compound["Level"].as_compound().unwrap();
+        let sections = level["Sections"].as_list().unwrap();
+        assert_eq!(sections.len(), 2);
+        let top = sections[1].as_compound().unwrap();
+        assert_eq!(top["Y"], Tag::Byte(6));
+        // Block 1000 at x 15, y 4, z 15 of the section: 0xE8 in Blocks, 3 in the upper nibble of Add
+        let index = 4 << 8 | 15 << 4 | 15;
+        assert_eq!(top["Blocks"].as_bytes().unwrap()[index], 0xE8);
+        assert_eq!(top["Add"].as_bytes().unwrap()[index / 2], 0x30);
+        assert_eq!(top["Data"].as_bytes().unwrap()[index / 2], 0x70);
+        assert!(!sections[0].as_compound().unwrap().contains_key("Add"));
+        assert_eq!(level["Biomes"].as_bytes().unwrap()[5 * 16 + 4], 21);
+    }
+
+    #[test]
+    fn reads_gzip_compressed_chunks() {
+        let dir = TempDir::new("gzip");
+        let path = dir.0.join("r.0.0.mca");
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&nbt::write_named("", &Tag::Compound(AnvilChunk::new(3, 2, column()).to_nbt()))).unwrap();
+        let compressed = gzip.finish().unwrap();
+        let mut file = vec![0; 2 * SECTOR_BYTES];
+        // Chunk 3, 2 in sector 2
+        let index = 3 + 2 * 32;
+        file[index * 4..index * 4 + 4].copy_from_slice(&(2 << 8 | 1u32).to_be_bytes());
+        file.extend((compressed.len() as u32 + 1).to_be_bytes());
+        file.push(GZIP);
+        file.extend(compressed);
+        file.resize(3 * SECTOR_BYTES, 0);
+        fs::write(&path, file).unwrap();
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert!(region.has_chunk(3, 2) && !region.has_chunk(2, 3));
+        let chunk = AnvilChunk::from_nbt(&region.read_chunk(3, 2).unwrap().unwrap()).unwrap();
+        assert_eq!(chunk.column, column());
+    }
+
+    #[test]
+    fn broken_locations_and_lengths_are_corrupt() {
+        let dir = TempDir::new("corrupt");
+        let path = dir.0.join("region/r.0.0.mca");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut file = vec![0; 3 * SECTOR_BYTES];
+        // Chunk 0, 0 in sector 2 but without sectors, chunk 1, 0 claims more bytes than its sector has
+        file[..4].copy_from_slice(&(2u32 << 8).to_be_bytes());
+        file[4..8].copy_from_slice(&(2 << 8 | 1u32).to_be_bytes());
+        file[2 * SECTOR_BYTES..2 * SECTOR_BYTES + 4].copy_from_slice(&(SECTOR_BYTES as u32).to_be_bytes());
+        file[2 * SECTOR_BYTES + 4] = ZLIB;
+        fs::write(&path, file).unwrap();
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert!(matches!(region.read_chunk(0, 0), Err(AnvilError::Corrupt(_))));
+        assert!(matches!(region.read_chunk(1, 0), Err(AnvilError::Corrupt(_))));
+
+        // The broken chunks are skipped, the good one still loads
+        let mut anvil = Anvil::open(&dir.0);
+        anvil.save_chunk(&AnvilChunk::new(2, 0, column())).unwrap();
+        let mut world = World::new();
+        assert_eq!(anvil.load_into(&mut world, [(0, 0), (1, 0), (2, 0)]), 1);
+        assert_eq!(world.column(2, 0), Some(&column()));
+    }
+
+    #[test]
+    fn saving_over_a_location_in_the_header_keeps_the_header() {
+        let dir = TempDir::new("header");
+        let path = dir.0.join("r.0.0.mca");
+        let mut file = vec![0; 2 * SECTOR_BYTES];
+        // Chunk 0, 0 claims the first sector, which is the location table
+        file[..4].copy_from_slice(&1u32.to_be_bytes());
+        fs::write(&path, file).unwrap();
+
+        let small = Compound::from([("a".to_owned(), Tag::Int(1))]);
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write_chunk(0, 0, &small).unwrap();
+        assert_eq!(region.locations[0], 2 << 8 | 1);
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read_chunk(0, 0).unwrap(), Some(small));
+    }
+
+    #[test]
+    fn sectors_are_reused_and_moved_when_chunks_grow() {
+        let dir = TempDir::new("sectors");
+        let path = dir.0.join("r.0.0.mca");
+        let mut region = RegionFile::open(&path).unwrap();
+        // Random bytes don't compress, so this takes 3 sectors
+        let noise: Vec<u8> = (0..10000).map(|_| rand::random()).collect();
+        let big = Compound::from([("noise".to_owned(), Tag::ByteArray(noise))]);
+        let small = Compound::from([("a".to_owned(), Tag::Int(1))]);
+
+        region.write_chunk(0, 0, &small).unwrap();
+        region.write_chunk(1, 0, &small).unwrap();
+        assert_eq!((region.locations[0], region.locations[1]), (2 << 8 | 1, 3 << 8 | 1));
+        // Doesn't fit into sector 2 anymore, so it moves to the end
+        region.write_chunk(0, 0, &big).unwrap();
+        assert_eq!(region.locations[0], 4 << 8 | 3);
+        // The freed sector 2 is reused
+        region.write_chunk(2, 0, &small).unwrap();
+        assert_eq!(region.locations[2], 2 << 8 | 1);
+        // Shrinking stays in place
+        region.write_chunk(0, 0, &small).unwrap();
+        assert_eq!(region.locations[0], 4 << 8 | 1);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 7 * SECTOR_BYTES as u64);
+
+        let mut reopened = RegionFile::open(&path).unwrap();
+        assert_eq!(reopened.read_chunk(0, 0).unwrap().unwrap(), small);
+        assert_eq!(reopened.read_chunk(2, 0).unwrap().unwrap(), small);
+        assert!(reopened.read_chunk(5, 5).unwrap().is_none());
+        reopened.write_chunk(3, 0, &small).unwrap();
+        assert_eq!(reopened.locations[3], 5 << 8 | 1);
+    }
+
+    #[test]
+    fn dirty_columns_are_saved_with_their_entities() {
+        let dir = TempDir::new("dirty");
+        let mut anvil = Anvil::open(&dir.0);
+        let mut chunk = AnvilChunk::new(0, 0, ChunkColumn::default());
+        chunk.tile_entities.push(Compound::from([("id".to_owned(), Tag::String("Chest".to_owned()))]));
+        anvil.save_chunk(&chunk).unwrap();
+
+        let mut world = World::new();
+        assert_eq!(anvil.load_into(&mut world, [(0, 0), (1, 0)]), 1);
+        world.set_block(0, 64, 0, Block::new(54, 2));
+        world.set_block(16, 64, 0, Block::new(1, 0));
+        assert_eq!(anvil.save_dirty(&mut world).unwrap(), 2);
+        assert_eq!(anvil.save_dirty(&mut world).unwrap(), 0);
+
+        let saved = anvil.load_chunk(0, 0).unwrap().unwrap();
+        assert_eq!(saved.column.get_block(0, 64, 0), Block::new(54, 2));
+        assert_eq!(saved.tile_entities, chunk.tile_entities);
+        assert_eq!(anvil.load_chunk(1, 0).unwrap().unwrap().column.get_block(0, 64, 0), Block::new(1, 0));
+    }
+
+    #[test]
+    fn player_files_keep_what_vanilla_saved() {
+        let dir = TempDir::new("players");
+        let players = Anvil::open(&dir.0).players();
+        let id = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        assert!(players.load(id).unwrap().is_none());
+
+        let path = dir.0.join("playerdata/01234567-89ab-cdef-0123-456789abcdef.dat");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let vanilla = Compound::from([
+            ("Inventory".to_owned(), Tag::List(Vec::new())),
+            ("OnGround".to_owned(), Tag::Byte(0)),
+        ]);
+        nbt::write_file(&path, "", &Tag::Compound(vanilla), nbt::Compression::Gzip).unwrap();
+
+        let data = PlayerData{pos: [0.5, 65.0, -3.25], rotation: [90.0, -10.0], on_ground: true};
+        players.save(id, &data).unwrap();
+        assert_eq!(players.load(id).unwrap(), Some(data));
+        let (_, root) = nbt::read_file(&path).unwrap();
+        let root = root.as_compound().unwrap();
+        assert_eq!(root["Inventory"], Tag::List(Vec::new()));
+        assert_eq!(root["OnGround"], Tag::Byte(1));
+        assert_eq!(root["Pos"], Tag::List(vec![Tag::Double(0.5), Tag::Double(65.0), Tag::Double(-3.25)]));
+    }
+
+    #[test]
+    fn player_files_are_only_replaced_when_broken() {
+        let dir = TempDir::new("broken-players");
+        let players = Anvil::open(&dir.0).players();
+        let (broken, unreadable) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let data = PlayerData{pos: [1.0, 2.0, 3.0], rotation: [0.0, 0.0], on_ground: false};
+
+        fs::create_dir_all(&players.dir).unwrap();
+        fs::write(players.path(broken), [0x1F, 0x8B, 0x00]).unwrap();
+        players.save(broken, &data).unwrap();
+        assert_eq!(players.load(broken).unwrap(), Some(data.clone()));
+
+        // Reading a directory fails with something else than NotFound
+        fs::create_dir_all(players.path(unreadable)).unwrap();
+        assert!(matches!(players.save(unreadable, &data), Err(AnvilError::Io(_))));
+        assert!(!players.path(unreadable).with_extension("dat.tmp").exists());
+    }
+}
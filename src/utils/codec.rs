@@ -0,0 +1,113 @@
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::compression;
+use super::error::ProtocolError;
+
+/// The frame length is a VarInt of at most 3 bytes, like in vanilla
+pub const MAX_FRAME_LENGTH_BYTES: usize = 3;
+pub const MAX_FRAME_LENGTH: usize = (1 << (7 * MAX_FRAME_LENGTH_BYTES)) - 1;
+pub const MAX_VARINT_BYTES: usize = 5;
+
+/// Reads a VarInt of at most `max_bytes` from the start of `data`.
+/// Returns the value and the number of bytes used, or `None` if `data` ends before the VarInt does.
+pub fn read_varint(data: &[u8], max_bytes: usize) -> Result<Option<(i32, usize)>, ProtocolError> {
+    let mut result = 0u32;
+    for (i, byte) in data.iter().take(max_bytes).enumerate() {
+        result |= ((byte & 0x7F) as u32) << (7 * i);
+        if (byte & 0x80) == 0 {
+            return Ok(Some((result as i32, i + 1)));
+        }
+    }
+    if data.len() >= max_bytes {
+        Err(ProtocolError::VarIntTooBig)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Splits the byte stream of a connection into packets (packet id + fields) and back.
+/// Handles the length prefix and, once negotiated, compression.
+#[derive(Default)]
+pub struct PacketCodec {
+    pub compression: Option<usize>,
+}
+
+impl Decoder for PacketCodec {
+    type Item = Vec<u8>;
+    type Error = ProtocolError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let Some((length, header)) = read_varint(src, MAX_FRAME_LENGTH_BYTES)? else {
+            return Ok(None);
+        };
+        let length = length as usize;
+        if length == 0 {
+            return Err(ProtocolError::InvalidFrame("Empty packet"));
+        }
+        if src.len() < header + length {
+            // Grow the buffer once instead of on every read
+            src.reserve(header + length - src.len());
+            return Ok(None);
+        }
+        src.advance(header);
+        let body = src.split_to(length).to_vec();
+        compression::unframe(body, self.compression).map(Some)
+    }
+}
+
+impl Encoder<Vec<u8>> for PacketCodec {
+    type Error = ProtocolError;
+
+    fn encode(&mut self, payload: Vec<u8>, dst: &mut BytesMut) -> Result<(), ProtocolError> {
+        let data = compression::frame(payload, self.compression);
+        if let Some((length, _)) = read_varint(&data, MAX_VARINT_BYTES)? {
+            if length as usize > MAX_FRAME_LENGTH {
+                return Err(ProtocolError::InvalidFrame("Packet is too big"));
+            }
+        }
+        dst.extend_from_slice(&data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_incomplete_frames() {
+        let mut codec = PacketCodec::default();
+        let mut src = BytesMut::from(&[0x05, 0x00, 0x01][..]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&[0x02, 0x03, 0x04, 0x06, 0x00]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(vec![0x00, 0x01, 0x02, 0x03, 0x04]));
+        assert_eq!(&src[..], &[0x06, 0x00]);
+    }
+
+    #[test]
+    fn rejects_frame_length_over_three_bytes() {
+        let mut codec = PacketCodec::default();
+        let mut src = BytesMut::from(&[0xFF, 0xFF, 0xFF, 0x01][..]);
+        assert!(codec.decode(&mut src).is_err());
+    }
+
+    #[test]
+    fn rejects_varint_over_five_bytes() {
+        assert!(read_varint(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x01], MAX_VARINT_BYTES).is_err());
+        assert_eq!(read_varint(&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F], MAX_VARINT_BYTES).unwrap(), Some((-1, 5)));
+    }
+
+    #[test]
+    fn round_trips_compressed_frames() {
+        let mut codec = PacketCodec{compression: Some(64)};
+        let mut dst = BytesMut::new();
+        let small = vec![0x01; 10];
+        let big = vec![0x02; 1000];
+        codec.encode(small.clone(), &mut dst).unwrap();
+        codec.encode(big.clone(), &mut dst).unwrap();
+        assert!(dst.len() < 1000);
+        assert_eq!(codec.decode(&mut dst).unwrap(), Some(small));
+        assert_eq!(codec.decode(&mut dst).unwrap(), Some(big));
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use super::codec;
+use super::error::ProtocolError;
+use super::packets::serialization::Serializable;
+
+/// Wraps a packet (id + fields) into a length prefixed frame.
+///
+/// Without a threshold this is `length | payload`. With compression enabled it is
+/// `length | data length | zlib(payload)`, where a data length of 0 marks a payload
+/// that is smaller than the threshold and therefore sent uncompressed.
+pub fn frame(payload: Vec<u8>, threshold: Option<usize>) -> Vec<u8> {
+    let body = match threshold {
+        None => payload,
+        Some(threshold) if payload.len() < threshold => {
+            let mut body = 0.serialize();
+            body.extend(payload);
+            body
+        }
+        Some(_) => {
+            let mut body = (payload.len() as i32).serialize();
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            // Writing into a Vec can't fail
+            encoder.write_all(&payload).unwrap();
+            body.extend(encoder.finish().unwrap());
+            body
+        }
+    };
+    let mut data = (body.len() as i32).serialize();
+    data.extend(body);
+    data
+}
+
+/// Vanilla refuses to inflate packets bigger than this
+pub const MAX_UNCOMPRESSED_LENGTH: usize = 2097152;
+
+/// Reverses [`frame`] for a frame whose length prefix was already consumed,
+/// returning the plain packet id + fields.
+pub fn unframe(body: Vec<u8>, threshold: Option<usize>) -> Result<Vec<u8>, ProtocolError> {
+    let Some(threshold) = threshold else {
+        return Ok(body);
+    };
+    let Some((data_length, index)) = codec::read_varint(&body, codec::MAX_VARINT_BYTES)? else {
+        return Err(ProtocolError::InvalidFrame("Missing data length"));
+    };
+    if data_length == 0 {
+        return Ok(body[index..].to_vec());
+    }
+    let data_length = data_length as usize;
+    if data_length < threshold {
+        return Err(ProtocolError::InvalidFrame("Compressed packet is below the threshold"));
+    }
+    if data_length > MAX_UNCOMPRESSED_LENGTH {
+        return Err(ProtocolError::InvalidFrame("Compressed packet is too big"));
+    }
+
+    let mut payload = Vec::with_capacity(data_length);
+    // Never inflate more than announced
+    ZlibDecoder::new(&body[index..]).take(data_length as u64 + 1).read_to_end(&mut payload)?;
+    if payload.len() != data_length {
+        return Err(ProtocolError::InvalidFrame("Decompressed length does not match data length"));
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The body of a frame, without its length prefix
+    fn body(frame: &[u8]) -> Vec<u8> {
+        let (length, index) = codec::read_varint(frame, codec::MAX_VARINT_BYTES).unwrap().unwrap();
+        assert_eq!(length as usize, frame.len() - index);
+        frame[index..].to_vec()
+    }
+
+    #[test]
+    fn small_packets_are_sent_uncompressed() {
+        let payload = vec![0x01, 0x02, 0x03];
+        let framed = frame(payload.clone(), Some(256));
+        assert_eq!(framed, [0x04, 0x00, 0x01, 0x02, 0x03]);
+        assert_eq!(unframe(body(&framed), Some(256)).unwrap(), payload);
+
+        // Without a threshold there is no data length at all
+        assert_eq!(frame(payload.clone(), None), [0x03, 0x01, 0x02, 0x03]);
+        assert_eq!(unframe(payload.clone(), None).unwrap(), payload);
+    }
+
+    #[test]
+    fn big_packets_are_compressed() {
+        let payload = vec![0x2A; 1000];
+        let body = body(&frame(payload.clone(), Some(256)));
+        // The data length is the uncompressed size
+        assert_eq!(body[..2], [0xE8, 0x07]);
+        assert!(body.len() < payload.len());
+        assert_eq!(unframe(body, Some(256)).unwrap(), payload);
+    }
+
+    #[test]
+    fn rejects_bad_data_lengths() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0x00; 16]).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let too_big = [((MAX_UNCOMPRESSED_LENGTH + 1) as i32).serialize(), compressed.clone()].concat();
+        assert!(matches!(unframe(too_big, Some(256)), Err(ProtocolError::InvalidFrame("Compressed packet is too big"))));
+        let below_threshold = [16.serialize(), compressed.clone()].concat();
+        assert!(matches!(unframe(below_threshold, Some(256)), Err(ProtocolError::InvalidFrame(_))));
+        let wrong_length = [300.serialize(), compressed].concat();
+        assert!(matches!(unframe(wrong_length, Some(256)), Err(ProtocolError::InvalidFrame(_))));
+    }
+}
@@ -0,0 +1,170 @@
+use super::packets::clientbound::ClientboundRegistryDataPacket;
+use super::types::{KnownPack, RegistryEntry};
+
+/// What a version with the configuration state (1.20.2+) is sent between login and play
+pub struct Configuration {
+    /// The versions of the vanilla data pack `minecraft:core`, one per game version of the protocol.
+    /// Registries are sent without their contents, so the client has to have one of these packs.
+    pub core_pack_versions: &'static [&'static str],
+    pub feature_flags: &'static [&'static str],
+    /// Every registry the client expects from the server, with the ids of its entries.
+    /// An entry's index is the id packets refer to it by.
+    pub registries: &'static [(&'static str, &'static [&'static str])],
+}
+
+impl Configuration {
+    pub fn core_packs(&self) -> Vec<KnownPack> {
+        self.core_pack_versions.iter()
+            .map(|version| KnownPack{
+                namespace: "minecraft".to_owned(),
+                id: "core".to_owned(),
+                version: version.to_string(),
+            })
+            .collect()
+    }
+
+    /// A Registry Data packet per registry, the contents of the entries come from the core pack
+    pub fn registry_data(&self) -> impl Iterator<Item = ClientboundRegistryDataPacket> + '_ {
+        self.registries.iter().map(|(registry, entries)| ClientboundRegistryDataPacket{
+            registry_id: registry.to_string(),
+            entries: entries.iter().map(|entry| RegistryEntry{id: entry.to_string(), data: None}).collect(),
+        })
+    }
+
+    /// The network id of an entry
+    pub fn index_of(&self, registry: &str, entry: &str) -> Option<usize> {
+        let (_, entries) = self.registries.iter().find(|(id, _)| *id == registry)?;
+        entries.iter().position(|candidate| *candidate == entry)
+    }
+}
+
+/// The client looks every one of these up, none can be left out
+const DAMAGE_TYPES: &[&str] = &[
+    "minecraft:arrow", "minecraft:bad_respawn_point", "minecraft:cactus", "minecraft:cramming",
+    "minecraft:dragon_breath", "minecraft:drown", "minecraft:dry_out", "minecraft:explosion", "minecraft:fall",
+    "minecraft:falling_anvil", "minecraft:falling_block", "minecraft:falling_stalactite", "minecraft:fireball",
+    "minecraft:fireworks", "minecraft:fly_into_wall", "minecraft:freeze", "minecraft:generic",
+    "minecraft:generic_kill", "minecraft:hot_floor", "minecraft:in_fire", "minecraft:in_wall",
+    "minecraft:indirect_magic", "minecraft:lava", "minecraft:lightning_bolt", "minecraft:mace_smash",
+    "minecraft:magic", "minecraft:mob_attack", "minecraft:mob_attack_no_aggro", "minecraft:mob_projectile",
+    "minecraft:on_fire", "minecraft:out_of_world", "minecraft:outside_border", "minecraft:player_attack",
+    "minecraft:player_explosion", "minecraft:sonic_boom", "minecraft:spit", "minecraft:stalagmite",
+    "minecraft:starve", "minecraft:sting", "minecraft:sweet_berry_bush", "minecraft:thorns", "minecraft:thrown",
+    "minecraft:trident", "minecraft:unattributed_fireball", "minecraft:wind_charge", "minecraft:wither",
+    "minecraft:wither_skull",
+];
+
+/// 1.21 and 1.21.1
+pub const V1_21: Configuration = Configuration{
+    core_pack_versions: &["1.21", "1.21.1"],
+    feature_flags: &["minecraft:vanilla"],
+    registries: &[
+        ("minecraft:worldgen/biome", &["minecraft:plains"]),
+        ("minecraft:chat_type", &[
+            "minecraft:chat", "minecraft:emote_command", "minecraft:msg_command_incoming", "minecraft:msg_command_outgoing",
+            "minecraft:say_command", "minecraft:team_msg_command_incoming", "minecraft:team_msg_command_outgoing",
+        ]),
+        ("minecraft:trim_pattern", &[
+            "minecraft:bolt", "minecraft:coast", "minecraft:dune", "minecraft:eye", "minecraft:flow", "minecraft:host",
+            "minecraft:raiser", "minecraft:rib", "minecraft:sentry", "minecraft:shaper", "minecraft:silence",
+            "minecraft:snout", "minecraft:spire", "minecraft:tide", "minecraft:vex", "minecraft:ward",
+            "minecraft:wayfinder", "minecraft:wild",
+        ]),
+        ("minecraft:trim_material", &[
+            "minecraft:amethyst", "minecraft:copper", "minecraft:diamond", "minecraft:emerald", "minecraft:gold",
+            "minecraft:iron", "minecraft:lapis", "minecraft:netherite", "minecraft:quartz", "minecraft:redstone",
+        ]),
+        ("minecraft:wolf_variant", &[
+            "minecraft:ashen", "minecraft:black", "minecraft:chestnut", "minecraft:pale", "minecraft:rusty",
+            "minecraft:snowy", "minecraft:spotted", "minecraft:striped", "minecraft:woods",
+        ]),
+        ("minecraft:painting_variant", &[
+            "minecraft:alban", "minecraft:aztec", "minecraft:aztec2", "minecraft:bomb", "minecraft:burning_skull",
+            "minecraft:bust", "minecraft:courbet", "minecraft:creebet", "minecraft:donkey_kong", "minecraft:fighters",
+            "minecraft:graham", "minecraft:kebab", "minecraft:match", "minecraft:pigscene", "minecraft:plant",
+            "minecraft:pointer", "minecraft:pool", "minecraft:sea", "minecraft:skeleton", "minecraft:skull_and_roses",
+            "minecraft:stage", "minecraft:sunset", "minecraft:void", "minecraft:wanderer", "minecraft:wasteland",
+            "minecraft:wither",
+        ]),
+        ("minecraft:dimension_type", &[
+            "minecraft:overworld", "minecraft:overworld_caves", "minecraft:the_end", "minecraft:the_nether",
+        ]),
+        ("minecraft:damage_type", DAMAGE_TYPES),
+        ("minecraft:banner_pattern", &[
+            "minecraft:base", "minecraft:border", "minecraft:bricks", "minecraft:circle", "minecraft:creeper",
+            "minecraft:cross", "minecraft:curly_border", "minecraft:diagonal_left", "minecraft:diagonal_right",
+            "minecraft:diagonal_up_left", "minecraft:diagonal_up_right", "minecraft:flow", "minecraft:flower",
+            "minecraft:globe", "minecraft:gradient", "minecraft:gradient_up", "minecraft:guster",
+            "minecraft:half_horizontal", "minecraft:half_horizontal_bottom", "minecraft:half_vertical",
+            "minecraft:half_vertical_right", "minecraft:mojang", "minecraft:piglin", "minecraft:rhombus",
+            "minecraft:skull", "minecraft:small_stripes", "minecraft:square_bottom_left", "minecraft:square_bottom_right",
+            "minecraft:square_top_left", "minecraft:square_top_right", "minecraft:straight_cross",
+            "minecraft:stripe_bottom", "minecraft:stripe_center", "minecraft:stripe_downleft",
+            "minecraft:stripe_downright", "minecraft:stripe_left", "minecraft:stripe_middle", "minecraft:stripe_right",
+            "minecraft:stripe_top", "minecraft:triangle_bottom", "minecraft:triangle_top", "minecraft:triangles_bottom",
+            "minecraft:triangles_top",
+        ]),
+        ("minecraft:enchantment", &[
+            "minecraft:aqua_affinity", "minecraft:bane_of_arthropods", "minecraft:binding_curse",
+            "minecraft:blast_protection", "minecraft:breach", "minecraft:channeling", "minecraft:density",
+            "minecraft:depth_strider", "minecraft:efficiency", "minecraft:feather_falling", "minecraft:fire_aspect",
+            "minecraft:fire_protection", "minecraft:flame", "minecraft:fortune", "minecraft:frost_walker",
+            "minecraft:impaling", "minecraft:infinity", "minecraft:knockback", "minecraft:looting", "minecraft:loyalty",
+            "minecraft:luck_of_the_sea", "minecraft:lure", "minecraft:mending", "minecraft:multishot",
+            "minecraft:piercing", "minecraft:power", "minecraft:projectile_protection", "minecraft:protection",
+            "minecraft:punch", "minecraft:quick_charge", "minecraft:respiration", "minecraft:riptide",
+            "minecraft:sharpness", "minecraft:silk_touch", "minecraft:smite", "minecraft:soul_speed",
+            "minecraft:sweeping_edge", "minecraft:swift_sneak", "minecraft:thorns", "minecraft:unbreaking",
+            "minecraft:vanishing_curse", "minecraft:wind_burst",
+        ]),
+        ("minecraft:jukebox_song", &[
+            "minecraft:11", "minecraft:13", "minecraft:5", "minecraft:blocks", "minecraft:cat", "minecraft:chirp",
+            "minecraft:creator", "minecraft:creator_music_box", "minecraft:far", "minecraft:mall", "minecraft:mellohi",
+            "minecraft:otherside", "minecraft:pigstep", "minecraft:precipice", "minecraft:relic", "minecraft:stal",
+            "minecraft:strad", "minecraft:wait", "minecraft:ward",
+        ]),
+    ],
+};
+
+/// 1.20.5 and 1.20.6. The content added by 1.21 only exists in an experimental pack, which isn't enabled.
+pub const V1_20_5: Configuration = Configuration{
+    core_pack_versions: &["1.20.5", "1.20.6"],
+    feature_flags: &["minecraft:vanilla"],
+    registries: &[
+        ("minecraft:worldgen/biome", &["minecraft:plains"]),
+        ("minecraft:chat_type", &[
+            "minecraft:chat", "minecraft:emote_command", "minecraft:msg_command_incoming", "minecraft:msg_command_outgoing",
+            "minecraft:say_command", "minecraft:team_msg_command_incoming", "minecraft:team_msg_command_outgoing",
+        ]),
+        ("minecraft:trim_pattern", &[
+            "minecraft:coast", "minecraft:dune", "minecraft:eye", "minecraft:host", "minecraft:raiser", "minecraft:rib",
+            "minecraft:sentry", "minecraft:shaper", "minecraft:silence", "minecraft:snout", "minecraft:spire",
+            "minecraft:tide", "minecraft:vex", "minecraft:ward", "minecraft:wayfinder", "minecraft:wild",
+        ]),
+        ("minecraft:trim_material", &[
+            "minecraft:amethyst", "minecraft:copper", "minecraft:diamond", "minecraft:emerald", "minecraft:gold",
+            "minecraft:iron", "minecraft:lapis", "minecraft:netherite", "minecraft:quartz", "minecraft:redstone",
+        ]),
+        ("minecraft:wolf_variant", &[
+            "minecraft:ashen", "minecraft:black", "minecraft:chestnut", "minecraft:pale", "minecraft:rusty",
+            "minecraft:snowy", "minecraft:spotted", "minecraft:striped", "minecraft:woods",
+        ]),
+        ("minecraft:dimension_type", &[
+            "minecraft:overworld", "minecraft:overworld_caves", "minecraft:the_end", "minecraft:the_nether",
+        ]),
+        ("minecraft:damage_type", DAMAGE_TYPES),
+        ("minecraft:banner_pattern", &[
+            "minecraft:base", "minecraft:border", "minecraft:bricks", "minecraft:circle", "minecraft:creeper",
+            "minecraft:cross", "minecraft:curly_border", "minecraft:diagonal_left", "minecraft:diagonal_right",
+            "minecraft:diagonal_up_left", "minecraft:diagonal_up_right", "minecraft:flower", "minecraft:globe",
+            "minecraft:gradient", "minecraft:gradient_up", "minecraft:half_horizontal", "minecraft:half_horizontal_bottom",
+            "minecraft:half_vertical", "minecraft:half_vertical_right", "minecraft:mojang", "minecraft:piglin",
+            "minecraft:rhombus", "minecraft:skull", "minecraft:small_stripes", "minecraft:square_bottom_left",
+            "minecraft:square_bottom_right", "minecraft:square_top_left", "minecraft:square_top_right",
+            "minecraft:straight_cross", "minecraft:stripe_bottom", "minecraft:stripe_center", "minecraft:stripe_downleft",
+            "minecraft:stripe_downright", "minecraft:stripe_left", "minecraft:stripe_middle", "minecraft:stripe_right",
+            "minecraft:stripe_top", "minecraft:triangle_bottom", "minecraft:triangle_top", "minecraft:triangles_bottom",
+            "minecraft:triangles_top",
+        ]),
+    ],
+};
@@ -0,0 +1,783 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{stream, SinkExt, StreamExt};
+use md5::{Digest, Md5};
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use tracing::{debug, field, warn, Instrument, Span};
+use uuid::Uuid;
+
+use super::codec::PacketCodec;
+use super::encryption::{self, EncryptedReader, EncryptedWriter, ServerKey};
+use super::error::ProtocolError;
+use super::legacy_ping::{self, LegacyPing};
+use super::other::{self, Direction, State};
+use super::packets::clientbound::{ClientboundConfigDisconnectPacket, ClientboundConfigPluginMessagePacket, ClientboundDisconnectPacket, ClientboundEncryptionRequestPacket, ClientboundFeatureFlagsPacket, ClientboundFinishConfigurationPacket, ClientboundKnownPacksPacket, ClientboundLoginSuccesPacket, ClientboundPingResponsePacket, ClientboundSetCompressionPacket, ClientboundStatusResponsePacket};
+use super::packets::serialization::{self, Decode, Serializable};
+use super::packets::serverbound::{ServerboundAcknowledgeFinishConfigurationPacket, ServerboundClientInformationPacket, ServerboundConfigKeepAlivePacket, ServerboundConfigPluginMessagePacket, ServerboundConfigResourcePackResponsePacket, ServerboundEncryptionResponsePacket, ServerboundHandshakePacket, ServerboundKnownPacksPacket, ServerboundLoginAcknowledgedPacket, ServerboundLoginStartPacket, ServerboundPingRequestPacket, ServerboundPongPacket, ServerboundStatusRequestPacket};
+use super::packets::{self, decode, Packet, PacketInfo};
+use super::registry::{PacketRegistry, Registries};
+use super::session::{self, GameProfile, SessionService};
+use super::smpmap::Block;
+use super::status::{OnlinePlayers, StatusHook, StatusPlayers, StatusRequest, StatusResponse, StatusVersion};
+use super::types::NetworkNbt;
+
+/// Sent to clients in the minecraft:brand plugin message, shown in their debug screen
+pub const SERVER_BRAND: &str = "rapid";
+/// How long to wait for the rest of a legacy ping once its first byte arrived
+const LEGACY_PING_TIMEOUT: Duration = Duration::from_millis(100);
+/// Capacity of the server inbox shared by all connections
+pub const SERVER_INBOX_CAPACITY: usize = 1024;
+/// Capacity of the outbox of every connection in play state
+pub const OUTBOX_CAPACITY: usize = 256;
+
+pub type Outbox = mpsc::Sender<Box<dyn Packet + Send>>;
+type Reader = FramedRead<EncryptedReader<OwnedReadHalf>, PacketCodec>;
+type Writer = FramedWrite<EncryptedWriter<OwnedWriteHalf>, PacketCodec>;
+
+/// Identifies a connection for as long as the server runs, ids are never reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionId(pub u64);
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Why a connection or player went away
+#[derive(Debug)]
+pub enum DisconnectReason {
+    /// The client closed the connection
+    Closed,
+    /// The client sent something invalid
+    Error(ProtocolError),
+    /// The server kicked the player with this message
+    Kicked(String),
+    /// The server closed the connection, e.g. after a kick
+    Server,
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::Closed => write!(f, "Connection closed"),
+            DisconnectReason::Error(error) => write!(f, "{error}"),
+            DisconnectReason::Kicked(reason) => write!(f, "Kicked: {reason}"),
+            DisconnectReason::Server => write!(f, "Closed by the server"),
+        }
+    }
+}
+
+/// Messages to the server task, mostly from connections
+pub enum ServerMessage {
+    /// A client connected, always the first message of a connection
+    Open {
+        id: ConnectionId,
+        address: SocketAddr,
+    },
+    /// The connection finished logging in, packets for it go to `outbox`
+    Join {
+        id: ConnectionId,
+        profile: GameProfile,
+        outbox: Outbox,
+    },
+    /// A play state packet, still raw (packet id + fields)
+    Packet {
+        id: ConnectionId,
+        data: Vec<u8>,
+    },
+    /// The client sent something invalid and has to be kicked
+    Error {
+        id: ConnectionId,
+        error: ProtocolError,
+    },
+    /// A client asked for the server list entry
+    Status {
+        reply: oneshot::Sender<OnlinePlayers>,
+    },
+    /// Changes a block, sent by [`ServerHandle::set_block`](crate::ServerHandle::set_block)
+    SetBlock {
+        x: i32,
+        y: i32,
+        z: i32,
+        block: Block,
+        /// Whether the block changed
+        reply: oneshot::Sender<bool>,
+    },
+    /// The connection ended, always the last message of a connection
+    Close {
+        id: ConnectionId,
+        reason: DisconnectReason,
+    },
+    /// Ends the server task, sent by [`ServerHandle::stop`](crate::ServerHandle::stop)
+    Stop,
+}
+
+/// Everything a connection needs to log a player in, shared by all connection tasks
+pub struct Settings {
+    /// Negative disables compression
+    pub compression_threshold: i32,
+    pub key: ServerKey,
+    /// Online mode if set
+    pub session_service: Option<Arc<dyn SessionService>>,
+    /// The protocol versions clients can join with
+    pub registries: Registries,
+    /// Shown in the server list
+    pub max_players: u32,
+    /// The MOTD as a chat component
+    pub motd: serde_json::Value,
+    /// A data URL, see [`favicon_from_png`](super::status::favicon_from_png)
+    pub favicon: Option<String>,
+    pub status_hook: Option<StatusHook>,
+    /// Set to the disconnect message once the server shuts down
+    pub shutdown: watch::Receiver<Option<String>>,
+}
+
+/// A single client. Handshake, status and login are handled by the connection task itself,
+/// so slow logins (e.g. waiting for the session server) never hold up anybody else.
+/// Once the player is in play state the server task takes over through [`ServerMessage`]s.
+pub struct Connection {
+    id: ConnectionId,
+    reader: Reader,
+    writer: Writer,
+    state: State,
+    /// Translates between the client's protocol version and the packet structs, known after the handshake
+    registry: Arc<PacketRegistry>,
+    settings: Arc<Settings>,
+    server: mpsc::Sender<ServerMessage>,
+    //What the handshake asked the status for
+    status_request: Option<StatusRequest>,
+    //Username and verify token while waiting for the Encryption Response
+    pending_login: Option<(String, Vec<u8>)>,
+    profile: Option<GameProfile>,
+    //Set once the client was kicked, nothing it sends afterwards is read
+    kicked: bool,
+}
+
+impl Connection {
+    pub fn new(id: ConnectionId, socket: TcpStream, settings: Arc<Settings>, server: mpsc::Sender<ServerMessage>) -> Connection {
+        let (reader, writer) = socket.into_split();
+        Connection{
+            id,
+            reader: FramedRead::new(EncryptedReader::new(reader), PacketCodec::default()),
+            writer: FramedWrite::new(EncryptedWriter::new(writer), PacketCodec::default()),
+            state: State::HANDSHAKE,
+            registry: settings.registries.newest().clone(),
+            settings,
+            server,
+            status_request: None,
+            pending_login: None,
+            profile: None,
+            kicked: false,
+        }
+    }
+
+    /// Runs the connection until it ends and returns why
+    pub async fn run(mut self) -> DisconnectReason {
+        if self.answer_legacy_ping().await {
+            return DisconnectReason::Closed;
+        }
+        let mut shutdown = self.settings.shutdown.clone();
+        while self.state != State::PLAY {
+            // Players are disconnected by the server, everybody else has to do it themselves
+            let frame = tokio::select! {
+                frame = self.reader.next() => Some(frame),
+                _ = shutdown.wait_for(Option::is_some) => None,
+            };
+            let Some(frame) = frame else {
+                let message = shutdown.borrow().clone().unwrap_or_default();
+                self.disconnect(&message).await;
+                return DisconnectReason::Server;
+            };
+            let result = match frame {
+                Some(Ok(data)) => {
+                    let span = tracing::info_span!("state", state = ?self.state);
+                    self.handle_raw_packet(data).instrument(span).await
+                }
+                Some(Err(e)) => Err(e),
+                None => return DisconnectReason::Closed,
+            };
+            if let Err(e) = result {
+                warn!("Protocol error: {}", e);
+                self.disconnect(&e.to_string()).await;
+                return DisconnectReason::Error(e);
+            }
+            if self.kicked {
+                return DisconnectReason::Server;
+            }
+        }
+        match self.profile.take() {
+            Some(profile) => {
+                Span::current().record("username", profile.name.as_str());
+                self.play(profile).instrument(tracing::info_span!("state", state = ?State::PLAY)).await
+            }
+            None => DisconnectReason::Server,
+        }
+    }
+
+    /// Hands the player over to the server: a reader task forwards everything the client
+    /// sends and this task writes whatever the server puts into the outbox.
+    async fn play(mut self, profile: GameProfile) -> DisconnectReason {
+        let (outbox, mut inbox) = mpsc::channel::<Box<dyn Packet + Send>>(OUTBOX_CAPACITY);
+        if self.server.send(ServerMessage::Join{id: self.id, profile, outbox}).await.is_err() {
+            return DisconnectReason::Server;
+        }
+        let reader = read_packets(self.id, self.reader, self.registry.clone(), self.server.clone());
+        let mut reader = tokio::spawn(reader.instrument(Span::current()));
+
+        // Ends once the server drops the outbox or the client closed the connection
+        let mut reason = None;
+        loop {
+            tokio::select! {
+                packet = inbox.recv() => {
+                    let Some(packet) = packet else {
+                        break;
+                    };
+                    packets::trace(Direction::Clientbound, &packet);
+                    // Older and newer versions may need several packets or none at all
+                    let packets = match self.registry.encode_all(State::PLAY, packet.serialize_payload()) {
+                        Ok(packets) => packets,
+                        Err(err) => {
+                            warn!("Error translating packet: {}", err);
+                            continue;
+                        }
+                    };
+                    if let Err(err) = self.writer.send_all(&mut stream::iter(packets).map(Ok)).await {
+                        reason.get_or_insert(DisconnectReason::Error(err));
+                        break;
+                    }
+                }
+                result = &mut reader, if reason.is_none() => {
+                    match result.unwrap_or(DisconnectReason::Closed) {
+                        // The server kicks the player, keep writing until its Disconnect went out
+                        kicked @ DisconnectReason::Kicked(_) => reason = Some(kicked),
+                        other => {
+                            reason = Some(other);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        // Makes sure a final Disconnect reaches the client
+        let _ = self.writer.close().await;
+        match reason {
+            Some(reason) => {
+                reader.abort();
+                reason
+            }
+            // The server may have dropped the outbox because of what the reader reported
+            None if reader.is_finished() => reader.await.unwrap_or(DisconnectReason::Server),
+            None => {
+                reader.abort();
+                DisconnectReason::Server
+            }
+        }
+    }
+
+    /// Answers the server list ping of clients before 1.7, which isn't framed like packets.
+    /// Only peeks at the socket, so a modern handshake is still read normally. Returns whether it was one.
+    async fn answer_legacy_ping(&mut self) -> bool {
+        let socket = self.reader.get_mut().get_mut();
+        let mut buffer = [0u8; 512];
+        let Ok(mut len) = socket.peek(&mut buffer).await else {
+            return false;
+        };
+        if len == 0 || buffer[0] != legacy_ping::LEGACY_PING {
+            return false;
+        }
+        // Only a 1.6 ping is recognizable as complete, the older ones are known once nothing else comes
+        let wait = async {
+            loop {
+                if let Some(ping) = legacy_ping::parse(&buffer[..len], false) {
+                    return ping;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                len = socket.peek(&mut buffer).await.unwrap_or(len);
+            }
+        };
+        let ping = match tokio::time::timeout(LEGACY_PING_TIMEOUT, wait).await {
+            Ok(ping) => ping,
+            Err(_) => legacy_ping::parse(&buffer[..len], true).unwrap_or(LegacyPing::Beta),
+        };
+        let request = match &ping {
+            LegacyPing::V1_6{protocol, host, port} => StatusRequest{
+                protocol_version: *protocol as i32,
+                server_address: host.clone(),
+                server_port: *port,
+            },
+            _ => StatusRequest{
+                protocol_version: 0,
+                server_address: String::new(),
+                server_port: 0,
+            },
+        };
+        let status = self.status_response(&request).await;
+        let writer = self.writer.get_mut();
+        if let Err(err) = writer.write_all(&legacy_ping::response(&ping, &status)).await {
+            debug!("Error writing legacy ping response: {}", err);
+        }
+        let _ = writer.shutdown().await;
+        true
+    }
+
+    async fn handle_raw_packet(&mut self, data: Vec<u8>) -> Result<(), ProtocolError> {
+        let Some(data) = self.registry.decode(self.state, data)? else {
+            return Ok(());
+        };
+        let (pid, data) = serialization::split_packet_id(&data)?;
+        match (self.state, pid) {
+            (ServerboundHandshakePacket::STATE, ServerboundHandshakePacket::ID) => self.handle_handshake_packet(decode::<ServerboundHandshakePacket>(data)?).await,
+            (ServerboundStatusRequestPacket::STATE, ServerboundStatusRequestPacket::ID) => self.handle_status_request_packet(decode::<ServerboundStatusRequestPacket>(data)?).await,
+            (ServerboundPingRequestPacket::STATE, ServerboundPingRequestPacket::ID) => self.handle_ping_request_packet(decode::<ServerboundPingRequestPacket>(data)?).await,
+            (ServerboundLoginStartPacket::STATE, ServerboundLoginStartPacket::ID) => self.handle_start_login_packet(decode::<ServerboundLoginStartPacket>(data)?).await,
+            (ServerboundEncryptionResponsePacket::STATE, ServerboundEncryptionResponsePacket::ID) => self.handle_encryption_response_packet(decode::<ServerboundEncryptionResponsePacket>(data)?).await,
+            (ServerboundLoginAcknowledgedPacket::STATE, ServerboundLoginAcknowledgedPacket::ID) => self.handle_login_acknowledged_packet(decode::<ServerboundLoginAcknowledgedPacket>(data)?).await,
+            (ServerboundClientInformationPacket::STATE, ServerboundClientInformationPacket::ID) => ignore::<ServerboundClientInformationPacket>(data),
+            (ServerboundConfigPluginMessagePacket::STATE, ServerboundConfigPluginMessagePacket::ID) => self.handle_config_plugin_message_packet(decode::<ServerboundConfigPluginMessagePacket>(data)?).await,
+            (ServerboundKnownPacksPacket::STATE, ServerboundKnownPacksPacket::ID) => self.handle_known_packs_packet(decode::<ServerboundKnownPacksPacket>(data)?).await,
+            (ServerboundAcknowledgeFinishConfigurationPacket::STATE, ServerboundAcknowledgeFinishConfigurationPacket::ID) => self.handle_acknowledge_finish_configuration_packet(decode::<ServerboundAcknowledgeFinishConfigurationPacket>(data)?).await,
+            (ServerboundConfigKeepAlivePacket::STATE, ServerboundConfigKeepAlivePacket::ID) => ignore::<ServerboundConfigKeepAlivePacket>(data),
+            (ServerboundPongPacket::STATE, ServerboundPongPacket::ID) => ignore::<ServerboundPongPacket>(data),
+            (ServerboundConfigResourcePackResponsePacket::STATE, ServerboundConfigResourcePackResponsePacket::ID) => ignore::<ServerboundConfigResourcePackResponsePacket>(data),
+            (state, id) => Err(ProtocolError::UnknownPacket{state, id}),
+        }
+    }
+
+    /// Kicks the client with the Disconnect packet of its current state.
+    /// Handshake and status have none, there the connection is simply closed.
+    async fn disconnect(&mut self, reason: &str) {
+        match self.state {
+            State::LOGIN => self.send_packet(ClientboundDisconnectPacket{
+                reason: other::chat_text(reason),
+            }).await,
+            State::CONFIGURATION => self.send_packet(ClientboundConfigDisconnectPacket{
+                reason: NetworkNbt::from_json(&other::chat_text(reason)),
+            }).await,
+            _ => {}
+        }
+        let _ = self.writer.close().await;
+        self.kicked = true;
+    }
+
+    async fn send_packet(&mut self, packet: impl Packet) {
+        packets::trace(Direction::Clientbound, &packet);
+        let data = match self.registry.encode(self.state, packet.serialize_payload()) {
+            Ok(Some(data)) => data,
+            _ => return,
+        };
+        if let Err(err) = self.writer.send(data).await {
+            debug!("Error writing packet: {}", err);
+        }
+    }
+
+    async fn handle_handshake_packet(&mut self, packet: ServerboundHandshakePacket) -> Result<(), ProtocolError> {
+        match packet.next_state {
+            1 => {
+                self.state = State::STATUS;
+                self.status_request = Some(StatusRequest{
+                    protocol_version: packet.protocol_version,
+                    server_address: packet.server_address,
+                    server_port: packet.server_port,
+                });
+            }
+            2 => {
+                self.state = State::LOGIN;
+                // Fails with the Outdated client/server disconnect
+                self.registry = self.settings.registries.find(packet.protocol_version)?;
+            }
+            next_state => return Err(ProtocolError::InvalidField(format!("next state {next_state}"))),
+        }
+        Ok(())
+    }
+
+    //Statuspacket handler
+    async fn handle_status_request_packet(&mut self, _: ServerboundStatusRequestPacket) -> Result<(), ProtocolError> {
+        let Some(request) = self.status_request.clone() else {
+            return Err(ProtocolError::InvalidField("unexpected status request".to_owned()));
+        };
+        let response = self.status_response(&request).await;
+        self.send_packet(ClientboundStatusResponsePacket{
+            json_string: response.to_json(),
+        }).await;
+        Ok(())
+    }
+    /// The server list entry from the live player list, changed by the status hook if there is one
+    async fn status_response(&self, request: &StatusRequest) -> StatusResponse {
+        let (reply, online) = oneshot::channel();
+        let online = match self.server.send(ServerMessage::Status{reply}).await {
+            Ok(()) => online.await.unwrap_or_default(),
+            Err(_) => OnlinePlayers::default(),
+        };
+        // Supported clients see their own version, the others the newest one
+        let version = match self.settings.registries.find(request.protocol_version) {
+            Ok(registry) => registry.version,
+            Err(_) => self.settings.registries.newest().version,
+        };
+        let mut response = StatusResponse{
+            version: StatusVersion{
+                name: version.name.to_owned(),
+                protocol: version.protocol,
+            },
+            players: StatusPlayers{
+                max: self.settings.max_players,
+                online: online.count as u32,
+                sample: online.sample,
+            },
+            description: self.settings.motd.clone(),
+            favicon: self.settings.favicon.clone(),
+            enforces_secure_chat: false,
+        };
+        if let Some(hook) = &self.settings.status_hook {
+            hook(request, &mut response);
+        }
+        response
+    }
+    async fn handle_ping_request_packet(&mut self, packet: ServerboundPingRequestPacket) -> Result<(), ProtocolError> {
+        self.send_packet(ClientboundPingResponsePacket{
+            payload: packet.paylaod,
+        }).await;
+        Ok(())
+    }
+
+    //Loginpacket handler
+    async fn handle_start_login_packet(&mut self, packet: ServerboundLoginStartPacket) -> Result<(), ProtocolError> {
+        // Like vanilla, offline mode neither encrypts nor asks the session server
+        if self.settings.session_service.is_none() {
+            let profile = GameProfile{
+                id: generate_offline_uuid(&packet.name),
+                name: packet.name,
+                properties: Vec::new(),
+            };
+            self.finish_login(profile).await;
+            return Ok(());
+        }
+        let verify_token = rand::random::<[u8; 4]>().to_vec();
+        self.send_packet(ClientboundEncryptionRequestPacket{
+            server_id: "".to_owned(),
+            public_key: self.settings.key.public_key().to_vec(),
+            verify_token: verify_token.clone(),
+        }).await;
+        self.pending_login = Some((packet.name, verify_token));
+        Ok(())
+    }
+    async fn handle_encryption_response_packet(&mut self, packet: ServerboundEncryptionResponsePacket) -> Result<(), ProtocolError> {
+        let Some((name, verify_token)) = self.pending_login.take() else {
+            return Err(ProtocolError::InvalidField("unexpected encryption response".to_owned()));
+        };
+        let key = &self.settings.key;
+        let shared_secret = match (key.decrypt(&packet.shared_secret), key.decrypt(&packet.verify_token)) {
+            (Ok(shared_secret), Ok(token)) if token == verify_token => shared_secret,
+            _ => Vec::new(),
+        };
+        let Some((encryptor, decryptor)) = encryption::new_ciphers(&shared_secret) else {
+            return Err(ProtocolError::InvalidField("failed to verify encryption".to_owned()));
+        };
+        // The client encrypts everything after the response
+        self.reader.get_mut().enable(decryptor);
+        self.writer.get_mut().enable(encryptor);
+
+        // Encryption is only requested in online mode
+        let Some(service) = self.settings.session_service.clone() else {
+            return Err(ProtocolError::InvalidField("unexpected encryption response".to_owned()));
+        };
+        let hash = session::server_hash("", &shared_secret, self.settings.key.public_key());
+        let profile = match service.has_joined(&name, &hash).await {
+            Ok(Some(profile)) => profile,
+            Ok(None) => {
+                self.disconnect("Failed to verify username!").await;
+                return Ok(());
+            }
+            Err(err) => {
+                warn!("Couldn't verify {name}: {err}");
+                self.disconnect("Authentication servers are down. Please try again later, sorry!").await;
+                return Ok(());
+            }
+        };
+        self.finish_login(profile).await;
+        Ok(())
+    }
+    async fn finish_login(&mut self, profile: GameProfile) {
+        let threshold = self.settings.compression_threshold;
+        if threshold >= 0 {
+            // Everything after Set Compression uses the compressed format, including the client's packets
+            self.send_packet(ClientboundSetCompressionPacket{
+                threshold,
+            }).await;
+            self.reader.decoder_mut().compression = Some(threshold as usize);
+            self.writer.encoder_mut().compression = Some(threshold as usize);
+        }
+        self.send_packet(ClientboundLoginSuccesPacket{
+            uuid: profile.id.hyphenated().to_string(),
+            username: profile.name.clone(),
+        }).await;
+        // Newer clients acknowledge the login and get configured first
+        if self.registry.configuration.is_none() {
+            self.state = State::PLAY;
+        }
+        self.profile = Some(profile);
+    }
+
+    //Configuration handler
+    async fn handle_login_acknowledged_packet(&mut self, _: ServerboundLoginAcknowledgedPacket) -> Result<(), ProtocolError> {
+        let Some(configuration) = self.registry.configuration.filter(|_| self.profile.is_some()) else {
+            return Err(ProtocolError::InvalidField("unexpected login acknowledged".to_owned()));
+        };
+        self.state = State::CONFIGURATION;
+        self.send_packet(ClientboundConfigPluginMessagePacket{
+            channel: "minecraft:brand".to_owned(),
+            data: SERVER_BRAND.to_owned().serialize(),
+        }).await;
+        self.send_packet(ClientboundFeatureFlagsPacket{
+            flags: configuration.feature_flags.iter().map(|flag| flag.to_string()).collect(),
+        }).await;
+        // The registries follow once the client said which packs it has, it only knows its own version's
+        self.send_packet(ClientboundKnownPacksPacket{
+            packs: configuration.core_packs(),
+        }).await;
+        Ok(())
+    }
+    async fn handle_config_plugin_message_packet(&mut self, packet: ServerboundConfigPluginMessagePacket) -> Result<(), ProtocolError> {
+        if packet.channel == "minecraft:brand" {
+            let brand = String::decode(&mut Cursor::new(packet.data.as_slice()))?;
+            debug!("Client brand: {}", brand);
+        }
+        Ok(())
+    }
+    async fn handle_known_packs_packet(&mut self, packet: ServerboundKnownPacksPacket) -> Result<(), ProtocolError> {
+        let Some(configuration) = self.registry.configuration else {
+            return Err(ProtocolError::InvalidField("unexpected known packs".to_owned()));
+        };
+        // Only the ids of the registry entries are sent, their contents have to come from the client's own pack
+        if !configuration.core_packs().iter().any(|pack| packet.packs.contains(pack)) {
+            let versions = configuration.core_pack_versions.join(" or ");
+            return Err(ProtocolError::InvalidField(format!("missing data pack minecraft:core {versions}")));
+        }
+        for registry_data in configuration.registry_data() {
+            self.send_packet(registry_data).await;
+        }
+        self.send_packet(ClientboundFinishConfigurationPacket{}).await;
+        Ok(())
+    }
+    async fn handle_acknowledge_finish_configuration_packet(&mut self, _: ServerboundAcknowledgeFinishConfigurationPacket) -> Result<(), ProtocolError> {
+        self.state = State::PLAY;
+        Ok(())
+    }
+}
+
+/// Forwards every packet of a player to the server until the connection is closed
+async fn read_packets(id: ConnectionId, mut reader: Reader, registry: Arc<PacketRegistry>, server: mpsc::Sender<ServerMessage>) -> DisconnectReason {
+    loop {
+        let result = tokio::select! {
+            result = reader.next() => result,
+            // Lets go of the socket once the server gave up on the connection
+            _ = server.closed() => return DisconnectReason::Server,
+        };
+        let Some(result) = result else {
+            break;
+        };
+        match result.and_then(|data| registry.decode(State::PLAY, data)) {
+            Ok(None) => {}
+            Ok(Some(data)) => {
+                // Waits for room in the inbox instead of dropping packets
+                if server.send(ServerMessage::Packet{id, data}).await.is_err() {
+                    return DisconnectReason::Server;
+                }
+            }
+            // The client went away without closing the connection properly
+            Err(ProtocolError::Io(error)) => return DisconnectReason::Error(ProtocolError::Io(error)),
+            Err(error) => {
+                // The server kicks the player, which also ends the writer
+                let reason = DisconnectReason::Kicked(error.to_string());
+                let _ = server.send(ServerMessage::Error{id, error}).await;
+                return reason;
+            }
+        }
+    }
+    DisconnectReason::Closed
+}
+
+/// Runs a connection from accept to close. The server is told about both ends,
+/// so it can clean up whatever it keeps per connection.
+pub async fn handle_connection(id: ConnectionId, socket: TcpStream, address: SocketAddr, settings: Arc<Settings>, server: mpsc::Sender<ServerMessage>) {
+    if server.send(ServerMessage::Open{id, address}).await.is_err() {
+        return;
+    }
+    // Everything logged by the connection carries these
+    let span = tracing::info_span!("connection", %id, %address, username = field::Empty);
+    let connection = Connection::new(id, socket, settings, server.clone()).run().instrument(span);
+    // A server that gave up waiting for the connection to close is gone, which drops the socket
+    let reason = tokio::select! {
+        reason = connection => reason,
+        _ = server.closed() => return,
+    };
+    let _ = server.send(ServerMessage::Close{id, reason}).await;
+}
+
+/// Decodes a packet nothing acts on yet, so malformed ones still get the client kicked
+pub fn ignore<P: Packet>(data: Vec<u8>) -> Result<(), ProtocolError> {
+    decode::<P>(data)?;
+    Ok(())
+}
+
+/// Vanilla's `UUID.nameUUIDFromBytes("OfflinePlayer:" + name)`, a version 3 UUID without a namespace
+pub fn generate_offline_uuid(username: &str) -> Uuid {
+    let digest = Md5::digest(format!("OfflinePlayer:{username}").as_bytes());
+    uuid::Builder::from_md5_bytes(digest.into()).into_uuid()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs8::DecodePublicKey;
+    use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use crate::utils::packets::clientbound::ClientboundRegistryDataPacket;
+    use crate::utils::packets::serialization::VarIntEncoded;
+    use crate::utils::session::MockSessionService;
+
+    fn frame(packet: Vec<u8>) -> Vec<u8> {
+        [(packet.len() as i32).serialize_varint(), packet].concat()
+    }
+
+    /// Packet id + fields of the next uncompressed frame
+    async fn read_frame(stream: &mut TcpStream) -> Vec<u8> {
+        let mut length = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = stream.read_u8().await.unwrap();
+            length |= ((byte & 0x7F) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut packet = vec![0; length];
+        stream.read_exact(&mut packet).await.unwrap();
+        packet
+    }
+
+    /// A connection of a server without compression and the client's end of it,
+    /// the connection shuts down once the sender is dropped
+    async fn connection(online_mode: bool) -> (Connection, TcpStream, watch::Sender<Option<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let (stop, shutdown) = watch::channel(None);
+        let settings = Arc::new(Settings{
+            compression_threshold: -1,
+            key: ServerKey::generate(),
+            session_service: online_mode.then(|| Arc::new(MockSessionService::new()) as Arc<dyn SessionService>),
+            registries: Registries::supported(online_mode),
+            max_players: 20,
+            motd: serde_json::Value::Null,
+            favicon: None,
+            status_hook: None,
+            shutdown,
+        });
+        let (server, _) = mpsc::channel(1);
+        (Connection::new(ConnectionId(0), socket, settings, server), client, stop)
+    }
+
+    #[test]
+    fn offline_uuids_match_vanilla() {
+        assert_eq!(generate_offline_uuid("Notch"), Uuid::parse_str("b50ad385-829d-3141-a216-7e7d7539ba7f").unwrap());
+    }
+
+    /// Logs a 1.8 client in up to the Encryption Request
+    async fn request_encryption(client: &mut TcpStream) -> ClientboundEncryptionRequestPacket {
+        let handshake = [0.serialize_varint(), 47.serialize_varint(), "localhost".to_owned().serialize(), 25565u16.serialize(), 2.serialize_varint()].concat();
+        let login = ServerboundLoginStartPacket{name: "Tester".to_owned()}.serialize_payload();
+        client.write_all(&[frame(handshake), frame(login)].concat()).await.unwrap();
+        let request = read_frame(client).await;
+        assert_eq!(request[0], 0x01);
+        ClientboundEncryptionRequestPacket::new(request[1..].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn mismatched_verify_tokens_are_rejected() {
+        let (connection, mut client, _stop) = connection(true).await;
+        let connection = tokio::spawn(connection.run());
+        let request = request_encryption(&mut client).await;
+        let public_key = RsaPublicKey::from_public_key_der(&request.public_key).unwrap();
+        let mut rng = rand::thread_rng();
+        let wrong_token: Vec<u8> = request.verify_token.iter().map(|byte| !byte).collect();
+        let response = ServerboundEncryptionResponsePacket{
+            shared_secret: public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &[7; 16]).unwrap(),
+            verify_token: public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &wrong_token).unwrap(),
+        };
+        client.write_all(&frame(response.serialize_payload())).await.unwrap();
+
+        // The kick is still readable, encryption never started
+        let disconnect = read_frame(&mut client).await;
+        assert_eq!(disconnect[0], 0x00);
+        let disconnect = ClientboundDisconnectPacket::new(disconnect[1..].to_vec()).unwrap();
+        assert!(disconnect.reason.contains("failed to verify encryption"), "{}", disconnect.reason);
+        assert!(matches!(connection.await.unwrap(), DisconnectReason::Error(ProtocolError::InvalidField(_))));
+    }
+
+    #[tokio::test]
+    async fn failed_authentication_ends_the_connection() {
+        let (connection, mut client, _stop) = connection(true).await;
+        let connection = tokio::spawn(connection.run());
+        let request = request_encryption(&mut client).await;
+        let public_key = RsaPublicKey::from_public_key_der(&request.public_key).unwrap();
+        let mut rng = rand::thread_rng();
+        // The session server never heard of the player
+        let response = ServerboundEncryptionResponsePacket{
+            shared_secret: public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &[7; 16]).unwrap(),
+            verify_token: public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &request.verify_token).unwrap(),
+        };
+        client.write_all(&frame(response.serialize_payload())).await.unwrap();
+
+        // Only the encrypted Disconnect comes back, then the socket is closed
+        let mut kick = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut kick)).await.unwrap().unwrap();
+        assert!(!kick.is_empty());
+        let reason = tokio::time::timeout(Duration::from_secs(5), connection).await.unwrap().unwrap();
+        assert!(matches!(reason, DisconnectReason::Server), "{reason}");
+    }
+
+    #[tokio::test]
+    async fn configuration_sends_the_registries_of_the_clients_version() {
+        let (connection, mut client, _stop) = connection(false).await;
+        tokio::spawn(connection.run());
+        let handshake = [0.serialize_varint(), 766.serialize_varint(), "localhost".to_owned().serialize(), 25565u16.serialize(), 2.serialize_varint()].concat();
+        let login = [0.serialize_varint(), "Tester".to_owned().serialize(), Uuid::nil().serialize()].concat();
+        client.write_all(&[frame(handshake), frame(login)].concat()).await.unwrap();
+        assert_eq!(read_frame(&mut client).await[0], 0x02);
+        client.write_all(&frame(ServerboundLoginAcknowledgedPacket{}.serialize_payload())).await.unwrap();
+
+        let known_packs = loop {
+            let packet = read_frame(&mut client).await;
+            if packet[0] as i32 == ClientboundKnownPacksPacket::ID {
+                break ClientboundKnownPacksPacket::new(packet[1..].to_vec()).unwrap().packs;
+            }
+        };
+        let versions: Vec<_> = known_packs.iter().map(|pack| pack.version.as_str()).collect();
+        assert_eq!(versions, ["1.20.5", "1.20.6"]);
+        // A 1.20.6 client only has its own version of the pack
+        let packs = known_packs.into_iter().filter(|pack| pack.version == "1.20.6").collect();
+        client.write_all(&frame(ServerboundKnownPacksPacket{packs}.serialize_payload())).await.unwrap();
+
+        let mut registries = Vec::new();
+        loop {
+            let packet = read_frame(&mut client).await;
+            match packet[0] as i32 {
+                ClientboundRegistryDataPacket::ID => registries.push(ClientboundRegistryDataPacket::new(packet[1..].to_vec()).unwrap().registry_id),
+                ClientboundFinishConfigurationPacket::ID => break,
+                id => panic!("unexpected packet {id:#04x}"),
+            }
+        }
+        assert!(registries.contains(&"minecraft:damage_type".to_owned()));
+        // Added by 1.21
+        assert!(!registries.contains(&"minecraft:enchantment".to_owned()));
+    }
+}
@@ -0,0 +1,230 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use aes::cipher::inout::InOutBuf;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use rsa::pkcs8::EncodePublicKey;
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+pub type Encryptor = cfb8::Encryptor<aes::Aes128>;
+pub type Decryptor = cfb8::Decryptor<aes::Aes128>;
+
+/// The RSA keypair generated at startup and used to exchange the shared secret.
+pub struct ServerKey {
+    private_key: RsaPrivateKey,
+    public_key: Vec<u8>,
+}
+
+impl ServerKey {
+    pub fn generate() -> ServerKey {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).expect("Failed to generate RSA keypair");
+        let public_key = private_key.to_public_key().to_public_key_der()
+            .expect("Failed to encode RSA public key")
+            .as_bytes()
+            .to_vec();
+        ServerKey{
+            private_key,
+            public_key,
+        }
+    }
+
+    /// ASN.1 DER encoded public key as sent in the Encryption Request
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, rsa::Error> {
+        self.private_key.decrypt(Pkcs1v15Encrypt, data)
+    }
+}
+
+/// Minecraft uses the shared secret as both the key and the IV.
+pub fn new_ciphers(shared_secret: &[u8]) -> Option<(Encryptor, Decryptor)> {
+    let encryptor = Encryptor::new_from_slices(shared_secret, shared_secret).ok()?;
+    let decryptor = Decryptor::new_from_slices(shared_secret, shared_secret).ok()?;
+    Some((encryptor, decryptor))
+}
+
+/// Decrypts everything read once [`EncryptedReader::enable`] was called.
+pub struct EncryptedReader<R> {
+    inner: R,
+    cipher: Option<Decryptor>,
+}
+
+impl<R> EncryptedReader<R> {
+    pub fn new(inner: R) -> EncryptedReader<R> {
+        EncryptedReader{
+            inner,
+            cipher: None,
+        }
+    }
+
+    pub fn enable(&mut self, cipher: Decryptor) {
+        self.cipher = Some(cipher);
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for EncryptedReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let start = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        if let Some(cipher) = this.cipher.as_mut() {
+            let (blocks, _) = InOutBuf::from(&mut buf.filled_mut()[start..]).into_chunks();
+            cipher.decrypt_blocks_inout_mut(blocks);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Encrypts everything written once [`EncryptedWriter::enable`] was called.
+///
+/// CFB8 is a stream cipher, so encrypted bytes are kept in `pending` until the
+/// inner writer accepted all of them; otherwise a short write would desync the cipher.
+pub struct EncryptedWriter<W> {
+    inner: W,
+    cipher: Option<Encryptor>,
+    pending: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> EncryptedWriter<W> {
+    pub fn new(inner: W) -> EncryptedWriter<W> {
+        EncryptedWriter{
+            inner,
+            cipher: None,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn enable(&mut self, cipher: Encryptor) {
+        self.cipher = Some(cipher);
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.drain(..written);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for EncryptedWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        match this.cipher.as_mut() {
+            None => Pin::new(&mut this.inner).poll_write(cx, buf),
+            Some(cipher) => {
+                let mut data = buf.to_vec();
+                let (blocks, _) = InOutBuf::from(data.as_mut_slice()).into_chunks();
+                cipher.encrypt_blocks_inout_mut(blocks);
+                this.pending = data;
+                // The bytes are accepted either way, anything left over is written by the next call or flush
+                let _ = this.poll_drain(cx)?;
+                Poll::Ready(Ok(buf.len()))
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::AsyncStreamCipher;
+    use rsa::pkcs8::DecodePublicKey;
+    use rsa::RsaPublicKey;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const SECRET: [u8; 16] = *b"0123456789abcdef";
+
+    fn message() -> Vec<u8> {
+        (0..=255).cycle().take(1000).collect()
+    }
+
+    #[test]
+    fn shared_secret_decrypts_what_the_public_key_encrypted() {
+        let key = ServerKey::generate();
+        let public_key = RsaPublicKey::from_public_key_der(key.public_key()).unwrap();
+        let encrypted = public_key.encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, &SECRET).unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), SECRET);
+        assert!(new_ciphers(&SECRET[..15]).is_none());
+    }
+
+    #[tokio::test]
+    async fn split_writes_encrypt_like_a_single_pass() {
+        let (encryptor, _) = new_ciphers(&SECRET).unwrap();
+        let mut expected = message();
+        encryptor.clone().encrypt(&mut expected);
+
+        // The tiny pipe makes most writes short, so bytes wait in `pending`
+        let (client, mut server) = tokio::io::duplex(7);
+        let mut writer = EncryptedWriter::new(client);
+        writer.enable(encryptor);
+        let write = async move {
+            for chunk in message().chunks(13) {
+                writer.write_all(chunk).await.unwrap();
+            }
+            writer.shutdown().await.unwrap();
+        };
+        let mut received = Vec::new();
+        let (_, read) = tokio::join!(write, server.read_to_end(&mut received));
+        read.unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn encrypted_round_trip_over_split_reads() {
+        let (encryptor, decryptor) = new_ciphers(&SECRET).unwrap();
+        let (client, server) = tokio::io::duplex(5);
+        let mut writer = EncryptedWriter::new(client);
+        let mut reader = EncryptedReader::new(server);
+
+        // Like the login, the first bytes are sent before encryption is enabled
+        writer.write_all(b"plain").await.unwrap();
+        let mut plain = [0; 5];
+        reader.read_exact(&mut plain).await.unwrap();
+        assert_eq!(&plain, b"plain");
+
+        writer.enable(encryptor);
+        reader.enable(decryptor);
+        let write = async move {
+            writer.write_all(&message()).await.unwrap();
+            writer.shutdown().await.unwrap();
+        };
+        let read = async {
+            let mut received = Vec::new();
+            let mut buf = [0; 3];
+            loop {
+                let read = reader.read(&mut buf).await.unwrap();
+                if read == 0 {
+                    break received;
+                }
+                received.extend_from_slice(&buf[..read]);
+            }
+        };
+        let (_, received) = tokio::join!(write, read);
+        assert_eq!(received, message());
+    }
+}
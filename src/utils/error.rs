@@ -0,0 +1,49 @@
+use std::{fmt, io};
+
+use super::other::State;
+
+/// Everything that can go wrong while reading or dispatching a client's packets.
+/// Any of these is the client's fault and ends only that client's connection.
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    /// Violations of the framing, like oversized or badly compressed packets
+    InvalidFrame(&'static str),
+    VarIntTooBig,
+    /// The packet ended before all of its fields were read
+    UnexpectedEnd,
+    /// A field holds a value that isn't allowed, like invalid UTF-8 in a string
+    InvalidField(String),
+    UnknownPacket {
+        state: State,
+        id: i32,
+    },
+    /// The client's protocol version is older than every supported one, holds the version to use instead
+    OutdatedClient(&'static str),
+    /// The client's protocol version is newer than every supported one, holds the newest supported version
+    OutdatedServer(&'static str),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(err) => write!(f, "{err}"),
+            ProtocolError::InvalidFrame(msg) => write!(f, "{msg}"),
+            ProtocolError::VarIntTooBig => write!(f, "VarInt is too big"),
+            ProtocolError::UnexpectedEnd => write!(f, "Packet ended unexpectedly"),
+            ProtocolError::InvalidField(msg) => write!(f, "Invalid field: {msg}"),
+            ProtocolError::UnknownPacket{state, id} => write!(f, "Unknown packet: {state:?}:{id:#04x}"),
+            // The messages of vanilla servers
+            ProtocolError::OutdatedClient(version) => write!(f, "Outdated client! Please use {version}"),
+            ProtocolError::OutdatedServer(version) => write!(f, "Outdated server! I'm still on {version}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<io::Error> for ProtocolError {
+    fn from(err: io::Error) -> Self {
+        ProtocolError::Io(err)
+    }
+}
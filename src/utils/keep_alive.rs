@@ -0,0 +1,125 @@
+use std::time::{Duration, Instant};
+
+use super::error::ProtocolError;
+
+/// How often players are asked for a keep alive and how long they have to answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAliveSettings {
+    pub interval: Duration,
+    /// Vanilla clients give up themselves after 20 seconds without a keep alive
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAliveAction {
+    Wait,
+    /// Send a Keep Alive with this id
+    Send(i32),
+    /// The last keep alive wasn't answered in time
+    TimedOut,
+}
+
+/// The keep alive state of a single player
+#[derive(Debug)]
+pub struct KeepAlive {
+    settings: KeepAliveSettings,
+    /// The id waiting for an answer and when it was sent
+    pending: Option<(i32, Instant)>,
+    last_sent: Instant,
+    latency: Option<Duration>,
+}
+
+impl KeepAlive {
+    /// The first keep alive is sent on the first poll
+    pub fn new(settings: KeepAliveSettings, now: Instant) -> KeepAlive {
+        KeepAlive{
+            settings,
+            pending: None,
+            last_sent: now.checked_sub(settings.interval).unwrap_or(now),
+            latency: None,
+        }
+    }
+
+    /// Called regularly, tells whether to send a keep alive or to kick the player
+    pub fn poll(&mut self, now: Instant) -> KeepAliveAction {
+        match self.pending {
+            Some((_, sent)) if now.duration_since(sent) >= self.settings.timeout => KeepAliveAction::TimedOut,
+            Some(_) => KeepAliveAction::Wait,
+            None if now.duration_since(self.last_sent) >= self.settings.interval => {
+                let id = rand::random::<i32>();
+                self.pending = Some((id, now));
+                self.last_sent = now;
+                KeepAliveAction::Send(id)
+            }
+            None => KeepAliveAction::Wait,
+        }
+    }
+
+    /// Handles the client's answer. Ids that weren't asked for are an error, like in vanilla.
+    pub fn receive(&mut self, id: i32, now: Instant) -> Result<(), ProtocolError> {
+        match self.pending {
+            Some((pending, sent)) if pending == id => {
+                self.pending = None;
+                let round_trip = now.duration_since(sent);
+                // Smoothed the same way as vanilla, so a single slow answer doesn't show up as lag
+                let latency = match self.latency {
+                    Some(latency) => (latency * 3 + round_trip) / 4,
+                    None => round_trip,
+                };
+                self.latency = Some(latency);
+                Ok(())
+            }
+            _ => Err(ProtocolError::InvalidField(format!("unexpected keep alive id {id}"))),
+        }
+    }
+
+    /// The smoothed round trip time, zero until the first answer
+    pub fn latency(&self) -> Duration {
+        self.latency.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: KeepAliveSettings = KeepAliveSettings{
+        interval: Duration::from_secs(15),
+        timeout: Duration::from_secs(30),
+    };
+
+    #[test]
+    fn sends_on_the_interval_and_measures_latency() {
+        let start = Instant::now();
+        let mut keep_alive = KeepAlive::new(SETTINGS, start);
+        let KeepAliveAction::Send(id) = keep_alive.poll(start) else {
+            panic!("the first poll sends a keep alive");
+        };
+        assert_eq!(keep_alive.poll(start + Duration::from_secs(1)), KeepAliveAction::Wait);
+        keep_alive.receive(id, start + Duration::from_millis(100)).unwrap();
+        assert_eq!(keep_alive.latency(), Duration::from_millis(100));
+        assert_eq!(keep_alive.poll(start + Duration::from_secs(14)), KeepAliveAction::Wait);
+
+        let later = start + Duration::from_secs(15);
+        let KeepAliveAction::Send(id) = keep_alive.poll(later) else {
+            panic!("the interval passed");
+        };
+        keep_alive.receive(id, later + Duration::from_millis(500)).unwrap();
+        assert_eq!(keep_alive.latency(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn times_out_and_rejects_unknown_ids() {
+        let start = Instant::now();
+        let mut keep_alive = KeepAlive::new(SETTINGS, start);
+        let KeepAliveAction::Send(id) = keep_alive.poll(start) else {
+            panic!("the first poll sends a keep alive");
+        };
+        assert!(keep_alive.receive(id.wrapping_add(1), start).is_err());
+        assert_eq!(keep_alive.poll(start + Duration::from_secs(29)), KeepAliveAction::Wait);
+        assert_eq!(keep_alive.poll(start + Duration::from_secs(30)), KeepAliveAction::TimedOut);
+        // Answering twice isn't allowed either
+        keep_alive.receive(id, start).unwrap();
+        assert!(keep_alive.receive(id, start).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! The server list ping of clients before 1.7, which predates packet frames.
+//! It starts with 0xFE, a length no modern client sends first, and is answered with a single
+//! 0xFF kick packet holding the status as a UTF-16 string.
+
+use super::status::StatusResponse;
+
+/// The first byte of every legacy ping
+pub const LEGACY_PING: u8 = 0xFE;
+const KICK: u8 = 0xFF;
+/// 0xFE 0x01 0xFA followed by the plugin channel
+const PING_HOST_CHANNEL: &str = "MC|PingHost";
+/// Legacy clients compare this with their own protocol, it's higher than any of them
+const LEGACY_PROTOCOL: i32 = 127;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegacyPing {
+    /// Beta 1.8 to 1.3: just 0xFE
+    Beta,
+    /// 1.4 and 1.5: 0xFE 0x01
+    V1_4,
+    /// 1.6: 0xFE 0x01 and a MC|PingHost plugin message with the address the player typed in
+    V1_6 {
+        protocol: u8,
+        host: String,
+        port: u16,
+    },
+}
+
+/// Parses the bytes a connection started with.
+/// While more bytes could still arrive (`complete` is false) only a full 1.6 ping is returned,
+/// since the shorter formats are prefixes of it. Once nothing else comes the shorter formats count too.
+pub fn parse(data: &[u8], complete: bool) -> Option<LegacyPing> {
+    match data {
+        [LEGACY_PING] if complete => Some(LegacyPing::Beta),
+        [LEGACY_PING, 0x01, 0xFA, rest @ ..] => match parse_ping_host(rest) {
+            Some(ping) => Some(ping),
+            None => complete.then_some(LegacyPing::V1_4),
+        },
+        [LEGACY_PING, 0x01, ..] if complete => Some(LegacyPing::V1_4),
+        _ => None,
+    }
+}
+
+fn parse_ping_host(data: &[u8]) -> Option<LegacyPing> {
+    let mut reader = Reader(data);
+    if reader.string()? != PING_HOST_CHANNEL {
+        return None;
+    }
+    let length = reader.u16()? as usize;
+    let mut payload = Reader(reader.take(length)?);
+    Some(LegacyPing::V1_6{
+        protocol: payload.take(1)?[0],
+        host: payload.string()?,
+        port: payload.i32()? as u16,
+    })
+}
+
+/// Reads the big-endian, UTF-16 types of the old protocol
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        let bytes = self.take(4)?;
+        Some(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Prefixed with its length in UTF-16 code units
+    fn string(&mut self) -> Option<String> {
+        let len = self.u16()? as usize;
+        let units: Vec<u16> = self.take(len * 2)?
+            .chunks_exact(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .collect();
+        String::from_utf16(&units).ok()
+    }
+}
+
+/// The kick packet answering `ping` with the same data as the modern status response
+pub fn response(ping: &LegacyPing, status: &StatusResponse) -> Vec<u8> {
+    let motd = legacy_text(&status.description);
+    let text = match ping {
+        // The fields are separated by §, so it can't be used for colors
+        LegacyPing::Beta => format!("{}§{}§{}", motd.replace('§', ""), status.players.online, status.players.max),
+        LegacyPing::V1_4 | LegacyPing::V1_6{..} => format!(
+            "§1\0{}\0{}\0{}\0{}\0{}",
+            LEGACY_PROTOCOL, status.version.name, motd, status.players.online, status.players.max,
+        ),
+    };
+    let units: Vec<u16> = text.encode_utf16().collect();
+    let mut data = vec![KICK];
+    data.extend((units.len() as u16).to_be_bytes());
+    data.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+    data
+}
+
+/// The text of a chat component without any formatting
+fn legacy_text(component: &serde_json::Value) -> String {
+    match component {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => parts.iter().map(legacy_text).collect(),
+        serde_json::Value::Object(fields) => {
+            let mut text = fields.get("text").map(legacy_text).unwrap_or_default();
+            if let Some(extra) = fields.get("extra") {
+                text.push_str(&legacy_text(extra));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::status::{StatusPlayers, StatusVersion};
+
+    fn utf16(text: &str) -> Vec<u8> {
+        let units: Vec<u16> = text.encode_utf16().collect();
+        let mut data = (units.len() as u16).to_be_bytes().to_vec();
+        data.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+        data
+    }
+
+    fn status() -> StatusResponse {
+        StatusResponse{
+            version: StatusVersion{name: "1.8.9".to_owned(), protocol: 47},
+            players: StatusPlayers{max: 20, online: 3, sample: Vec::new()},
+            description: serde_json::json!({"text": "A §aMinecraft", "extra": [{"text": " Server"}]}),
+            favicon: None,
+            enforces_secure_chat: false,
+        }
+    }
+
+    #[test]
+    fn detects_every_legacy_format() {
+        assert_eq!(parse(&[0xFE], false), None);
+        assert_eq!(parse(&[0xFE], true), Some(LegacyPing::Beta));
+        assert_eq!(parse(&[0xFE, 0x01], false), None);
+        assert_eq!(parse(&[0xFE, 0x01], true), Some(LegacyPing::V1_4));
+
+        let mut host = vec![78];
+        host.extend(utf16("localhost"));
+        host.extend(25565i32.to_be_bytes());
+        let mut ping = vec![0xFE, 0x01, 0xFA];
+        ping.extend(utf16("MC|PingHost"));
+        ping.extend((host.len() as u16).to_be_bytes());
+        ping.extend(&host);
+        let expected = LegacyPing::V1_6{protocol: 78, host: "localhost".to_owned(), port: 25565};
+        assert_eq!(parse(&ping, false), Some(expected));
+        assert_eq!(parse(&ping[..ping.len() - 1], false), None);
+        assert_eq!(parse(&ping[..ping.len() - 1], true), Some(LegacyPing::V1_4));
+
+        // A modern handshake
+        assert_eq!(parse(&[0x10, 0x00, 0x2F], true), None);
+    }
+
+    #[test]
+    fn answers_with_the_status() {
+        let beta = response(&LegacyPing::Beta, &status());
+        assert_eq!(beta, [vec![0xFF], utf16("A aMinecraft Server§3§20")].concat());
+        let modern = response(&LegacyPing::V1_4, &status());
+        assert_eq!(modern, [vec![0xFF], utf16(&["§1", "127", "1.8.9", "A §aMinecraft Server", "3", "20"].join("\0"))].concat());
+    }
+}
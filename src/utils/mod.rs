@@ -1,4 +1,19 @@
 pub mod packets;
 pub mod other;
-pub mod stream_reader;
-pub mod smpmap;
\ No newline at end of file
+pub mod smpmap;
+pub mod world;
+pub mod nbt;
+pub mod anvil;
+pub mod compression;
+pub mod encryption;
+pub mod session;
+pub mod connection;
+pub mod codec;
+pub mod error;
+pub mod types;
+pub mod registry;
+pub mod configuration;
+pub mod status;
+pub mod legacy_ping;
+pub mod keep_alive;
+pub mod properties;
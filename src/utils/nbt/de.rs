@@ -0,0 +1,237 @@
+//! Reads `Deserialize` types from tags, the counterpart of the mapping in `ser`.
+//! Numbers convert between widths as long as the value fits and any array reads as a sequence.
+
+use serde::de::value::{MapDeserializer, SeqDeserializer, StringDeserializer};
+use std::fmt;
+
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+use super::{Compound, NbtError, Tag, BYTE_ARRAY, INT_ARRAY, LONG_ARRAY, TAG};
+
+pub fn from_tag<T: DeserializeOwned>(tag: Tag) -> Result<T, NbtError> {
+    T::deserialize(TagDeserializer(tag))
+}
+
+pub struct TagDeserializer(Tag);
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(TAG, TagVisitor)
+    }
+}
+
+/// Builds tags from any self-describing format, arrays only come back from a [`TagDeserializer`]
+struct TagVisitor;
+
+impl<'de> Visitor<'de> for TagVisitor {
+    type Value = Tag;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an NBT tag")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Tag, E> {
+        Ok(Tag::Byte(value as i8))
+    }
+
+    fn visit_i8<E: de::Error>(self, value: i8) -> Result<Tag, E> {
+        Ok(Tag::Byte(value))
+    }
+
+    fn visit_i16<E: de::Error>(self, value: i16) -> Result<Tag, E> {
+        Ok(Tag::Short(value))
+    }
+
+    fn visit_i32<E: de::Error>(self, value: i32) -> Result<Tag, E> {
+        Ok(Tag::Int(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Tag, E> {
+        Ok(Tag::Long(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Tag, E> {
+        Ok(Tag::Long(value as i64))
+    }
+
+    fn visit_f32<E: de::Error>(self, value: f32) -> Result<Tag, E> {
+        Ok(Tag::Float(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Tag, E> {
+        Ok(Tag::Double(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Tag, E> {
+        Ok(Tag::String(value.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Tag, E> {
+        Ok(Tag::String(value))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Tag, E> {
+        Ok(Tag::ByteArray(value.to_vec()))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Tag, D::Error> {
+        Tag::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Tag, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Tag, A::Error> {
+        let mut elements: Vec<Tag> = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element::<Tag>()? {
+            if elements.first().is_some_and(|first| first.id() != element.id()) {
+                return Err(de::Error::custom("list elements of different types"));
+            }
+            elements.push(element);
+        }
+        Ok(Tag::List(elements))
+    }
+
+    /// A map with one of the array names as its only key is how [`TagDeserializer`] hands over arrays
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Tag, A::Error> {
+        let mut compound = Compound::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                BYTE_ARRAY if compound.is_empty() => return Ok(Tag::ByteArray(map.next_value()?)),
+                INT_ARRAY if compound.is_empty() => return Ok(Tag::IntArray(map.next_value()?)),
+                LONG_ARRAY if compound.is_empty() => return Ok(Tag::LongArray(map.next_value()?)),
+                _ => compound.insert(key, map.next_value()?),
+            };
+        }
+        Ok(Tag::Compound(compound))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, NbtError> for Tag {
+    type Deserializer = TagDeserializer;
+
+    fn into_deserializer(self) -> TagDeserializer {
+        TagDeserializer(self)
+    }
+}
+
+/// Unsigned types are written with the bits of the signed tag of the same size, so they read back the same way
+macro_rules! deserialize_unsigned {
+    ($($method:ident: $tag:ident as $ty:ty => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+                match self.0 {
+                    Tag::$tag(value) => visitor.$visit(value as $ty),
+                    _ => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for TagDeserializer {
+    type Error = NbtError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_i8(value),
+            Tag::Short(value) => visitor.visit_i16(value),
+            Tag::Int(value) => visitor.visit_i32(value),
+            Tag::Long(value) => visitor.visit_i64(value),
+            Tag::Float(value) => visitor.visit_f32(value),
+            Tag::Double(value) => visitor.visit_f64(value),
+            Tag::ByteArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter().map(|value| Tag::Byte(value as i8)))),
+            Tag::String(value) => visitor.visit_string(value),
+            Tag::List(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Tag::Compound(compound) => {
+                visitor.visit_map(MapDeserializer::new(compound.into_iter().map(|(key, value)| (StringDeserializer::new(key), value))))
+            }
+            Tag::IntArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter().map(Tag::Int))),
+            Tag::LongArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter().map(Tag::Long))),
+        }
+    }
+
+    /// Vanilla stores booleans as bytes
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_bool(value != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_unsigned!(
+        deserialize_u8: Byte as u8 => visit_u8,
+        deserialize_u16: Short as u16 => visit_u16,
+        deserialize_u32: Int as u32 => visit_u32,
+        deserialize_u64: Long as u64 => visit_u64
+    );
+
+    /// Fields that are left out are `None`, so a tag that is there is always `Some`
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, NbtError> {
+        let token = match &self.0 {
+            Tag::ByteArray(_) if name == TAG => BYTE_ARRAY,
+            Tag::IntArray(_) if name == TAG => INT_ARRAY,
+            Tag::LongArray(_) if name == TAG => LONG_ARRAY,
+            _ => return visitor.visit_newtype_struct(self),
+        };
+        let array = MapDeserializer::new(std::iter::once((StringDeserializer::new(token.to_owned()), self.0)));
+        visitor.visit_map(array)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _: &'static str, _: &'static [&'static str], visitor: V) -> Result<V::Value, NbtError> {
+        match self.0 {
+            Tag::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Tag::Compound(compound) if compound.len() == 1 => {
+                let (variant, value) = compound.into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer{variant, value})
+            }
+            _ => Err(NbtError::Invalid("enums are a string or a compound with a single entry".to_owned())),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Tag,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = NbtError;
+    type Variant = TagDeserializer;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, TagDeserializer), NbtError> {
+        let variant = seed.deserialize(StringDeserializer::<NbtError>::new(self.variant))?;
+        Ok((variant, TagDeserializer(self.value)))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for TagDeserializer {
+    type Error = NbtError;
+
+    fn unit_variant(self) -> Result<(), NbtError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, NbtError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, NbtError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _: &'static [&'static str], visitor: V) -> Result<V::Value, NbtError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
@@ -0,0 +1,608 @@
+//! Named Binary Tag, the format of region files, `level.dat` and player data.
+//! Everything is big-endian and strings are Java's modified UTF-8.
+//!
+//! Besides the [`Tag`] tree, Rust types can be mapped with serde through [`to_tag`] and [`from_tag`],
+//! and tags print as SNBT, the text format of commands, which [`parse_snbt`] reads back.
+
+mod de;
+mod ser;
+mod snbt;
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::{fmt, io};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub use de::from_tag;
+pub use ser::to_tag;
+pub use snbt::parse_snbt;
+
+/// Tags nested deeper than this are rejected instead of overflowing the stack, like vanilla
+const MAX_DEPTH: usize = 512;
+
+/// Tag names to tags, sorted so the same compound is always written the same way
+pub type Compound = BTreeMap<String, Tag>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// Signed in Java, kept unsigned here since it mostly holds block ids and nibbles
+    ByteArray(Vec<u8>),
+    String(String),
+    /// Every element has the same type, an empty list is written as a list of TAG_End
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub const END: u8 = 0;
+
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Tag::Float(value) => Some(value as f64),
+            Tag::Double(value) => Some(value),
+            _ => self.as_i64().map(|value| value as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Tag::ByteArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_ints(&self) -> Option<&[i32]> {
+        match self {
+            Tag::IntArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NbtError {
+    Io(io::Error),
+    /// The data ended in the middle of a tag
+    UnexpectedEnd,
+    UnknownTag(u8),
+    TooDeep,
+    /// Valid NBT, just not what was expected, e.g. a missing or mistyped field
+    Invalid(String),
+}
+
+impl fmt::Display for NbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NbtError::Io(err) => write!(f, "{err}"),
+            NbtError::UnexpectedEnd => write!(f, "NBT ended unexpectedly"),
+            NbtError::UnknownTag(tag) => write!(f, "Unknown NBT tag {tag}"),
+            NbtError::TooDeep => write!(f, "NBT is nested too deep"),
+            NbtError::Invalid(msg) => write!(f, "Invalid NBT: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for NbtError {}
+
+impl From<io::Error> for NbtError {
+    fn from(err: io::Error) -> Self {
+        NbtError::Io(err)
+    }
+}
+
+impl serde::ser::Error for NbtError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        NbtError::Invalid(msg.to_string())
+    }
+}
+
+impl serde::de::Error for NbtError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        NbtError::Invalid(msg.to_string())
+    }
+}
+
+/// How NBT files are compressed, `level.dat` and player data use gzip, region files zlib
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zlib,
+}
+
+impl Compression {
+    /// Tells from the first bytes, anything that isn't gzip or zlib is taken as uncompressed
+    pub fn detect(data: &[u8]) -> Compression {
+        match data {
+            [0x1F, 0x8B, ..] => Compression::Gzip,
+            // The zlib header is a multiple of 31 and deflate has method 8
+            [first, second, ..] if first & 0x0F == 8 && u16::from_be_bytes([*first, *second]) % 31 == 0 => Compression::Zlib,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Reads the root tag with its name, decompressing it first if it is compressed
+pub fn read_compressed(data: &[u8]) -> Result<(String, Tag), NbtError> {
+    let mut inflated = Vec::new();
+    match Compression::detect(data) {
+        Compression::None => return read_named(data),
+        Compression::Gzip => GzDecoder::new(data).read_to_end(&mut inflated)?,
+        Compression::Zlib => ZlibDecoder::new(data).read_to_end(&mut inflated)?,
+    };
+    read_named(&inflated)
+}
+
+pub fn write_compressed(name: &str, tag: &Tag, compression: Compression) -> Result<Vec<u8>, NbtError> {
+    let data = write_named(name, tag);
+    Ok(match compression {
+        Compression::None => data,
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&data)?;
+            encoder.finish()?
+        }
+        Compression::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&data)?;
+            encoder.finish()?
+        }
+    })
+}
+
+/// Reads a file like `level.dat`, whatever its compression
+pub fn read_file(path: &Path) -> Result<(String, Tag), NbtError> {
+    read_compressed(&std::fs::read(path)?)
+}
+
+pub fn write_file(path: &Path, name: &str, tag: &Tag, compression: Compression) -> Result<(), NbtError> {
+    Ok(std::fs::write(path, write_compressed(name, tag, compression)?)?)
+}
+
+/// Reads a value from the compressed or uncompressed bytes of a named root, ignoring the name
+pub fn from_bytes<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T, NbtError> {
+    from_tag(read_compressed(data)?.1)
+}
+
+pub fn to_bytes<T: Serialize + ?Sized>(name: &str, value: &T, compression: Compression) -> Result<Vec<u8>, NbtError> {
+    write_compressed(name, &to_tag(value)?, compression)
+}
+
+/// serde has no notion of NBT's array tags, so these names mark newtypes that become them
+const BYTE_ARRAY: &str = "__nbt_byte_array";
+const INT_ARRAY: &str = "__nbt_int_array";
+const LONG_ARRAY: &str = "__nbt_long_array";
+/// Asks [`de::TagDeserializer`] for the tag as it is, with arrays kept apart from lists
+const TAG: &str = "__nbt_tag";
+
+macro_rules! array_type {
+    ($name:ident, $element:ty, $token:expr, $doc:literal) => {
+        #[doc = $doc]
+        /// Any list of numbers is accepted when reading.
+        #[derive(Debug, Clone, Default, PartialEq, Eq)]
+        pub struct $name(pub Vec<$element>);
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_newtype_struct($token, &self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Vec::<$element>::deserialize(deserializer).map($name)
+            }
+        }
+    };
+}
+
+array_type!(ByteArray, u8, BYTE_ARRAY, "Serialized as TAG_Byte_Array instead of a list of bytes.");
+array_type!(IntArray, i32, INT_ARRAY, "Serialized as TAG_Int_Array instead of a list of ints.");
+array_type!(LongArray, i64, LONG_ARRAY, "Serialized as TAG_Long_Array instead of a list of longs.");
+
+/// Reads the root tag with its name, uncompressed
+pub fn read_named(data: &[u8]) -> Result<(String, Tag), NbtError> {
+    let mut reader = Reader{data, position: 0};
+    let id = reader.byte()?;
+    if id == Tag::END {
+        return Err(NbtError::Invalid("the root is TAG_End".to_owned()));
+    }
+    let name = reader.string()?;
+    let tag = reader.payload(id, 0)?;
+    Ok((name, tag))
+}
+
+/// Writes `tag` as the root with the given name, uncompressed
+pub fn write_named(name: &str, tag: &Tag) -> Vec<u8> {
+    let mut data = vec![tag.id()];
+    write_string(&mut data, name);
+    write_payload(&mut data, tag);
+    data
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], NbtError> {
+        if self.data.len() - self.position < len {
+            return Err(NbtError::UnexpectedEnd);
+        }
+        self.position += len;
+        Ok(&self.data[self.position - len..self.position])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], NbtError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn byte(&mut self) -> Result<u8, NbtError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn length(&mut self) -> Result<usize, NbtError> {
+        let len = i32::from_be_bytes(self.array()?);
+        usize::try_from(len).map_err(|_| NbtError::Invalid(format!("negative length {len}")))
+    }
+
+    fn string(&mut self) -> Result<String, NbtError> {
+        let len = u16::from_be_bytes(self.array()?);
+        Ok(decode_modified_utf8(self.take(len as usize)?))
+    }
+
+    /// Reads a length and that many elements of `N` bytes, checking the length before allocating
+    fn elements<T, const N: usize>(&mut self, convert: fn([u8; N]) -> T) -> Result<Vec<T>, NbtError> {
+        let len = self.length()?;
+        let bytes = self.take(len.checked_mul(N).ok_or(NbtError::UnexpectedEnd)?)?;
+        Ok(bytes.chunks_exact(N).map(|chunk| convert(chunk.try_into().unwrap())).collect())
+    }
+
+    fn payload(&mut self, id: u8, depth: usize) -> Result<Tag, NbtError> {
+        if depth > MAX_DEPTH {
+            return Err(NbtError::TooDeep);
+        }
+        Ok(match id {
+            1 => Tag::Byte(self.byte()? as i8),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => Tag::ByteArray(self.elements(|[byte]: [u8; 1]| byte)?),
+            8 => Tag::String(self.string()?),
+            9 => {
+                let element = self.byte()?;
+                let len = self.length()?;
+                if element == Tag::END && len > 0 {
+                    return Err(NbtError::Invalid("list of TAG_End".to_owned()));
+                }
+                // Every element takes at least a byte
+                let mut elements = Vec::with_capacity(len.min(self.data.len() - self.position));
+                for _ in 0..len {
+                    elements.push(self.payload(element, depth + 1)?);
+                }
+                Tag::List(elements)
+            }
+            10 => {
+                let mut compound = Compound::new();
+                loop {
+                    let id = self.byte()?;
+                    if id == Tag::END {
+                        break;
+                    }
+                    let name = self.string()?;
+                    compound.insert(name, self.payload(id, depth + 1)?);
+                }
+                Tag::Compound(compound)
+            }
+            11 => Tag::IntArray(self.elements(i32::from_be_bytes)?),
+            12 => Tag::LongArray(self.elements(i64::from_be_bytes)?),
+            id => return Err(NbtError::UnknownTag(id)),
+        })
+    }
+}
+
+fn write_string(data: &mut Vec<u8>, text: &str) {
+    let mut bytes = encode_modified_utf8(text);
+    bytes.truncate(u16::MAX as usize);
+    data.extend((bytes.len() as u16).to_be_bytes());
+    data.extend(bytes);
+}
+
+fn write_payload(data: &mut Vec<u8>, tag: &Tag) {
+    match tag {
+        Tag::Byte(value) => data.push(*value as u8),
+        Tag::Short(value) => data.extend(value.to_be_bytes()),
+        Tag::Int(value) => data.extend(value.to_be_bytes()),
+        Tag::Long(value) => data.extend(value.to_be_bytes()),
+        Tag::Float(value) => data.extend(value.to_be_bytes()),
+        Tag::Double(value) => data.extend(value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            data.extend((values.len() as i32).to_be_bytes());
+            data.extend(values);
+        }
+        Tag::String(value) => write_string(data, value),
+        Tag::List(elements) => {
+            data.push(elements.first().map_or(Tag::END, Tag::id));
+            data.extend((elements.len() as i32).to_be_bytes());
+            for element in elements {
+                write_payload(data, element);
+            }
+        }
+        Tag::Compound(compound) => {
+            for (name, tag) in compound {
+                data.push(tag.id());
+                write_string(data, name);
+                write_payload(data, tag);
+            }
+            data.push(Tag::END);
+        }
+        Tag::IntArray(values) => {
+            data.extend((values.len() as i32).to_be_bytes());
+            values.iter().for_each(|value| data.extend(value.to_be_bytes()));
+        }
+        Tag::LongArray(values) => {
+            data.extend((values.len() as i32).to_be_bytes());
+            values.iter().for_each(|value| data.extend(value.to_be_bytes()));
+        }
+    }
+}
+
+/// Java writes NUL as two bytes and characters outside the BMP as two 3 byte surrogates
+fn encode_modified_utf8(text: &str) -> Vec<u8> {
+    if !text.bytes().any(|byte| byte == 0 || byte >= 0xF0) {
+        return text.as_bytes().to_vec();
+    }
+    let mut bytes = Vec::with_capacity(text.len() + 4);
+    for unit in text.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => bytes.extend([0xC0 | (unit >> 6) as u8, 0x80 | (unit & 0x3F) as u8]),
+            _ => bytes.extend([0xE0 | (unit >> 12) as u8, 0x80 | (unit >> 6 & 0x3F) as u8, 0x80 | (unit & 0x3F) as u8]),
+        }
+    }
+    bytes
+}
+
+/// Invalid sequences become U+FFFD instead of failing, like Java's lenient readers
+fn decode_modified_utf8(bytes: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        if !text.bytes().any(|byte| byte == 0xED || byte == 0xC0) {
+            return text.to_owned();
+        }
+    }
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i] as u16;
+        let continuation = |offset: usize| bytes.get(i + offset).map(|&byte| byte as u16 & 0x3F);
+        let (unit, len) = match byte {
+            0x00..=0x7F => (byte, 1),
+            0xC0..=0xDF => match continuation(1) {
+                Some(second) => ((byte & 0x1F) << 6 | second, 2),
+                None => (0xFFFD, 1),
+            },
+            0xE0..=0xEF => match (continuation(1), continuation(2)) {
+                (Some(second), Some(third)) => ((byte & 0x0F) << 12 | second << 6 | third, 3),
+                _ => (0xFFFD, 1),
+            },
+            _ => (0xFFFD, 1),
+        };
+        units.push(unit);
+        i += len;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_hello_world_example() {
+        // test.nbt from the original NBT specification
+        let data = b"\x0a\x00\x0bhello world\x08\x00\x04name\x00\x09Bananrama\x00";
+        let (name, tag) = read_named(data).unwrap();
+        assert_eq!(name, "hello world");
+        let compound = tag.as_compound().unwrap();
+        assert_eq!(compound["name"].as_str(), Some("Bananrama"));
+        assert_eq!(write_named(&name, &tag), data);
+    }
+
+    #[test]
+    fn every_tag_round_trips() {
+        let compound = Compound::from([
+            ("byte".to_owned(), Tag::Byte(-1)),
+            ("short".to_owned(), Tag::Short(300)),
+            ("int".to_owned(), Tag::Int(-70000)),
+            ("long".to_owned(), Tag::Long(1 << 40)),
+            ("float".to_owned(), Tag::Float(0.5)),
+            ("double".to_owned(), Tag::Double(-2.25)),
+            ("bytes".to_owned(), Tag::ByteArray(vec![0, 255])),
+            ("string".to_owned(), Tag::String("nul\0 and \u{1F600}".to_owned())),
+            ("list".to_owned(), Tag::List(vec![Tag::Short(1), Tag::Short(2)])),
+            ("empty".to_owned(), Tag::List(Vec::new())),
+            ("nested".to_owned(), Tag::Compound(Compound::from([("a".to_owned(), Tag::Int(1))]))),
+            ("ints".to_owned(), Tag::IntArray(vec![1, -1])),
+            ("longs".to_owned(), Tag::LongArray(vec![i64::MIN])),
+        ]);
+        let data = write_named("root", &Tag::Compound(compound.clone()));
+        assert_eq!(read_named(&data).unwrap(), ("root".to_owned(), Tag::Compound(compound)));
+    }
+
+    #[test]
+    fn strings_are_modified_utf8() {
+        assert_eq!(encode_modified_utf8("a\0b"), [b'a', 0xC0, 0x80, b'b']);
+        assert_eq!(encode_modified_utf8("\u{1F600}"), [0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]);
+        assert_eq!(decode_modified_utf8(&[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]), "\u{1F600}");
+        assert_eq!(decode_modified_utf8("h\u{e9}llo".as_bytes()), "h\u{e9}llo");
+    }
+
+    #[test]
+    fn broken_data_is_an_error() {
+        assert!(matches!(read_named(b"\x0a\x00\x00\x03\x00\x01a\x00"), Err(NbtError::UnexpectedEnd)));
+        assert!(matches!(read_named(b"\x0d\x00\x00"), Err(NbtError::UnknownTag(13))));
+        assert!(matches!(read_named(b"\x07\x00\x00\x7f\xff\xff\xff"), Err(NbtError::UnexpectedEnd)));
+        // Lists of a single list each
+        let mut nested = vec![0x09, 0x00, 0x00];
+        nested.extend(std::iter::repeat_n([0x09, 0x00, 0x00, 0x00, 0x01], 600).flatten());
+        assert!(matches!(read_named(&nested), Err(NbtError::TooDeep)));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Item {
+        id: String,
+        count: u8,
+        damage: i16,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tag: Option<Compound>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum GameType {
+        Survival,
+        Spectating{target: i64},
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Player {
+        name: String,
+        on_ground: bool,
+        pos: Vec<f64>,
+        inventory: Vec<Item>,
+        sections: ByteArray,
+        height_map: IntArray,
+        mode: GameType,
+        last_mode: Option<GameType>,
+    }
+
+    #[test]
+    fn structs_map_to_compounds() {
+        let player = Player {
+            name: "Notch".to_owned(),
+            on_ground: true,
+            pos: vec![0.5, 64.0, -0.5],
+            inventory: vec![Item{id: "minecraft:stone".to_owned(), count: 200, damage: 0, tag: None}],
+            sections: ByteArray(vec![1, 255]),
+            height_map: IntArray(vec![64; 4]),
+            mode: GameType::Spectating{target: 7},
+            last_mode: None,
+        };
+        let tag = to_tag(&player).unwrap();
+        let compound = tag.as_compound().unwrap();
+        assert_eq!(compound["on_ground"], Tag::Byte(1));
+        assert_eq!(compound["inventory"].as_list().unwrap()[0].as_compound().unwrap()["Count"], Tag::Byte(-56));
+        assert!(!compound["inventory"].as_list().unwrap()[0].as_compound().unwrap().contains_key("Tag"));
+        assert_eq!(compound["sections"], Tag::ByteArray(vec![1, 255]));
+        assert_eq!(compound["height_map"], Tag::IntArray(vec![64; 4]));
+        assert_eq!(compound["mode"].to_string(), "{Spectating:{target:7L}}");
+        assert!(!compound.contains_key("last_mode"));
+        assert_eq!(from_tag::<Player>(tag).unwrap(), player);
+
+        let raw = Tag::Compound(Compound::from([("ints".to_owned(), Tag::IntArray(vec![1])), ("list".to_owned(), Tag::List(vec![Tag::Byte(1)]))]));
+        let item = Item{id: "minecraft:map".to_owned(), count: 1, damage: 3, tag: raw.as_compound().cloned()};
+        assert_eq!(from_tag::<Item>(to_tag(&item).unwrap()).unwrap(), item);
+
+        assert_eq!(to_tag(&GameType::Survival).unwrap(), Tag::String("Survival".to_owned()));
+        assert!(to_tag(&(1, "two")).is_err());
+        assert!(from_tag::<Item>(Tag::Compound(Compound::new())).is_err());
+    }
+
+    #[test]
+    fn compression_is_detected() {
+        let tag = Tag::Compound(Compound::from([("Data".to_owned(), Tag::Long(-1))]));
+        for compression in [Compression::None, Compression::Gzip, Compression::Zlib] {
+            let data = write_compressed("", &tag, compression).unwrap();
+            assert_eq!(Compression::detect(&data), compression);
+            assert_eq!(read_compressed(&data).unwrap(), (String::new(), tag.clone()));
+        }
+        let bytes = to_bytes("", &Compound::from([("Data".to_owned(), Tag::Long(-1))]), Compression::Gzip).unwrap();
+        assert_eq!(from_bytes::<BTreeMap<String, i64>>(&bytes).unwrap()["Data"], -1);
+    }
+
+    #[test]
+    fn snbt_round_trips() {
+        let text = r#"{"": [B;1b,-1b], Count:3b, Damage:-2s, Pos:[0.5d,64d], id:"minecraft:stone",
+            display:{Name:'say "hi"',Lore:[]}, ints:[I;1,2], longs:[L;5L], f:1.5f, big:1L, on:true, plain:abc}"#;
+        let tag = parse_snbt(text).unwrap();
+        let compound = tag.as_compound().unwrap();
+        assert_eq!(compound[""], Tag::ByteArray(vec![1, 255]));
+        assert_eq!(compound["Damage"], Tag::Short(-2));
+        assert_eq!(compound["display"].as_compound().unwrap()["Name"].as_str(), Some("say \"hi\""));
+        assert_eq!(compound["on"], Tag::Byte(1));
+        assert_eq!(compound["plain"].as_str(), Some("abc"));
+        assert_eq!(parse_snbt(&tag.to_string()).unwrap(), tag);
+        assert_eq!(
+            Tag::Compound(Compound::from([("a b".to_owned(), Tag::List(vec![Tag::Int(1), Tag::Int(2)]))])).to_string(),
+            r#"{"a b":[1,2]}"#,
+        );
+
+        assert!(parse_snbt("[1,2b]").is_err());
+        assert!(parse_snbt("{a:1").is_err());
+        assert!(parse_snbt("{a:1} b").is_err());
+        assert!(matches!(parse_snbt(&"[".repeat(600)), Err(NbtError::TooDeep)));
+    }
+}
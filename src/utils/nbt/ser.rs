@@ -0,0 +1,430 @@
+//! Maps `Serialize` types to tags: structs and maps become compounds, sequences lists
+//! and enums are externally tagged. `None` fields are left out like vanilla does.
+
+use serde::ser::{self, Impossible, Serialize, Serializer};
+
+use super::{Compound, NbtError, Tag, BYTE_ARRAY, INT_ARRAY, LONG_ARRAY};
+
+pub fn to_tag<T: Serialize + ?Sized>(value: &T) -> Result<Tag, NbtError> {
+    value.serialize(TagSerializer)?.ok_or_else(|| NbtError::Invalid("nothing to serialize".to_owned()))
+}
+
+/// Arrays go through the same newtypes as [`super::ByteArray`] and the like to stay arrays
+impl Serialize for Tag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Tag::Byte(value) => serializer.serialize_i8(*value),
+            Tag::Short(value) => serializer.serialize_i16(*value),
+            Tag::Int(value) => serializer.serialize_i32(*value),
+            Tag::Long(value) => serializer.serialize_i64(*value),
+            Tag::Float(value) => serializer.serialize_f32(*value),
+            Tag::Double(value) => serializer.serialize_f64(*value),
+            Tag::ByteArray(values) => serializer.serialize_newtype_struct(BYTE_ARRAY, values),
+            Tag::String(value) => serializer.serialize_str(value),
+            Tag::List(elements) => serializer.collect_seq(elements),
+            Tag::Compound(compound) => serializer.collect_map(compound),
+            Tag::IntArray(values) => serializer.serialize_newtype_struct(INT_ARRAY, values),
+            Tag::LongArray(values) => serializer.serialize_newtype_struct(LONG_ARRAY, values),
+        }
+    }
+}
+
+/// Produces `None` for values that have no tag, like `None` and `()`
+struct TagSerializer;
+
+fn list(elements: Vec<Tag>) -> Result<Tag, NbtError> {
+    if let Some(first) = elements.first() {
+        if elements.iter().any(|element| element.id() != first.id()) {
+            return Err(NbtError::Invalid("list elements of different types".to_owned()));
+        }
+    }
+    Ok(Tag::List(elements))
+}
+
+fn element<T: Serialize + ?Sized>(value: &T) -> Result<Tag, NbtError> {
+    value.serialize(TagSerializer)?.ok_or_else(|| NbtError::Invalid("list elements can't be left out".to_owned()))
+}
+
+/// Turns the list of an [`super::IntArray`] and the like into the array tag
+fn array(token: &str, tag: Tag) -> Result<Tag, NbtError> {
+    let Tag::List(elements) = tag else {
+        return Err(NbtError::Invalid(format!("{token} isn't a sequence")));
+    };
+    let numbers = elements.iter().map(|element| element.as_i64().ok_or_else(|| NbtError::Invalid("array of non integers".to_owned())));
+    Ok(match token {
+        BYTE_ARRAY => Tag::ByteArray(numbers.map(|number| number.map(|number| number as u8)).collect::<Result<_, _>>()?),
+        INT_ARRAY => Tag::IntArray(numbers.map(|number| number.map(|number| number as i32)).collect::<Result<_, _>>()?),
+        _ => Tag::LongArray(numbers.collect::<Result<_, _>>()?),
+    })
+}
+
+impl Serializer for TagSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = VariantSerializer<ListSerializer>;
+    type SerializeMap = CompoundSerializer;
+    type SerializeStruct = CompoundSerializer;
+    type SerializeStructVariant = VariantSerializer<CompoundSerializer>;
+
+    fn serialize_bool(self, value: bool) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Byte(value as i8)))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Byte(value)))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Short(value)))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Int(value)))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Long(value)))
+    }
+
+    // NBT has no unsigned types, they keep their bits in the signed tag of the same size
+    fn serialize_u8(self, value: u8) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Byte(value as i8)))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Short(value as i16)))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Int(value as i32)))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Long(value as i64)))
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Float(value)))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Double(value)))
+    }
+
+    fn serialize_char(self, value: char) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::String(value.to_string())))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::String(value.to_owned())))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::ByteArray(value.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, NbtError> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, NbtError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, NbtError> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Compound(Compound::new())))
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::String(variant.to_owned())))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<Self::Ok, NbtError> {
+        match name {
+            BYTE_ARRAY | INT_ARRAY | LONG_ARRAY => Ok(Some(array(name, element(value)?)?)),
+            _ => value.serialize(self),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, variant: &'static str, value: &T) -> Result<Self::Ok, NbtError> {
+        let mut compound = Compound::new();
+        if let Some(tag) = value.serialize(TagSerializer)? {
+            compound.insert(variant.to_owned(), tag);
+        }
+        Ok(Some(Tag::Compound(compound)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, NbtError> {
+        Ok(ListSerializer{elements: Vec::with_capacity(len.unwrap_or(0))})
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, NbtError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, NbtError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, variant: &'static str, len: usize) -> Result<Self::SerializeTupleVariant, NbtError> {
+        Ok(VariantSerializer{variant, inner: ListSerializer{elements: Vec::with_capacity(len)}})
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, NbtError> {
+        Ok(CompoundSerializer{compound: Compound::new(), key: None})
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<Self::SerializeStruct, NbtError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, variant: &'static str, _: usize) -> Result<Self::SerializeStructVariant, NbtError> {
+        Ok(VariantSerializer{variant, inner: CompoundSerializer{compound: Compound::new(), key: None}})
+    }
+}
+
+struct ListSerializer {
+    elements: Vec<Tag>,
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        self.elements.push(element(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        list(self.elements).map(Some)
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct CompoundSerializer {
+    compound: Compound,
+    /// Set between `serialize_key` and `serialize_value`
+    key: Option<String>,
+}
+
+impl CompoundSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), NbtError> {
+        if let Some(tag) = value.serialize(TagSerializer)? {
+            self.compound.insert(key, tag);
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), NbtError> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        let key = self.key.take().ok_or_else(|| NbtError::Invalid("value without a key".to_owned()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(Some(Tag::Compound(self.compound)))
+    }
+}
+
+impl ser::SerializeStruct for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), NbtError> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Wraps the tag of a tuple or struct variant into a compound with the variant's name
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn wrap(variant: &str, tag: Option<Tag>) -> Option<Tag> {
+        let mut compound = Compound::new();
+        if let Some(tag) = tag {
+            compound.insert(variant.to_owned(), tag);
+        }
+        Some(Tag::Compound(compound))
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<ListSerializer> {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtError> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(Self::wrap(self.variant, ser::SerializeSeq::end(self.inner)?))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<CompoundSerializer> {
+    type Ok = Option<Tag>;
+    type Error = NbtError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), NbtError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, NbtError> {
+        Ok(Self::wrap(self.variant, ser::SerializeStruct::end(self.inner)?))
+    }
+}
+
+/// Compound keys are strings, numbers and chars are written as text
+struct KeySerializer;
+
+macro_rules! key_as_text {
+    ($($method:ident: $ty:ty),*) => {
+        $(
+            fn $method(self, value: $ty) -> Result<String, NbtError> {
+                Ok(value.to_string())
+            }
+        )*
+    };
+}
+
+impl Serializer for KeySerializer {
+    type Ok = String;
+    type Error = NbtError;
+    type SerializeSeq = Impossible<String, NbtError>;
+    type SerializeTuple = Impossible<String, NbtError>;
+    type SerializeTupleStruct = Impossible<String, NbtError>;
+    type SerializeTupleVariant = Impossible<String, NbtError>;
+    type SerializeMap = Impossible<String, NbtError>;
+    type SerializeStruct = Impossible<String, NbtError>;
+    type SerializeStructVariant = Impossible<String, NbtError>;
+
+    key_as_text!(
+        serialize_bool: bool, serialize_i8: i8, serialize_i16: i16, serialize_i32: i32, serialize_i64: i64,
+        serialize_u8: u8, serialize_u16: u16, serialize_u32: u32, serialize_u64: u64, serialize_char: char
+    );
+
+    fn serialize_str(self, value: &str) -> Result<String, NbtError> {
+        Ok(value.to_owned())
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> Result<String, NbtError> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T) -> Result<String, NbtError> {
+        value.serialize(self)
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<String, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<String, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<String, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_none(self) -> Result<String, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<String, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_unit(self) -> Result<String, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<String, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, _: &'static str, _: &T) -> Result<String, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeTupleStruct, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeTupleVariant, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, NbtError> {
+        Err(key_error())
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeStructVariant, NbtError> {
+        Err(key_error())
+    }
+}
+
+fn key_error() -> NbtError {
+    NbtError::Invalid("compound keys have to be strings".to_owned())
+}
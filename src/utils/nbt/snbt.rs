@@ -0,0 +1,266 @@
+//! SNBT, the text form of tags used by commands like `/give`, for logging and tests.
+//! Printing and parsing round trip, except for NaN and infinite floats.
+
+use std::fmt;
+
+use super::{Compound, NbtError, Tag, MAX_DEPTH};
+
+/// Characters that don't need quotes in keys and unquoted strings
+fn is_unquoted(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    let quote = if text.contains('"') && !text.contains('\'') { '\'' } else { '"' };
+    write!(f, "{quote}")?;
+    for c in text.chars() {
+        if c == quote || c == '\\' {
+            write!(f, "\\")?;
+        }
+        write!(f, "{c}")?;
+    }
+    write!(f, "{quote}")
+}
+
+fn write_array<T: fmt::Display>(f: &mut fmt::Formatter<'_>, prefix: char, values: &[T], suffix: &str) -> fmt::Result {
+    write!(f, "[{prefix};")?;
+    for (i, value) in values.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        write!(f, "{separator}{value}{suffix}")?;
+    }
+    write!(f, "]")
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::Byte(value) => write!(f, "{value}b"),
+            Tag::Short(value) => write!(f, "{value}s"),
+            Tag::Int(value) => write!(f, "{value}"),
+            Tag::Long(value) => write!(f, "{value}L"),
+            Tag::Float(value) => write!(f, "{value}f"),
+            Tag::Double(value) => write!(f, "{value}d"),
+            Tag::ByteArray(values) => {
+                let values: Vec<i8> = values.iter().map(|&value| value as i8).collect();
+                write_array(f, 'B', &values, "b")
+            }
+            Tag::String(value) => write_quoted(f, value),
+            Tag::List(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(f, "{separator}{element}")?;
+                }
+                write!(f, "]")
+            }
+            Tag::Compound(compound) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in compound.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    if !key.is_empty() && key.chars().all(is_unquoted) {
+                        write!(f, "{key}")?;
+                    } else {
+                        write_quoted(f, key)?;
+                    }
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+            Tag::IntArray(values) => write_array(f, 'I', values, ""),
+            Tag::LongArray(values) => write_array(f, 'L', values, "L"),
+        }
+    }
+}
+
+/// Parses a single tag, like `{Count:1b,id:"minecraft:stone"}`
+pub fn parse_snbt(text: &str) -> Result<Tag, NbtError> {
+    let mut parser = Parser{text, position: 0};
+    let tag = parser.tag(0)?;
+    parser.skip_whitespace();
+    if parser.position < text.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(tag)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> NbtError {
+        NbtError::Invalid(format!("{msg} at {} in SNBT", self.position))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), NbtError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{c}'")))
+        }
+    }
+
+    /// A quoted or unquoted string, unquoted ones are empty if there is none
+    fn string(&mut self) -> Result<(String, bool), NbtError> {
+        let Some(quote @ ('"' | '\'')) = self.peek() else {
+            let rest = &self.text[self.position..];
+            let len = rest.find(|c| !is_unquoted(c)).unwrap_or(rest.len());
+            self.position += len;
+            return Ok((rest[..len].to_owned(), false));
+        };
+        let mut text = String::new();
+        let mut chars = self.text[self.position + 1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, escaped @ ('\\' | '"' | '\''))) => text.push(escaped),
+                    _ => return Err(self.error("invalid escape")),
+                },
+                c if c == quote => {
+                    self.position += i + 2;
+                    return Ok((text, true));
+                }
+                c => text.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn tag(&mut self, depth: usize) -> Result<Tag, NbtError> {
+        if depth > MAX_DEPTH {
+            return Err(NbtError::TooDeep);
+        }
+        match self.peek() {
+            Some('{') => self.compound(depth),
+            Some('[') => self.list(depth),
+            Some(_) => {
+                let (text, quoted) = self.string()?;
+                if quoted {
+                    Ok(Tag::String(text))
+                } else if text.is_empty() {
+                    Err(self.error("expected a value"))
+                } else {
+                    Ok(unquoted(text))
+                }
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn compound(&mut self, depth: usize) -> Result<Tag, NbtError> {
+        self.expect('{')?;
+        let mut compound = Compound::new();
+        if self.eat('}') {
+            return Ok(Tag::Compound(compound));
+        }
+        loop {
+            let (key, quoted) = self.string()?;
+            if key.is_empty() && !quoted {
+                return Err(self.error("expected a key"));
+            }
+            self.expect(':')?;
+            compound.insert(key, self.tag(depth + 1)?);
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect('}')?;
+        Ok(Tag::Compound(compound))
+    }
+
+    fn list(&mut self, depth: usize) -> Result<Tag, NbtError> {
+        self.expect('[')?;
+        let rest = &self.text[self.position..];
+        if let Some(prefix @ ('B' | 'I' | 'L')) = rest.chars().next() {
+            if rest[1..].trim_start().starts_with(';') {
+                self.position += 1;
+                self.expect(';')?;
+                return self.array(prefix, depth);
+            }
+        }
+        let mut elements = Vec::new();
+        if self.eat(']') {
+            return Ok(Tag::List(elements));
+        }
+        loop {
+            let element = self.tag(depth + 1)?;
+            if elements.first().is_some_and(|first: &Tag| first.id() != element.id()) {
+                return Err(self.error("list elements of different types"));
+            }
+            elements.push(element);
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(']')?;
+        Ok(Tag::List(elements))
+    }
+
+    /// The elements of `[B;1b,2b]` and the like, after the `;`
+    fn array(&mut self, prefix: char, depth: usize) -> Result<Tag, NbtError> {
+        let mut values = Vec::new();
+        if !self.eat(']') {
+            loop {
+                let value = match (prefix, self.tag(depth + 1)?) {
+                    ('B', Tag::Byte(value)) => value as i64,
+                    ('I', Tag::Int(value)) => value as i64,
+                    ('L', Tag::Long(value)) => value,
+                    _ => return Err(self.error("array element of the wrong type")),
+                };
+                values.push(value);
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect(']')?;
+        }
+        Ok(match prefix {
+            'B' => Tag::ByteArray(values.into_iter().map(|value| value as u8).collect()),
+            'I' => Tag::IntArray(values.into_iter().map(|value| value as i32).collect()),
+            _ => Tag::LongArray(values),
+        })
+    }
+}
+
+/// Numbers by their suffix, booleans as bytes and anything else as a string, like vanilla
+fn unquoted(text: String) -> Tag {
+    match text.as_str() {
+        "true" => return Tag::Byte(1),
+        "false" => return Tag::Byte(0),
+        _ => {}
+    }
+    let (number, suffix) = text.split_at(text.len() - 1);
+    let number_chars = |number: &str| number.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.'));
+    let tag = match suffix {
+        "b" | "B" => number.parse().ok().map(Tag::Byte),
+        "s" | "S" => number.parse().ok().map(Tag::Short),
+        "l" | "L" => number.parse().ok().map(Tag::Long),
+        "f" | "F" if number_chars(number) => number.parse().ok().map(Tag::Float),
+        "d" | "D" if number_chars(number) => number.parse().ok().map(Tag::Double),
+        _ if text.contains(['.', 'e', 'E']) && number_chars(&text) => text.parse().ok().map(Tag::Double),
+        _ => text.parse().ok().map(Tag::Int),
+    };
+    tag.unwrap_or(Tag::String(text))
+}
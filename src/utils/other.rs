@@ -1,9 +1,26 @@
-use super::packets::serialization::{Serializeable, Int};
-
-#[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum State {
     HANDSHAKE,
     STATUS,
     LOGIN,
+    /// Between login and play since 1.20.2, older versions never get here
+    CONFIGURATION,
     PLAY,
-}
\ No newline at end of file
+}
+
+/// Which side sends a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Clientbound,
+    Serverbound,
+}
+
+/// A plain chat component as used for disconnect reasons
+pub fn chat_text(text: &str) -> String {
+    chat_text_value(text).to_string()
+}
+
+pub fn chat_text_value(text: &str) -> serde_json::Value {
+    serde_json::json!({"text": text})
+}
@@ -1,187 +1,1322 @@
-pub trait Packet {
-    fn serialize(&self) -> Vec<u8>;
-    fn deserialize(&mut self, data: Vec<u8>)
-    where
-        Self: Sized;
-    
-    fn new(data: Vec<u8>) -> Self
+use std::fmt::Debug;
+
+use crate::utils::error::ProtocolError;
+use crate::utils::other::{Direction, State};
+
+pub use open_mc_rust_macros::Packet;
+
+/// Target of the packet trace, e.g. `RUST_LOG=info,packets=trace` logs every packet as its struct
+pub const TRACE_TARGET: &str = "packets";
+
+pub trait Packet: Debug {
+    fn serialize(&self) -> Vec<u8> {
+        self.serialize_compressed(None)
+    }
+    fn serialize_compressed(&self, threshold: Option<usize>) -> Vec<u8> {
+        crate::utils::compression::frame(self.serialize_payload(), threshold)
+    }
+    /// Packet id + fields, without any framing
+    fn serialize_payload(&self) -> Vec<u8>;
+    fn new(data: Vec<u8>) -> Result<Self, ProtocolError>
     where
         Self: Sized;
 }
 
+/// Decodes a serverbound packet, the dispatchers use this instead of [`Packet::new`] so it shows up in the packet trace
+pub fn decode<P: Packet>(data: Vec<u8>) -> Result<P, ProtocolError> {
+    let packet = P::new(data)?;
+    trace(Direction::Serverbound, &packet);
+    Ok(packet)
+}
+
+pub fn trace<P: Debug + ?Sized>(direction: Direction, packet: &P) {
+    tracing::trace!(target: TRACE_TARGET, ?direction, "{:?}", packet);
+}
+
+/// Where a packet belongs, generated by `#[derive(Packet)]` from its `#[packet(..)]` attribute.
+/// The dispatchers match on `(STATE, ID)` instead of repeating the numbers.
+#[allow(dead_code)]
+pub trait PacketInfo {
+    const ID: i32;
+    const STATE: State;
+    const DIRECTION: Direction;
+    const NAME: &'static str;
+}
+
 
+#[allow(dead_code)]
 pub(crate) mod serverbound {
-    use super::serialization::Serializable;
-    use super::serialization::deserialize;
-
-    // Define a macro to generate common serialization and deserialization code
-    macro_rules! packet {
-        ($id:expr, $name:ident { $($field:ident : $ty:tt),* $(,)? }) => {
-            #[derive(Default)]
-            pub struct $name {
-                $(pub $field: $ty),*
-            }
+    use uuid::Uuid;
 
-            impl crate::utils::packets::Packet for $name {
-                fn serialize(&self) -> Vec<u8> {
-                    let mut buffer: Vec<u8> = Vec::new();
-                    $(
-                        buffer.extend(&self.$field.serialize());
-                    )*
-                    let pid = $id.serialize();
-                    let mut final_buf = ((buffer.len()+pid.len()) as i32).serialize();
-                    final_buf.extend(pid);
-                    final_buf.extend(buffer);
-                    final_buf
-                }
+    use crate::utils::types::{KnownPack, Slot, UseEntityAction};
 
-                fn deserialize(&mut self, data: Vec<u8>) {
-                    let mut index = 0;
-                    $(
-                        self.$field = deserialize!(data, index, $ty);
-                    )*
-                }
+    use super::serialization::Position;
+    use super::Packet;
 
-                fn new(data: Vec<u8>) -> $name {
-                    let mut p: $name = Default::default();
-                    p.deserialize(data);
-                    p
-                }
-            }
-        };
+    //HANDSHAKE
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x00, state = Handshake, direction = Serverbound)]
+    pub struct ServerboundHandshakePacket {
+        #[varint]
+        pub protocol_version: i32,
+        pub server_address: String,
+        pub server_port: u16,
+        #[varint]
+        pub next_state: i32,
     }
 
     //STATUS
-    packet!(0, ServerboundHandshakePacket{
-        protocol_version: i32,
-        server_address: String,
-        server_port: u16,
-        next_state: i32,
-    });
-    packet!(0, ServerboundStatusRequestPacket{});
-    packet!(1, ServerboundPingRequestPacket{
-        paylaod: i64,
-    });
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x00, state = Status, direction = Serverbound)]
+    pub struct ServerboundStatusRequestPacket {}
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x01, state = Status, direction = Serverbound)]
+    pub struct ServerboundPingRequestPacket {
+        pub paylaod: i64,
+    }
 
     //LOGIN
-    packet!(0, ServerboundLoginStartPacket{
-        name: String,
-    });
-    packet!(3, ServerboundLoginAcknowledgedPacket{});
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x00, state = Login, direction = Serverbound)]
+    pub struct ServerboundLoginStartPacket {
+        pub name: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x01, state = Login, direction = Serverbound)]
+    pub struct ServerboundEncryptionResponsePacket {
+        #[length_prefixed]
+        pub shared_secret: Vec<u8>,
+        #[length_prefixed]
+        pub verify_token: Vec<u8>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x03, state = Login, direction = Serverbound)]
+    pub struct ServerboundLoginAcknowledgedPacket {}
+
+    //CONFIGURATION, only 1.20.2+ has it so the ids are the ones of 1.21
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x00, state = Configuration, direction = Serverbound)]
+    pub struct ServerboundClientInformationPacket {
+        pub locale: String,
+        pub view_distance: i8,
+        #[varint]
+        pub chat_mode: i32,
+        pub chat_colors: bool,
+        pub displayed_skin_parts: u8,
+        #[varint]
+        pub main_hand: i32,
+        pub text_filtering: bool,
+        pub allow_server_listings: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x02, state = Configuration, direction = Serverbound)]
+    pub struct ServerboundConfigPluginMessagePacket {
+        pub channel: String,
+        #[rest]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x03, state = Configuration, direction = Serverbound)]
+    pub struct ServerboundAcknowledgeFinishConfigurationPacket {}
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x04, state = Configuration, direction = Serverbound)]
+    pub struct ServerboundConfigKeepAlivePacket {
+        pub id: i64,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x05, state = Configuration, direction = Serverbound)]
+    pub struct ServerboundPongPacket {
+        #[fixed]
+        pub id: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x06, state = Configuration, direction = Serverbound)]
+    pub struct ServerboundConfigResourcePackResponsePacket {
+        pub uuid: Uuid,
+        #[varint]
+        pub result: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x07, state = Configuration, direction = Serverbound)]
+    pub struct ServerboundKnownPacksPacket {
+        #[length_prefixed]
+        pub packs: Vec<KnownPack>,
+    }
 
     //PLAY
-    packet!(1, ServerboundChatMessagenPacket{
-        message: String,
-    });
-    packet!(2, ServerboundUseEntityPacket{
-        target: i32,
-        _type: i32,
-    });
-    packet!(3, SerevrboundPlayerPacket{
-        on_ground: bool,
-    });
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x00, state = Play, direction = Serverbound)]
+    pub struct ServerboundKeepAlivePacket {
+        #[varint]
+        pub id: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x01, state = Play, direction = Serverbound)]
+    pub struct ServerboundChatMessagePacket {
+        pub message: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x02, state = Play, direction = Serverbound)]
+    pub struct ServerboundUseEntityPacket {
+        #[varint]
+        pub target: i32,
+        pub action: UseEntityAction,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x03, state = Play, direction = Serverbound)]
+    pub struct ServerboundPlayerPacket {
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x04, state = Play, direction = Serverbound)]
+    pub struct ServerboundPlayerPositionPacket {
+        pub x: f64,
+        pub feet_y: f64,
+        pub z: f64,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x05, state = Play, direction = Serverbound)]
+    pub struct ServerboundPlayerLookPacket {
+        pub yaw: f32,
+        pub pitch: f32,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x06, state = Play, direction = Serverbound)]
+    pub struct ServerboundPlayerPositionAndLookPacket {
+        pub x: f64,
+        pub feet_y: f64,
+        pub z: f64,
+        pub yaw: f32,
+        pub pitch: f32,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x07, state = Play, direction = Serverbound)]
+    pub struct ServerboundPlayerDiggingPacket {
+        pub status: i8,
+        pub location: Position,
+        pub face: i8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x08, state = Play, direction = Serverbound)]
+    pub struct ServerboundPlayerBlockPlacementPacket {
+        pub location: Position,
+        pub face: i8,
+        pub held_item: Slot,
+        pub cursor_x: i8,
+        pub cursor_y: i8,
+        pub cursor_z: i8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x09, state = Play, direction = Serverbound)]
+    pub struct ServerboundHeldItemChangePacket {
+        pub slot: i16,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0A, state = Play, direction = Serverbound)]
+    pub struct ServerboundAnimationPacket {}
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0B, state = Play, direction = Serverbound)]
+    pub struct ServerboundEntityActionPacket {
+        #[varint]
+        pub entity_id: i32,
+        #[varint]
+        pub action: i32,
+        #[varint]
+        pub jump_boost: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0C, state = Play, direction = Serverbound)]
+    pub struct ServerboundSteerVehiclePacket {
+        pub sideways: f32,
+        pub forward: f32,
+        pub flags: u8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0D, state = Play, direction = Serverbound)]
+    pub struct ServerboundCloseWindowPacket {
+        pub window_id: u8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0E, state = Play, direction = Serverbound)]
+    pub struct ServerboundClickWindowPacket {
+        pub window_id: u8,
+        pub slot: i16,
+        pub button: i8,
+        pub action_number: i16,
+        pub mode: i8,
+        pub clicked_item: Slot,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0F, state = Play, direction = Serverbound)]
+    pub struct ServerboundConfirmTransactionPacket {
+        pub window_id: i8,
+        pub action_number: i16,
+        pub accepted: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x10, state = Play, direction = Serverbound)]
+    pub struct ServerboundCreativeInventoryActionPacket {
+        pub slot: i16,
+        pub clicked_item: Slot,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x11, state = Play, direction = Serverbound)]
+    pub struct ServerboundEnchantItemPacket {
+        pub window_id: i8,
+        pub enchantment: i8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x12, state = Play, direction = Serverbound)]
+    pub struct ServerboundUpdateSignPacket {
+        pub location: Position,
+        pub line1: String,
+        pub line2: String,
+        pub line3: String,
+        pub line4: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x13, state = Play, direction = Serverbound)]
+    pub struct ServerboundPlayerAbilitiesPacket {
+        pub flags: i8,
+        pub flying_speed: f32,
+        pub walking_speed: f32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x14, state = Play, direction = Serverbound)]
+    pub struct ServerboundTabCompletePacket {
+        pub text: String,
+        #[option]
+        pub looked_at_block: Option<Position>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x15, state = Play, direction = Serverbound)]
+    pub struct ServerboundClientSettingsPacket {
+        pub locale: String,
+        pub view_distance: i8,
+        pub chat_mode: i8,
+        pub chat_colors: bool,
+        pub displayed_skin_parts: u8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x16, state = Play, direction = Serverbound)]
+    pub struct ServerboundClientStatusPacket {
+        #[varint]
+        pub action: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x17, state = Play, direction = Serverbound)]
+    pub struct ServerboundPluginMessagePacket {
+        pub channel: String,
+        #[rest]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x18, state = Play, direction = Serverbound)]
+    pub struct ServerboundSpectatePacket {
+        pub target_player: Uuid,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x19, state = Play, direction = Serverbound)]
+    pub struct ServerboundResourcePackStatusPacket {
+        pub hash: String,
+        #[varint]
+        pub result: i32,
+    }
 }
 
+#[allow(dead_code)]
 pub(crate) mod clientbound {
+    use std::io::Cursor;
+
     use uuid::Uuid;
 
+    use crate::utils::error::ProtocolError;
+    use crate::utils::other::{Direction, State};
     use crate::utils::smpmap::ChunkColumn;
+    use crate::utils::types::{
+        BlockChangeRecord, ChunkMeta, CombatEvent, EntityMetadata, EntityProperty, ExplosionRecord, KnownPack, MapIcon, MapUpdate,
+        NetworkNbt, ObjectData, ObjectiveAction, PlayerListAction, RawNbt, RegistryEntry, ScoreAction, Slot, Statistic,
+        TeamAction, TitleAction, WorldBorderAction,
+    };
+
+    use super::serialization::{self, Angle, Decode, FixedEncoded, Position, Serializable};
+    use super::{Packet, PacketInfo};
 
-    use super::serialization::{Serializable, Int};
-    use super::serialization::deserialize;
-    // Define a macro to generate common serialization and deserialization code
-    macro_rules! packet {
-        ($id:expr, $name:ident { $($field:ident : $ty:ident),* $(,)? }) => {
-            #[derive(Default)]
-            pub struct $name {
-                $(pub $field: $ty),*
+    //STATUS
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x00, state = Status, direction = Clientbound)]
+    pub struct ClientboundStatusResponsePacket {
+        pub json_string: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x01, state = Status, direction = Clientbound)]
+    pub struct ClientboundPingResponsePacket {
+        pub payload: i64,
+    }
+
+    //LOGIN
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x00, state = Login, direction = Clientbound)]
+    pub struct ClientboundDisconnectPacket {
+        pub reason: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x01, state = Login, direction = Clientbound)]
+    pub struct ClientboundEncryptionRequestPacket {
+        pub server_id: String,
+        #[length_prefixed]
+        pub public_key: Vec<u8>,
+        #[length_prefixed]
+        pub verify_token: Vec<u8>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x02, state = Login, direction = Clientbound)]
+    pub struct ClientboundLoginSuccesPacket {
+        /// Hyphenated, Login Success is the only packet sending the UUID as a string
+        pub uuid: String,
+        pub username: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x03, state = Login, direction = Clientbound)]
+    pub struct ClientboundSetCompressionPacket {
+        #[varint]
+        pub threshold: i32,
+    }
+
+    //CONFIGURATION, only 1.20.2+ has it so the ids are the ones of 1.21
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x01, state = Configuration, direction = Clientbound)]
+    pub struct ClientboundConfigPluginMessagePacket {
+        pub channel: String,
+        #[rest]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x02, state = Configuration, direction = Clientbound)]
+    pub struct ClientboundConfigDisconnectPacket {
+        pub reason: NetworkNbt,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x03, state = Configuration, direction = Clientbound)]
+    pub struct ClientboundFinishConfigurationPacket {}
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x04, state = Configuration, direction = Clientbound)]
+    pub struct ClientboundConfigKeepAlivePacket {
+        pub id: i64,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x05, state = Configuration, direction = Clientbound)]
+    pub struct ClientboundPingPacket {
+        #[fixed]
+        pub id: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x07, state = Configuration, direction = Clientbound)]
+    pub struct ClientboundRegistryDataPacket {
+        pub registry_id: String,
+        #[length_prefixed]
+        pub entries: Vec<RegistryEntry>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0C, state = Configuration, direction = Clientbound)]
+    pub struct ClientboundFeatureFlagsPacket {
+        #[length_prefixed]
+        pub flags: Vec<String>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0E, state = Configuration, direction = Clientbound)]
+    pub struct ClientboundKnownPacksPacket {
+        #[length_prefixed]
+        pub packs: Vec<KnownPack>,
+    }
+
+    //PLAY
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x00, state = Play, direction = Clientbound)]
+    pub struct ClientboundKeepAlivePacket {
+        #[varint]
+        pub id: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x01, state = Play, direction = Clientbound)]
+    pub struct ClientboundJoinGamePacket {
+        #[fixed]
+        pub id: i32,
+        pub gamemode: u8,
+        pub dimension: i8,
+        pub difficulty: u8,
+        pub max_players: u8,
+        pub level_type: String,
+        pub reduced_debug_info: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x02, state = Play, direction = Clientbound)]
+    pub struct ClientboundChatMessagePacket {
+        pub json_data: String,
+        /// 0 chat, 1 system message, 2 above the hotbar
+        pub position: i8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x03, state = Play, direction = Clientbound)]
+    pub struct ClientboundTimeUpdatePacket {
+        pub world_age: i64,
+        pub time_of_day: i64,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x04, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityEquipmentPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub slot: i16,
+        pub item: Slot,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x05, state = Play, direction = Clientbound)]
+    pub struct ClientboundSpawnPositionPacket {
+        pub location: Position,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x06, state = Play, direction = Clientbound)]
+    pub struct ClientboundUpdateHealthPacket {
+        pub health: f32,
+        #[varint]
+        pub food: i32,
+        pub food_saturation: f32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x07, state = Play, direction = Clientbound)]
+    pub struct ClientboundRespawnPacket {
+        #[fixed]
+        pub dimension: i32,
+        pub difficulty: u8,
+        pub gamemode: u8,
+        pub level_type: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x08, state = Play, direction = Clientbound)]
+    pub struct ClientboundPlayerPositionAndLookPacket {
+        pub x: f64,
+        pub y: f64,
+        pub z: f64,
+        pub yaw: f32,
+        pub pitch: f32,
+        /// Bits of the fields that are relative
+        pub flags: i8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x09, state = Play, direction = Clientbound)]
+    pub struct ClientboundHeldItemChangePacket {
+        pub slot: i8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0A, state = Play, direction = Clientbound)]
+    pub struct ClientboundUseBedPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub location: Position,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0B, state = Play, direction = Clientbound)]
+    pub struct ClientboundAnimationPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub animation: u8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0C, state = Play, direction = Clientbound)]
+    pub struct ClientboundSpawnPlayerPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub player_uuid: Uuid,
+        /// Fixed-point with 5 fraction bits, like all entity coordinates
+        #[fixed]
+        pub x: i32,
+        #[fixed]
+        pub y: i32,
+        #[fixed]
+        pub z: i32,
+        pub yaw: Angle,
+        pub pitch: Angle,
+        pub current_item: i16,
+        pub metadata: EntityMetadata,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0D, state = Play, direction = Clientbound)]
+    pub struct ClientboundCollectItemPacket {
+        #[varint]
+        pub collected_entity_id: i32,
+        #[varint]
+        pub collector_entity_id: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0E, state = Play, direction = Clientbound)]
+    pub struct ClientboundSpawnObjectPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub kind: i8,
+        #[fixed]
+        pub x: i32,
+        #[fixed]
+        pub y: i32,
+        #[fixed]
+        pub z: i32,
+        pub pitch: Angle,
+        pub yaw: Angle,
+        pub data: ObjectData,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x0F, state = Play, direction = Clientbound)]
+    pub struct ClientboundSpawnMobPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub kind: u8,
+        #[fixed]
+        pub x: i32,
+        #[fixed]
+        pub y: i32,
+        #[fixed]
+        pub z: i32,
+        pub yaw: Angle,
+        pub pitch: Angle,
+        pub head_pitch: Angle,
+        pub velocity_x: i16,
+        pub velocity_y: i16,
+        pub velocity_z: i16,
+        pub metadata: EntityMetadata,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x10, state = Play, direction = Clientbound)]
+    pub struct ClientboundSpawnPaintingPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub title: String,
+        pub location: Position,
+        pub direction: u8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x11, state = Play, direction = Clientbound)]
+    pub struct ClientboundSpawnExperienceOrbPacket {
+        #[varint]
+        pub entity_id: i32,
+        #[fixed]
+        pub x: i32,
+        #[fixed]
+        pub y: i32,
+        #[fixed]
+        pub z: i32,
+        pub count: i16,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x12, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityVelocityPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub velocity_x: i16,
+        pub velocity_y: i16,
+        pub velocity_z: i16,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x13, state = Play, direction = Clientbound)]
+    pub struct ClientboundDestroyEntitiesPacket {
+        #[length_prefixed]
+        #[varint]
+        pub entity_ids: Vec<i32>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x14, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityPacket {
+        #[varint]
+        pub entity_id: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x15, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityRelativeMovePacket {
+        #[varint]
+        pub entity_id: i32,
+        pub delta_x: i8,
+        pub delta_y: i8,
+        pub delta_z: i8,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x16, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityLookPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub yaw: Angle,
+        pub pitch: Angle,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x17, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityLookAndRelativeMovePacket {
+        #[varint]
+        pub entity_id: i32,
+        pub delta_x: i8,
+        pub delta_y: i8,
+        pub delta_z: i8,
+        pub yaw: Angle,
+        pub pitch: Angle,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x18, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityTeleportPacket {
+        #[varint]
+        pub entity_id: i32,
+        #[fixed]
+        pub x: i32,
+        #[fixed]
+        pub y: i32,
+        #[fixed]
+        pub z: i32,
+        pub yaw: Angle,
+        pub pitch: Angle,
+        pub on_ground: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x19, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityHeadLookPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub head_yaw: Angle,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x1A, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityStatusPacket {
+        #[fixed]
+        pub entity_id: i32,
+        pub entity_status: i8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x1B, state = Play, direction = Clientbound)]
+    pub struct ClientboundAttachEntityPacket {
+        #[fixed]
+        pub entity_id: i32,
+        #[fixed]
+        pub vehicle_id: i32,
+        pub leash: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x1C, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityMetadataPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub metadata: EntityMetadata,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x1D, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityEffectPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub effect_id: i8,
+        pub amplifier: i8,
+        #[varint]
+        pub duration: i32,
+        pub hide_particles: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x1E, state = Play, direction = Clientbound)]
+    pub struct ClientboundRemoveEntityEffectPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub effect_id: i8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x1F, state = Play, direction = Clientbound)]
+    pub struct ClientboundSetExperiencePacket {
+        pub experience_bar: f32,
+        #[varint]
+        pub level: i32,
+        #[varint]
+        pub total_experience: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x20, state = Play, direction = Clientbound)]
+    pub struct ClientboundEntityPropertiesPacket {
+        #[varint]
+        pub entity_id: i32,
+        #[length_prefixed(int)]
+        pub properties: Vec<EntityProperty>,
+    }
+
+    /// The layout of the data depends on the bit mask, so this one is implemented by hand
+    #[derive(Debug, Default)]
+    pub struct ClientboundChunkDataPacket {
+        pub chunk_x: i32,
+        pub chunk_z: i32,
+        /// The whole column with its biomes, otherwise only the sections of the bit mask are replaced
+        pub ground_up_continuous: bool,
+        pub primary_bit_mask: u16,
+        /// Only sent for dimensions with a sky, the client knows from the dimension
+        pub sky_light_sent: bool,
+        pub column: ChunkColumn,
+    }
+
+    impl ClientboundChunkDataPacket {
+        /// Sends every section of the column
+        pub fn from_column(chunk_x: i32, chunk_z: i32, column: ChunkColumn, sky_light_sent: bool) -> Self {
+            ClientboundChunkDataPacket{
+                chunk_x,
+                chunk_z,
+                ground_up_continuous: true,
+                primary_bit_mask: column.primary_bit_mask(),
+                sky_light_sent,
+                column,
             }
+        }
 
-            impl crate::utils::packets::Packet for $name {
-                fn serialize(&self) -> Vec<u8> {
-                    let mut buffer: Vec<u8> = Vec::new();
-                    $(
-                        buffer.extend(&self.$field.serialize());
-                    )*
-                    let pid = $id.serialize();
-                    let mut final_buf = ((buffer.len()+pid.len()) as i32).serialize();
-                    final_buf.extend(pid);
-                    final_buf.extend(buffer);
-                    final_buf
-                }
+        /// Tells the client to unload the column, vanilla sends no sections but still the biomes
+        pub fn unload(chunk_x: i32, chunk_z: i32) -> Self {
+            ClientboundChunkDataPacket{chunk_x, chunk_z, ground_up_continuous: true, ..Default::default()}
+        }
+    }
 
-                fn deserialize(&mut self, data: Vec<u8>) {
-                    let mut index = 0;
-                    $(
-                        self.$field = deserialize!(data, index, $ty);
-                    )*
-                }
+    impl PacketInfo for ClientboundChunkDataPacket {
+        const ID: i32 = 0x21;
+        const STATE: State = State::PLAY;
+        const DIRECTION: Direction = Direction::Clientbound;
+        const NAME: &'static str = "ClientboundChunkDataPacket";
+    }
 
-                fn new(data: Vec<u8>) -> $name {
-                    let mut p: $name = Default::default();
-                    p.deserialize(data);
-                    p
-                }
+    impl Packet for ClientboundChunkDataPacket {
+        fn serialize_payload(&self) -> Vec<u8> {
+            let mut buffer = Self::ID.serialize();
+            buffer.extend(self.chunk_x.serialize_fixed());
+            buffer.extend(self.chunk_z.serialize_fixed());
+            buffer.extend(self.ground_up_continuous.serialize());
+            buffer.extend(self.primary_bit_mask.serialize());
+            let size = ChunkColumn::data_size(self.primary_bit_mask, self.sky_light_sent, self.ground_up_continuous);
+            buffer.extend((size as i32).serialize());
+            self.column.write_sections(self.primary_bit_mask, self.sky_light_sent, self.ground_up_continuous, &mut buffer);
+            buffer
+        }
+
+        fn new(data: Vec<u8>) -> Result<Self, ProtocolError> {
+            let cursor = &mut Cursor::new(data.as_slice());
+            let chunk_x = i32::decode_fixed(cursor)?;
+            let chunk_z = i32::decode_fixed(cursor)?;
+            let ground_up_continuous = bool::decode(cursor)?;
+            let primary_bit_mask = u16::decode(cursor)?;
+            let size = serialization::read_length(cursor, serialization::LengthPrefix::VarInt)?;
+            let mut column = ChunkColumn::default();
+            // Whether sky light was sent can only be told from the size
+            let sky_light_sent = size == ChunkColumn::data_size(primary_bit_mask, true, ground_up_continuous);
+            let data = serialization::take(cursor, size)?;
+            column.read_sections(&mut Cursor::new(data), primary_bit_mask, sky_light_sent, ground_up_continuous)?;
+            serialization::expect_end(cursor, "ClientboundChunkDataPacket")?;
+            Ok(ClientboundChunkDataPacket{chunk_x, chunk_z, ground_up_continuous, primary_bit_mask, sky_light_sent, column})
+        }
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x22, state = Play, direction = Clientbound)]
+    pub struct ClientboundMultiBlockChangePacket {
+        #[fixed]
+        pub chunk_x: i32,
+        #[fixed]
+        pub chunk_z: i32,
+        #[length_prefixed]
+        pub records: Vec<BlockChangeRecord>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x23, state = Play, direction = Clientbound)]
+    pub struct ClientboundBlockChangePacket {
+        pub location: Position,
+        /// Block type in the upper bits, metadata in the lower 4
+        #[varint]
+        pub block_id: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x24, state = Play, direction = Clientbound)]
+    pub struct ClientboundBlockActionPacket {
+        pub location: Position,
+        pub action_id: u8,
+        pub action_param: u8,
+        #[varint]
+        pub block_type: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x25, state = Play, direction = Clientbound)]
+    pub struct ClientboundBlockBreakAnimationPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub location: Position,
+        pub destroy_stage: i8,
+    }
+
+    /// Every column is sent whole with its biomes, how much data a column takes depends on its bit mask
+    #[derive(Debug, Default)]
+    pub struct ClientboundMapChunkBulkPacket {
+        pub sky_light_sent: bool,
+        pub chunk_meta: Vec<ChunkMeta>,
+        /// One per entry of `chunk_meta`
+        pub columns: Vec<ChunkColumn>,
+    }
+
+    impl ClientboundMapChunkBulkPacket {
+        /// Sends every section of the columns, given with their chunk x and z
+        pub fn from_columns(sky_light_sent: bool, columns: impl IntoIterator<Item = (i32, i32, ChunkColumn)>) -> Self {
+            let (chunk_meta, columns) = columns.into_iter()
+                .map(|(chunk_x, chunk_z, column)| {
+                    (ChunkMeta{chunk_x, chunk_z, primary_bit_mask: column.primary_bit_mask()}, column)
+                })
+                .unzip();
+            ClientboundMapChunkBulkPacket{sky_light_sent, chunk_meta, columns}
+        }
+    }
+
+    impl PacketInfo for ClientboundMapChunkBulkPacket {
+        const ID: i32 = 0x26;
+        const STATE: State = State::PLAY;
+        const DIRECTION: Direction = Direction::Clientbound;
+        const NAME: &'static str = "ClientboundMapChunkBulkPacket";
+    }
+
+    impl Packet for ClientboundMapChunkBulkPacket {
+        fn serialize_payload(&self) -> Vec<u8> {
+            let mut buffer = Self::ID.serialize();
+            buffer.extend(self.sky_light_sent.serialize());
+            buffer.extend(self.chunk_meta.serialize());
+            for (meta, column) in self.chunk_meta.iter().zip(&self.columns) {
+                column.write_sections(meta.primary_bit_mask, self.sky_light_sent, true, &mut buffer);
             }
-        };
+            buffer
+        }
+
+        fn new(data: Vec<u8>) -> Result<Self, ProtocolError> {
+            let cursor = &mut Cursor::new(data.as_slice());
+            let sky_light_sent = bool::decode(cursor)?;
+            let chunk_meta = Vec::<ChunkMeta>::decode(cursor)?;
+            let mut columns = Vec::with_capacity(chunk_meta.len());
+            for meta in &chunk_meta {
+                let mut column = ChunkColumn::default();
+                column.read_sections(cursor, meta.primary_bit_mask, sky_light_sent, true)?;
+                columns.push(column);
+            }
+            serialization::expect_end(cursor, "ClientboundMapChunkBulkPacket")?;
+            Ok(ClientboundMapChunkBulkPacket{sky_light_sent, chunk_meta, columns})
+        }
     }
 
-    //STATUS
-    packet!(0, ClientboundStatusResponsePacket{
-        json_string: String
-    });
-    packet!(1, ClientboundPingResponsePacket{
-        payload: i64,
-    });
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x27, state = Play, direction = Clientbound)]
+    pub struct ClientboundExplosionPacket {
+        pub x: f32,
+        pub y: f32,
+        pub z: f32,
+        pub radius: f32,
+        #[length_prefixed(int)]
+        pub records: Vec<ExplosionRecord>,
+        pub player_motion_x: f32,
+        pub player_motion_y: f32,
+        pub player_motion_z: f32,
+    }
 
-    //LOGIN
-    packet!(0, ClientboundDisconnectPacket{
-        reason: String,
-    });
-    packet!(2, ClientboundLoginSuccesPacket{
-        uuid: Uuid,
-        username: String,
-    });
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x28, state = Play, direction = Clientbound)]
+    pub struct ClientboundEffectPacket {
+        #[fixed]
+        pub effect_id: i32,
+        pub location: Position,
+        #[fixed]
+        pub data: i32,
+        pub disable_relative_volume: bool,
+    }
 
-    //PLAY
-    packet!(0, ClientboundKeepAlivePacket{
-        id: i32,
-    });
-    packet!(1, ClientboundJoinGamePacket{
-        id: Int,
-        gamemode: u8,
-        dimension: i8,
-        difficulty: u8,
-        max_players: u8,
-        level_type: String,
-        reduced_debug_info: bool,
-    });
-    packet!(0x3F, ClientboundPluginMessagePacket{
-        channel: String,
-        data: String,
-    });
-    packet!(0x41, ClientboundDifficultyPacket{
-        difficulty: u8,
-    });
-    packet!(0x26, ClientboundMapChunkBulkPacket{
-        sky_light_sent: bool,
-        chunk_column_count: i32,
-        chunk_meta: ChunkBulk,
-        chunk_data: ChunkColumn,chunk_data2: ChunkColumn
-    });
-}
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x29, state = Play, direction = Clientbound)]
+    pub struct ClientboundSoundEffectPacket {
+        pub sound_name: String,
+        /// Multiplied by 8
+        #[fixed]
+        pub x: i32,
+        #[fixed]
+        pub y: i32,
+        #[fixed]
+        pub z: i32,
+        pub volume: f32,
+        pub pitch: u8,
+    }
 
-pub mod serialization  {
-    #[derive(Default)]
-    pub struct Int {
-        pub value: i32,
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x2A, state = Play, direction = Clientbound)]
+    pub struct ClientboundParticlePacket {
+        #[fixed]
+        pub particle_id: i32,
+        pub long_distance: bool,
+        pub x: f32,
+        pub y: f32,
+        pub z: f32,
+        pub offset_x: f32,
+        pub offset_y: f32,
+        pub offset_z: f32,
+        pub particle_data: f32,
+        #[fixed]
+        pub count: i32,
+        /// Only iconcrack, blockcrack and blockdust have data
+        #[rest]
+        #[varint]
+        pub data: Vec<i32>,
     }
-    impl Serializable for Int {
-        fn serialize(&self) -> Vec<u8> {
-            self.value.to_le_bytes().to_vec()
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x2B, state = Play, direction = Clientbound)]
+    pub struct ClientboundChangeGameStatePacket {
+        pub reason: u8,
+        pub value: f32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x2C, state = Play, direction = Clientbound)]
+    pub struct ClientboundSpawnGlobalEntityPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub kind: i8,
+        #[fixed]
+        pub x: i32,
+        #[fixed]
+        pub y: i32,
+        #[fixed]
+        pub z: i32,
+    }
+
+    /// The entity id is only sent for horse inventories, so this one is implemented by hand
+    #[derive(Debug, Default)]
+    pub struct ClientboundOpenWindowPacket {
+        pub window_id: u8,
+        pub window_type: String,
+        pub window_title: String,
+        pub number_of_slots: u8,
+        /// Only for "EntityHorse"
+        pub entity_id: Option<i32>,
+    }
+
+    impl PacketInfo for ClientboundOpenWindowPacket {
+        const ID: i32 = 0x2D;
+        const STATE: State = State::PLAY;
+        const DIRECTION: Direction = Direction::Clientbound;
+        const NAME: &'static str = "ClientboundOpenWindowPacket";
+    }
+
+    impl Packet for ClientboundOpenWindowPacket {
+        fn serialize_payload(&self) -> Vec<u8> {
+            let mut buffer = Self::ID.serialize();
+            buffer.extend(self.window_id.serialize());
+            buffer.extend(self.window_type.serialize());
+            buffer.extend(self.window_title.serialize());
+            buffer.extend(self.number_of_slots.serialize());
+            if let Some(entity_id) = self.entity_id {
+                buffer.extend(entity_id.serialize_fixed());
+            }
+            buffer
+        }
+
+        fn new(data: Vec<u8>) -> Result<Self, ProtocolError> {
+            let cursor = &mut Cursor::new(data.as_slice());
+            let window_id = u8::decode(cursor)?;
+            let window_type = String::decode(cursor)?;
+            let window_title = String::decode(cursor)?;
+            let number_of_slots = u8::decode(cursor)?;
+            let entity_id = match window_type.as_str() {
+                "EntityHorse" => Some(i32::decode_fixed(cursor)?),
+                _ => None,
+            };
+            serialization::expect_end(cursor, "ClientboundOpenWindowPacket")?;
+            Ok(ClientboundOpenWindowPacket{window_id, window_type, window_title, number_of_slots, entity_id})
         }
     }
 
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x2E, state = Play, direction = Clientbound)]
+    pub struct ClientboundCloseWindowPacket {
+        pub window_id: u8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x2F, state = Play, direction = Clientbound)]
+    pub struct ClientboundSetSlotPacket {
+        pub window_id: i8,
+        pub slot: i16,
+        pub slot_data: Slot,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x30, state = Play, direction = Clientbound)]
+    pub struct ClientboundWindowItemsPacket {
+        pub window_id: u8,
+        #[length_prefixed(short)]
+        pub slots: Vec<Slot>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x31, state = Play, direction = Clientbound)]
+    pub struct ClientboundWindowPropertyPacket {
+        pub window_id: u8,
+        pub property: i16,
+        pub value: i16,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x32, state = Play, direction = Clientbound)]
+    pub struct ClientboundConfirmTransactionPacket {
+        pub window_id: i8,
+        pub action_number: i16,
+        pub accepted: bool,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x33, state = Play, direction = Clientbound)]
+    pub struct ClientboundUpdateSignPacket {
+        pub location: Position,
+        pub line1: String,
+        pub line2: String,
+        pub line3: String,
+        pub line4: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x34, state = Play, direction = Clientbound)]
+    pub struct ClientboundMapPacket {
+        #[varint]
+        pub item_damage: i32,
+        pub scale: i8,
+        #[length_prefixed]
+        pub icons: Vec<MapIcon>,
+        pub update: MapUpdate,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x35, state = Play, direction = Clientbound)]
+    pub struct ClientboundUpdateBlockEntityPacket {
+        pub location: Position,
+        pub action: u8,
+        pub nbt_data: RawNbt,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x36, state = Play, direction = Clientbound)]
+    pub struct ClientboundOpenSignEditorPacket {
+        pub location: Position,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x37, state = Play, direction = Clientbound)]
+    pub struct ClientboundStatisticsPacket {
+        #[length_prefixed]
+        pub statistics: Vec<Statistic>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x38, state = Play, direction = Clientbound)]
+    pub struct ClientboundPlayerListItemPacket {
+        pub action: PlayerListAction,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x39, state = Play, direction = Clientbound)]
+    pub struct ClientboundPlayerAbilitiesPacket {
+        pub flags: i8,
+        pub flying_speed: f32,
+        pub field_of_view_modifier: f32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x3A, state = Play, direction = Clientbound)]
+    pub struct ClientboundTabCompletePacket {
+        #[length_prefixed]
+        pub matches: Vec<String>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x3B, state = Play, direction = Clientbound)]
+    pub struct ClientboundScoreboardObjectivePacket {
+        pub objective_name: String,
+        pub action: ObjectiveAction,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x3C, state = Play, direction = Clientbound)]
+    pub struct ClientboundUpdateScorePacket {
+        pub score_name: String,
+        pub action: ScoreAction,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x3D, state = Play, direction = Clientbound)]
+    pub struct ClientboundDisplayScoreboardPacket {
+        pub position: i8,
+        pub score_name: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x3E, state = Play, direction = Clientbound)]
+    pub struct ClientboundTeamsPacket {
+        pub team_name: String,
+        pub action: TeamAction,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x3F, state = Play, direction = Clientbound)]
+    pub struct ClientboundPluginMessagePacket {
+        pub channel: String,
+        #[rest]
+        pub data: Vec<u8>,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x40, state = Play, direction = Clientbound)]
+    pub struct ClientboundPlayDisconnectPacket {
+        pub reason: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x41, state = Play, direction = Clientbound)]
+    pub struct ClientboundDifficultyPacket {
+        pub difficulty: u8,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x42, state = Play, direction = Clientbound)]
+    pub struct ClientboundCombatEventPacket {
+        pub event: CombatEvent,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x43, state = Play, direction = Clientbound)]
+    pub struct ClientboundCameraPacket {
+        #[varint]
+        pub camera_id: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x44, state = Play, direction = Clientbound)]
+    pub struct ClientboundWorldBorderPacket {
+        pub action: WorldBorderAction,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x45, state = Play, direction = Clientbound)]
+    pub struct ClientboundTitlePacket {
+        pub action: TitleAction,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x46, state = Play, direction = Clientbound)]
+    pub struct ClientboundPlaySetCompressionPacket {
+        #[varint]
+        pub threshold: i32,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x47, state = Play, direction = Clientbound)]
+    pub struct ClientboundPlayerListHeaderAndFooterPacket {
+        pub header: String,
+        pub footer: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x48, state = Play, direction = Clientbound)]
+    pub struct ClientboundResourcePackSendPacket {
+        pub url: String,
+        pub hash: String,
+    }
+
+    #[derive(Packet, Debug, Default)]
+    #[packet(id = 0x49, state = Play, direction = Clientbound)]
+    pub struct ClientboundUpdateEntityNbtPacket {
+        #[varint]
+        pub entity_id: i32,
+        pub tag: RawNbt,
+    }
+}
+
+#[allow(dead_code)]
+pub mod serialization  {
+    pub use open_mc_rust_macros::{Decode, Serializable};
 
     pub trait Serializable {
         fn serialize(&self) -> Vec<u8>;
@@ -189,11 +1324,12 @@ pub mod serialization  {
     
     impl Serializable for i32 {
         fn serialize(&self) -> Vec<u8> {
-            const SEGMENT_BITS: i32 = 0x7F;
-            const CONTINUE_BIT: i32 = 0x80;
+            const SEGMENT_BITS: u32 = 0x7F;
+            const CONTINUE_BIT: u32 = 0x80;
             
             let mut result = Vec::new();
-            let mut val = self.to_owned();
+            // Negative values are sent as their two's complement, so shift as unsigned
+            let mut val = *self as u32;
             
             loop {
                 if (val & !SEGMENT_BITS) == 0 {
@@ -203,7 +1339,6 @@ pub mod serialization  {
             
                 result.push((val & SEGMENT_BITS | CONTINUE_BIT) as u8);
             
-                // Note: >> is used for right shift in Rust
                 val >>= 7;
             }
         }
@@ -211,7 +1346,8 @@ pub mod serialization  {
     
     impl Serializable for String {
         fn serialize(&self) -> Vec<u8> {
-            let mut data = (self.chars().count() as i32).serialize();
+            // The length is in UTF-8 bytes, not characters
+            let mut data = (self.len() as i32).serialize();
             data.extend(self.as_bytes());
             data
         }
@@ -219,7 +1355,7 @@ pub mod serialization  {
     
     impl Serializable for u16 {
         fn serialize(&self) -> Vec<u8> {
-            self.to_le_bytes().to_vec()
+            self.to_be_bytes().to_vec()
         }
     }
 
@@ -229,20 +1365,21 @@ pub mod serialization  {
         }
     }
 
+    /// 16 bytes, most significant first
     impl Serializable for Uuid {
         fn serialize(&self) -> Vec<u8> {
-            self.to_string().serialize()
+            self.as_bytes().to_vec()
         }
     }
 
     impl Serializable for u8 {
         fn serialize(&self) -> Vec<u8> {
-            self.to_le_bytes().to_vec()
+            self.to_be_bytes().to_vec()
         }
     }
     impl Serializable for i8 {
         fn serialize(&self) -> Vec<u8> {
-            self.to_le_bytes().to_vec()
+            self.to_be_bytes().to_vec()
         }
     }
     
@@ -252,107 +1389,771 @@ pub mod serialization  {
         }
     }
 
-    impl Serializable for ChunkColumn {
+    impl Serializable for i16 {
+        fn serialize(&self) -> Vec<u8> {
+            self.to_be_bytes().to_vec()
+        }
+    }
+
+    impl Serializable for f32 {
+        fn serialize(&self) -> Vec<u8> {
+            self.to_be_bytes().to_vec()
+        }
+    }
+
+    impl Serializable for f64 {
+        fn serialize(&self) -> Vec<u8> {
+            self.to_be_bytes().to_vec()
+        }
+    }
+
+    /// A 64 bit integer sent as a VarLong
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct VarLong {
+        pub value: i64,
+    }
+    impl Serializable for VarLong {
         fn serialize(&self) -> Vec<u8> {
-            let mut data=Vec::new();
-            for _ in 1..16 {
-                for b in self.blocks {
-                    data.push(b<<4|1&15);
-                    data.push(2);
+            let mut result = Vec::new();
+            let mut val = self.value as u64;
+            loop {
+                if (val & !0x7F) == 0 {
+                    result.push(val as u8);
+                    return result;
                 }
-                data.extend((16 as i32).serialize());
-                data.extend((16*16*16*2 as i32).serialize());
-                data.extend([15|15 as u8; 16*16*8]);
-                for i in 1..self.light.data.len() {
-                    data.push(self.light.data[i-1]|self.light.data[i]);
+                result.push((val & 0x7F | 0x80) as u8);
+                val >>= 7;
+            }
+        }
+    }
+
+    /// A block position packed into a long: 26 bits x, 12 bits y, 26 bits z
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Position {
+        pub x: i32,
+        pub y: i32,
+        pub z: i32,
+    }
+    impl Serializable for Position {
+        fn serialize(&self) -> Vec<u8> {
+            let value = ((self.x as i64 & 0x3FFFFFF) << 38) | ((self.y as i64 & 0xFFF) << 26) | (self.z as i64 & 0x3FFFFFF);
+            value.serialize()
+        }
+    }
+
+    /// A rotation in steps of 1/256 of a full turn
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Angle {
+        pub value: u8,
+    }
+    impl Serializable for Angle {
+        fn serialize(&self) -> Vec<u8> {
+            vec![self.value]
+        }
+    }
+
+    /// Prefixed with a bool telling whether the value is present
+    impl<T: Serializable> Serializable for Option<T> {
+        fn serialize(&self) -> Vec<u8> {
+            match self {
+                Some(value) => {
+                    let mut data = true.serialize();
+                    data.extend(value.serialize());
+                    data
                 }
-                data.extend([1 as u8; 256]);
+                None => false.serialize(),
+            }
+        }
+    }
+
+    /// Prefixed with the number of elements as a VarInt
+    impl<T: Serializable> Serializable for Vec<T> {
+        fn serialize(&self) -> Vec<u8> {
+            let mut data = (self.len() as i32).serialize();
+            for element in self {
+                data.extend(element.serialize());
             }
             data
         }
     }
-    
-    macro_rules! deserialize {
-        ($data:expr, $index:expr, i32) => {{
-            let mut result = 0;
-            let mut shift = 0;
-    
-            loop {
-                let byte = $data[$index];
-                $index+=1;
-                result |= ((byte & 0x7F) as u64) << shift;
+
+    /// The counterpart of [`Serializable`]: reads a value from the packet data,
+    /// failing instead of panicking when the data is too short or invalid.
+    pub trait Decode: Sized {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError>;
+    }
+
+    /// Takes the next `len` bytes from the cursor
+    pub fn take<'a>(cursor: &mut Cursor<&'a [u8]>, len: usize) -> Result<&'a [u8], ProtocolError> {
+        let data = *cursor.get_ref();
+        let start = cursor.position() as usize;
+        if data.len().saturating_sub(start) < len {
+            return Err(ProtocolError::UnexpectedEnd);
+        }
+        cursor.set_position((start + len) as u64);
+        Ok(&data[start..start + len])
+    }
+
+    macro_rules! decode_be_bytes {
+        ($($ty:ty),*) => {
+            $(
+                impl Decode for $ty {
+                    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+                        let bytes = take(cursor, std::mem::size_of::<$ty>())?;
+                        Ok(<$ty>::from_be_bytes(bytes.try_into().unwrap()))
+                    }
+                }
+            )*
+        };
+    }
+    decode_be_bytes!(u8, i8, u16, i16, i64, f32, f64);
+
+    /// VarInt, like [`Serializable`] for `i32`
+    impl Decode for i32 {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            let mut result = 0u32;
+            for i in 0..5 {
+                let byte = take(cursor, 1)?[0];
+                result |= ((byte & 0x7F) as u32) << (7 * i);
                 if (byte & 0x80) == 0 {
-                    break;
+                    return Ok(result as i32);
                 }
-                shift += 7;
             }
-            result as i32
-        }};
-        ($data:expr, $index:expr, String) => {{
-            let len: i32 = deserialize!($data, $index, i32);
-            if $index < usize::MAX {
-                let offset = len as usize;
-                let end_index = ($index).saturating_add(offset);
-    
-                // Get the subarray
-                let subarray = &$data[$index..end_index];
-                $index = end_index;
-    
-                // Convert the subarray to a string
-                if let Ok(utf8_string) = std::str::from_utf8(subarray) {
-                    utf8_string.to_owned()
-                } else {
-                    println!("Error: Invalid UTF-8 data in subarray");
-                    "".to_owned()
+            Err(ProtocolError::VarIntTooBig)
+        }
+    }
+
+    impl Decode for VarLong {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            let mut result = 0u64;
+            for i in 0..10 {
+                let byte = take(cursor, 1)?[0];
+                result |= ((byte & 0x7F) as u64) << (7 * i);
+                if (byte & 0x80) == 0 {
+                    return Ok(VarLong{value: result as i64});
                 }
+            }
+            Err(ProtocolError::VarIntTooBig)
+        }
+    }
+
+    impl Decode for bool {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            Ok(take(cursor, 1)?[0] == 0x01)
+        }
+    }
+
+    impl Decode for String {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            let len = read_length(cursor, LengthPrefix::VarInt)?;
+            match std::str::from_utf8(take(cursor, len)?) {
+                Ok(utf8_string) => Ok(utf8_string.to_owned()),
+                Err(err) => Err(ProtocolError::InvalidField(err.to_string())),
+            }
+        }
+    }
+
+    impl Decode for Uuid {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            Ok(Uuid::from_bytes(take(cursor, 16)?.try_into().unwrap()))
+        }
+    }
+
+    impl Decode for Position {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            let value = i64::decode(cursor)?;
+            // Shifting left first and then back keeps the sign of every component
+            Ok(Position{
+                x: (value >> 38) as i32,
+                y: ((value << 26) >> 52) as i32,
+                z: ((value << 38) >> 38) as i32,
+            })
+        }
+    }
+
+    impl Decode for Angle {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            Ok(Angle{
+                value: u8::decode(cursor)?,
+            })
+        }
+    }
+
+    impl<T: Decode> Decode for Option<T> {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            if bool::decode(cursor)? {
+                Ok(Some(T::decode(cursor)?))
             } else {
-                println!("Error: Index out of bounds");
-                "".to_owned()
+                Ok(None)
             }
-        }};
-        ($data:expr, $index:expr, u16) => {{
-            $index+=2;
-            u16::from_le_bytes([$data[$index-1],
-                                $data[$index-2]])
-        }};
-        ($data:expr, $index:expr, i64) => {{
-            $index+=8;
-            i64::from_le_bytes([$data[$index-1],$data[$index-2],
-                                $data[$index-3],$data[$index-4],
-                                $data[$index-5],$data[$index-6],
-                                $data[$index-7],$data[$index-8],])
-        }};
-        ($data:expr, $index:expr, Uuid) => {{
-            Uuid::parse_str(&deserialize!($data, $index, String)).unwrap()
-        }}; 
-        ($data:expr, $index:expr, Int) => {{
-            $index+=4;
-            Int{
-                value: i32::from_le_bytes([
-                    $data[$index-1],$data[$index-2],
-                    $data[$index-3],$data[$index-4],
-                ])
+        }
+    }
+
+    impl<T: Decode> Decode for Vec<T> {
+        fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            let len = read_length(cursor, LengthPrefix::VarInt)?;
+            // Every element takes at least a byte, so a bogus length can't make us allocate more than the packet
+            let mut elements = Vec::with_capacity(len.min(remaining(cursor)));
+            for _ in 0..len {
+                elements.push(T::decode(cursor)?);
             }
-        }}; 
-        ($data:expr, $index:expr, u8) => {{
-            $index+=1;
-            u8::from_le_bytes([$data[$index-1]])
-        }};
-        ($data:expr, $index:expr, i8) => {{
-            $index+=1;
-            i8::from_le_bytes([$data[$index-1]])
-        }};
-        ($data:expr, $index:expr, bool) => {{
-            $index+=1;
-            if $data[$index-1] == 0x01 {true} else {false}
-        }};
-        ($data:expr, $index:expr, ChunkColumn) => {{
-            ChunkColumn::default()
-        }};
-    }
-
-    pub(crate) use deserialize;
+            Ok(elements)
+        }
+    }
+
+    /// Integers with a variable length encoding, selected with `#[varint]`
+    pub trait VarIntEncoded: Sized {
+        fn serialize_varint(&self) -> Vec<u8>;
+        fn decode_varint(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError>;
+    }
+
+    impl VarIntEncoded for i32 {
+        fn serialize_varint(&self) -> Vec<u8> {
+            self.serialize()
+        }
+        fn decode_varint(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            i32::decode(cursor)
+        }
+    }
+
+    impl VarIntEncoded for i64 {
+        fn serialize_varint(&self) -> Vec<u8> {
+            VarLong{value: *self}.serialize()
+        }
+        fn decode_varint(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            Ok(VarLong::decode(cursor)?.value)
+        }
+    }
+
+    /// Integers with a fixed-width big-endian encoding, selected with `#[fixed]`
+    pub trait FixedEncoded: Sized {
+        fn serialize_fixed(&self) -> Vec<u8>;
+        fn decode_fixed(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError>;
+    }
+
+    impl FixedEncoded for i32 {
+        fn serialize_fixed(&self) -> Vec<u8> {
+            self.to_be_bytes().to_vec()
+        }
+        fn decode_fixed(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            Ok(i32::from_be_bytes(take(cursor, 4)?.try_into().unwrap()))
+        }
+    }
+
+    impl FixedEncoded for i64 {
+        fn serialize_fixed(&self) -> Vec<u8> {
+            self.serialize()
+        }
+        fn decode_fixed(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+            i64::decode(cursor)
+        }
+    }
+
+    /// How the length of a `#[length_prefixed]` field is sent
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum LengthPrefix {
+        VarInt,
+        Byte,
+        Short,
+        Int,
+    }
+
+    pub fn write_length(buffer: &mut Vec<u8>, len: usize, prefix: LengthPrefix) {
+        match prefix {
+            LengthPrefix::VarInt => buffer.extend((len as i32).serialize()),
+            LengthPrefix::Byte => buffer.push(len as u8),
+            LengthPrefix::Short => buffer.extend((len as i16).serialize()),
+            LengthPrefix::Int => buffer.extend((len as i32).serialize_fixed()),
+        }
+    }
+
+    /// Reads a length prefix, which must not be negative
+    pub fn read_length(cursor: &mut Cursor<&[u8]>, prefix: LengthPrefix) -> Result<usize, ProtocolError> {
+        let len = match prefix {
+            LengthPrefix::VarInt => i32::decode(cursor)?,
+            LengthPrefix::Byte => u8::decode(cursor)? as i32,
+            LengthPrefix::Short => i16::decode(cursor)? as i32,
+            LengthPrefix::Int => i32::decode_fixed(cursor)?,
+        };
+        if len < 0 {
+            return Err(ProtocolError::InvalidField(format!("negative length {len}")));
+        }
+        Ok(len as usize)
+    }
+
+    /// Number of bytes left in the packet
+    pub fn remaining(cursor: &Cursor<&[u8]>) -> usize {
+        cursor.get_ref().len().saturating_sub(cursor.position() as usize)
+    }
+
+    /// Rejects bytes after the last field like derived packets do, for packets decoded by hand
+    pub fn expect_end(cursor: &Cursor<&[u8]>, packet: &str) -> Result<(), ProtocolError> {
+        match remaining(cursor) {
+            0 => Ok(()),
+            unread => Err(ProtocolError::InvalidField(format!("{unread} unread bytes after {packet}"))),
+        }
+    }
+
+    /// Splits a packet into its id and the still encoded fields
+    pub fn split_packet_id(data: &[u8]) -> Result<(i32, Vec<u8>), ProtocolError> {
+        let mut cursor = Cursor::new(data);
+        let pid = i32::decode(&mut cursor)?;
+        Ok((pid, data[cursor.position() as usize..].to_vec()))
+    }
+
+    use std::io::Cursor;
+
     use uuid::Uuid;
 
+    use crate::utils::error::ProtocolError;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use uuid::Uuid;
+
+    use super::serialization::{self, Angle, Decode, FixedEncoded, Position, Serializable, VarLong};
+    use super::clientbound::{ClientboundChunkDataPacket, ClientboundMapChunkBulkPacket, ClientboundOpenWindowPacket};
+    use super::serverbound::ServerboundHandshakePacket;
+    use super::{Packet, PacketInfo};
     use crate::utils::smpmap::ChunkColumn;
-}
\ No newline at end of file
+    use crate::utils::error::ProtocolError;
+    use crate::utils::other::{Direction, State};
+
+    fn round_trip<T: Serializable + Decode>(value: &T) -> T {
+        let data = value.serialize();
+        let mut cursor = Cursor::new(data.as_slice());
+        let decoded = T::decode(&mut cursor).unwrap();
+        assert_eq!(cursor.position() as usize, data.len());
+        decoded
+    }
+
+    #[test]
+    fn primitives_round_trip() {
+        assert_eq!(round_trip(&300i32), 300);
+        assert_eq!(round_trip(&-7i16), -7);
+        assert_eq!(round_trip(&i64::MIN), i64::MIN);
+        assert_eq!(round_trip(&1.5f32), 1.5);
+        assert_eq!(round_trip(&-2.25f64), -2.25);
+        assert!(round_trip(&true));
+        assert_eq!(round_trip(&"h\u{e9}llo".to_owned()), "h\u{e9}llo");
+        assert_eq!(round_trip(&VarLong{value: 1 << 40}), VarLong{value: 1 << 40});
+        assert_eq!(round_trip(&Angle{value: 200}), Angle{value: 200});
+        let uuid = Uuid::new_v4();
+        assert_eq!(round_trip(&uuid), uuid);
+    }
+
+    #[test]
+    fn position_keeps_negative_coordinates() {
+        let position = Position{x: -33554432, y: -1, z: 1234567};
+        assert_eq!(round_trip(&position), position);
+        assert_eq!(round_trip(&Position{x: 18357644, y: 831, z: -20882616}), Position{x: 18357644, y: 831, z: -20882616});
+    }
+
+    #[test]
+    fn containers_round_trip() {
+        assert_eq!(round_trip(&Some(5i32)), Some(5));
+        assert_eq!(round_trip(&None::<String>), None);
+        assert_eq!(round_trip(&vec![Some(1i16), None, Some(-1)]), vec![Some(1), None, Some(-1)]);
+        assert_eq!(round_trip(&vec![1u8, 2, 3]), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let data = [0x05, b'a', b'b'];
+        assert!(matches!(String::decode(&mut Cursor::new(&data[..])), Err(ProtocolError::UnexpectedEnd)));
+        assert!(matches!(i64::decode(&mut Cursor::new(&[0u8; 7][..])), Err(ProtocolError::UnexpectedEnd)));
+        assert!(matches!(Vec::<u8>::decode(&mut Cursor::new(&[0x7F][..])), Err(ProtocolError::UnexpectedEnd)));
+        assert!(matches!(i32::decode(&mut Cursor::new(&[0xFF; 6][..])), Err(ProtocolError::VarIntTooBig)));
+    }
+
+    #[test]
+    fn packets_decode_their_fields() {
+        let mut data = 47i32.serialize();
+        data.extend("localhost".to_owned().serialize());
+        data.extend([0x63, 0xDD]);
+        data.extend(2i32.serialize());
+        let packet = ServerboundHandshakePacket::new(data).unwrap();
+        assert_eq!(packet.protocol_version, 47);
+        assert_eq!(packet.server_address, "localhost");
+        assert_eq!(packet.server_port, 25565);
+        assert_eq!(packet.next_state, 2);
+
+        assert!(ServerboundHandshakePacket::new(vec![47]).is_err());
+    }
+
+    #[test]
+    fn trailing_data_is_an_error() {
+        let mut data = 47i32.serialize();
+        data.extend("localhost".to_owned().serialize());
+        data.extend([0x63, 0xDD]);
+        data.extend(2i32.serialize());
+        data.push(0);
+        assert!(matches!(ServerboundHandshakePacket::new(data), Err(ProtocolError::InvalidField(_))));
+
+        // Packets decoded by hand too, the ids are all a single byte
+        let fields = |packet: &dyn Packet| packet.serialize_payload()[1..].to_vec();
+        let chest = ClientboundOpenWindowPacket{window_type: "minecraft:chest".to_owned(), ..Default::default()};
+        let mut data = fields(&chest);
+        assert!(ClientboundOpenWindowPacket::new(data.clone()).is_ok());
+        data.extend(7i32.serialize_fixed());
+        assert!(matches!(ClientboundOpenWindowPacket::new(data), Err(ProtocolError::InvalidField(_))));
+        let mut data = fields(&ClientboundMapChunkBulkPacket::from_columns(true, vec![(0, 0, ChunkColumn::default())]));
+        assert!(ClientboundMapChunkBulkPacket::new(data.clone()).is_ok());
+        data.push(0);
+        assert!(matches!(ClientboundMapChunkBulkPacket::new(data), Err(ProtocolError::InvalidField(_))));
+        let mut data = fields(&ClientboundChunkDataPacket::default());
+        assert!(ClientboundChunkDataPacket::new(data.clone()).is_ok());
+        data.push(0);
+        assert!(matches!(ClientboundChunkDataPacket::new(data), Err(ProtocolError::InvalidField(_))));
+    }
+
+    #[derive(Packet, Debug, PartialEq)]
+    #[packet(id = 0x26, state = Play, direction = Clientbound)]
+    struct EncodingsPacket {
+        #[varint]
+        small: i32,
+        #[fixed]
+        entity: i32,
+        #[varint]
+        long: i64,
+        #[length_prefixed(short)]
+        #[fixed]
+        records: Vec<i32>,
+        #[option]
+        #[varint]
+        maybe: Option<i64>,
+        #[rest]
+        data: Vec<u8>,
+    }
+
+    #[test]
+    fn derived_packets_use_field_encodings() {
+        let packet = EncodingsPacket{
+            small: -1,
+            entity: 1,
+            long: 300,
+            records: vec![2, -2],
+            maybe: Some(1),
+            data: vec![9, 8, 7],
+        };
+        let data = packet.serialize_payload();
+        assert_eq!(data, [
+            0x26,
+            0xFF, 0xFF, 0xFF, 0xFF, 0x0F,
+            0x00, 0x00, 0x00, 0x01,
+            0xAC, 0x02,
+            0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0xFF, 0xFF, 0xFF, 0xFE,
+            0x01, 0x01,
+            0x09, 0x08, 0x07,
+        ]);
+
+        let (pid, fields) = super::serialization::split_packet_id(&data).unwrap();
+        assert_eq!(pid, EncodingsPacket::ID);
+        assert_eq!(EncodingsPacket::new(fields).unwrap(), packet);
+        assert_eq!((EncodingsPacket::STATE, EncodingsPacket::DIRECTION), (State::PLAY, Direction::Clientbound));
+        assert_eq!(EncodingsPacket::NAME, "EncodingsPacket");
+    }
+
+    /// Pairs of values and their encoding as sent by vanilla
+    fn assert_wire<T: Serializable + Decode + PartialEq + std::fmt::Debug>(cases: &[(T, &[u8])]) {
+        for (value, bytes) in cases {
+            assert_eq!(&value.serialize(), bytes, "encoding {value:?}");
+            assert_eq!(&T::decode(&mut Cursor::new(*bytes)).unwrap(), value, "decoding {bytes:02x?}");
+        }
+    }
+
+    #[test]
+    fn varints_match_vanilla_bytes() {
+        assert_wire(&[
+            (0i32, &[0x00][..]),
+            (1, &[0x01]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (255, &[0xFF, 0x01]),
+            (25565, &[0xDD, 0xC7, 0x01]),
+            (2097151, &[0xFF, 0xFF, 0x7F]),
+            (i32::MAX, &[0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+            (-1, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+            (i32::MIN, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+        ]);
+        assert_wire(&[
+            (VarLong{value: 2147483648}, &[0x80, 0x80, 0x80, 0x80, 0x08][..]),
+            (VarLong{value: i64::MAX}, &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F]),
+            (VarLong{value: -1}, &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]),
+            (VarLong{value: i64::MIN}, &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]),
+        ]);
+    }
+
+    #[test]
+    fn numbers_are_big_endian() {
+        assert_wire(&[(25565u16, &[0x63, 0xDD][..]), (0x0102, &[0x01, 0x02])]);
+        assert_wire(&[(-2i16, &[0xFF, 0xFE][..])]);
+        assert_wire(&[(-2i8, &[0xFE][..])]);
+        assert_wire(&[(200u8, &[0xC8][..])]);
+        assert_wire(&[(0x0102030405060708i64, &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08][..])]);
+        assert_wire(&[(1.0f32, &[0x3F, 0x80, 0x00, 0x00][..])]);
+        assert_wire(&[(-2.0f64, &[0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00][..])]);
+        assert_eq!((-17i32).serialize_fixed(), [0xFF, 0xFF, 0xFF, 0xEF]);
+        assert_eq!(i32::decode_fixed(&mut Cursor::new(&[0x00, 0x00, 0x36, 0x85][..])).unwrap(), 13957);
+    }
+
+    #[test]
+    fn strings_are_prefixed_with_their_byte_length() {
+        assert_wire(&[
+            ("MC|Brand".to_owned(), &b"\x08MC|Brand"[..]),
+            ("h\u{e9}llo".to_owned(), &[0x06, b'h', 0xC3, 0xA9, b'l', b'l', b'o']),
+            ("\u{1F600}".to_owned(), &[0x04, 0xF0, 0x9F, 0x98, 0x80]),
+        ]);
+        assert!(String::decode(&mut Cursor::new(&[0x02, 0xC3, 0x28][..])).is_err());
+    }
+
+    #[test]
+    fn uuids_are_sixteen_bytes() {
+        let uuid = Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        assert_wire(&[(uuid, &[
+            0x06, 0x9A, 0x79, 0xF4, 0x44, 0xE9, 0x47, 0x26,
+            0xA5, 0xBE, 0xFC, 0xA9, 0x0E, 0x38, 0xAA, 0xF5,
+        ][..])]);
+    }
+
+    #[test]
+    fn captured_chunk_bulk_round_trips() {
+        // A vanilla Map Chunk Bulk from the pre-netty protocol: short column count, int data length,
+        // sky light flag, zlib data and per column int x, int z, ushort primary and add bit masks
+        let captured: &[u8] = include_bytes!("../../packet0x38.bin");
+        let (pid, fields) = serialization::split_packet_id(captured).unwrap();
+        assert_eq!(pid, 0x38);
+
+        let cursor = &mut Cursor::new(fields.as_slice());
+        let column_count = i16::decode(cursor).unwrap();
+        let data_length = i32::decode_fixed(cursor).unwrap();
+        let sky_light_sent = bool::decode(cursor).unwrap();
+        let data = serialization::take(cursor, data_length as usize).unwrap().to_vec();
+        let mut columns = Vec::new();
+        for _ in 0..column_count {
+            let x = i32::decode_fixed(cursor).unwrap();
+            let z = i32::decode_fixed(cursor).unwrap();
+            columns.push((x, z, u16::decode(cursor).unwrap(), u16::decode(cursor).unwrap()));
+        }
+        assert_eq!(serialization::remaining(cursor), 0);
+        assert_eq!(columns, [
+            (-17, 14, 0x1F, 0), (-16, 14, 0x1F, 0), (-16, 15, 0x1F, 0), (-17, 15, 0x1F, 0), (-18, 15, 0x1F, 0),
+        ]);
+
+        // The bit masks have to be read the right way round to account for all of the block data
+        let mut inflated = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(data.as_slice()), &mut inflated).unwrap();
+        let expected: usize = columns.iter()
+            .map(|(_, _, primary, add)| primary.count_ones() as usize * (4096 + 3 * 2048) + add.count_ones() as usize * 2048 + 256)
+            .sum();
+        assert_eq!(inflated.len(), expected);
+
+        let mut encoded = 0x38i32.serialize();
+        encoded.extend(column_count.serialize());
+        encoded.extend(data_length.serialize_fixed());
+        encoded.extend(sky_light_sent.serialize());
+        encoded.extend(&data);
+        for (x, z, primary, add) in columns {
+            encoded.extend(x.serialize_fixed());
+            encoded.extend(z.serialize_fixed());
+            encoded.extend(primary.serialize());
+            encoded.extend(add.serialize());
+        }
+        assert_eq!(encoded, captured);
+    }
+
+    #[test]
+    fn captured_chunks_encode_in_the_1_8_layout() {
+        use super::clientbound::ClientboundMapChunkBulkPacket;
+        use crate::utils::smpmap::{ChunkColumn, NibbleArray, SECTION_VOLUME};
+
+        // The pre-netty packet holds each column's arrays in turn: block ids, metadata, block light, sky light and biomes
+        let captured: &[u8] = include_bytes!("../../packet0x38.bin");
+        let data_length = i32::decode_fixed(&mut Cursor::new(&captured[3..7])).unwrap() as usize;
+        let mut inflated = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(&captured[8..8 + data_length]), &mut inflated).unwrap();
+        let meta = &captured[8 + data_length..];
+
+        let mut columns = Vec::new();
+        let mut expected = vec![0x26, 0x01, 0x05];
+        let mut expected_data = Vec::new();
+        let mut legacy = inflated.as_slice();
+        for entry in meta.chunks(12) {
+            let x = i32::decode_fixed(&mut Cursor::new(&entry[0..4])).unwrap();
+            let z = i32::decode_fixed(&mut Cursor::new(&entry[4..8])).unwrap();
+            let sections = u16::from_be_bytes([entry[8], entry[9]]).count_ones() as usize;
+            expected.extend(&entry[..10]);
+
+            let (ids, rest) = legacy.split_at(sections * SECTION_VOLUME);
+            let (metadata, rest) = rest.split_at(sections * SECTION_VOLUME / 2);
+            let (block_light, rest) = rest.split_at(sections * SECTION_VOLUME / 2);
+            let (sky_light, rest) = rest.split_at(sections * SECTION_VOLUME / 2);
+            let (biomes, rest) = rest.split_at(256);
+            legacy = rest;
+
+            for (i, id) in ids.iter().enumerate() {
+                let nibble = metadata[i / 2] >> (i % 2 * 4) & 0x0F;
+                expected_data.extend(((*id as u16) << 4 | nibble as u16).to_le_bytes());
+            }
+            expected_data.extend(block_light);
+            expected_data.extend(sky_light);
+            expected_data.extend(biomes);
+
+            let mut column = ChunkColumn::default();
+            for y in 0..sections {
+                let section = column.section_mut(y);
+                let states = section.states_mut();
+                for (i, state) in states.iter_mut().enumerate() {
+                    let index = y * SECTION_VOLUME + i;
+                    *state = (ids[index] as u16) << 4 | (metadata[index / 2] >> (index % 2 * 4) & 0x0F) as u16;
+                }
+                let light = |data: &[u8]| NibbleArray::from_bytes(data[y * 2048..(y + 1) * 2048].to_vec()).unwrap();
+                section.block_light = light(block_light);
+                section.sky_light = light(sky_light);
+            }
+            column.biomes = biomes.to_vec();
+            columns.push((x, z, column));
+        }
+        assert!(legacy.is_empty());
+        expected.extend(expected_data);
+
+        let packet = ClientboundMapChunkBulkPacket::from_columns(true, columns);
+        let data = packet.serialize_payload();
+        assert_eq!(data.len(), expected.len());
+        assert!(data == expected, "the encoded bulk differs from the captured chunks");
+
+        let (pid, fields) = serialization::split_packet_id(&data).unwrap();
+        assert_eq!(pid, ClientboundMapChunkBulkPacket::ID);
+        let decoded = ClientboundMapChunkBulkPacket::new(fields).unwrap();
+        assert_eq!(decoded.chunk_meta, packet.chunk_meta);
+        assert!(decoded.columns == packet.columns);
+    }
+
+    #[test]
+    fn chunk_data_sends_the_sections_of_its_bit_mask() {
+        use super::clientbound::ClientboundChunkDataPacket;
+        use crate::utils::smpmap::{Block, ChunkColumn};
+
+        let mut column = ChunkColumn::default();
+        column.set_block(1, 2, 3, Block::new(35, 14));
+        column.set_block(0, 100, 0, Block::new(1, 0));
+        column.set_biome(4, 5, 6);
+        let packet = ClientboundChunkDataPacket::from_column(-3, 7, column, false);
+        let data = packet.serialize_payload();
+        assert_eq!(data[..12], [0x21, 0xFF, 0xFF, 0xFF, 0xFD, 0x00, 0x00, 0x00, 0x07, 0x01, 0x00, 0x41]);
+        // Two sections of blocks and block light, no sky light and the biomes
+        assert_eq!(data[12..15], [0x80, 0xA2, 0x01]);
+        let blocks = 15 + (2 << 8 | 3 << 4 | 1) * 2;
+        assert_eq!(data[blocks..blocks + 2], [0x3E, 0x02]);
+
+        let (_, fields) = serialization::split_packet_id(&data).unwrap();
+        let decoded = ClientboundChunkDataPacket::new(fields).unwrap();
+        assert!(!decoded.sky_light_sent);
+        assert_eq!(decoded.column.get_block(1, 2, 3), Block::new(35, 14));
+        assert_eq!(decoded.column.get_biome(4, 5), 6);
+
+        let unload = ClientboundChunkDataPacket::unload(1, 1).serialize_payload();
+        assert_eq!(unload.len(), 1 + 4 + 4 + 1 + 2 + 2 + 256);
+    }
+
+    #[test]
+    fn play_catalogue_covers_protocol_47() {
+        use super::{clientbound, serverbound};
+
+        let serverbound = [
+            serverbound::ServerboundKeepAlivePacket::ID, serverbound::ServerboundChatMessagePacket::ID,
+            serverbound::ServerboundUseEntityPacket::ID, serverbound::ServerboundPlayerPacket::ID,
+            serverbound::ServerboundPlayerPositionPacket::ID, serverbound::ServerboundPlayerLookPacket::ID,
+            serverbound::ServerboundPlayerPositionAndLookPacket::ID,
+            serverbound::ServerboundPlayerDiggingPacket::ID,
+            serverbound::ServerboundPlayerBlockPlacementPacket::ID,
+            serverbound::ServerboundHeldItemChangePacket::ID, serverbound::ServerboundAnimationPacket::ID,
+            serverbound::ServerboundEntityActionPacket::ID, serverbound::ServerboundSteerVehiclePacket::ID,
+            serverbound::ServerboundCloseWindowPacket::ID, serverbound::ServerboundClickWindowPacket::ID,
+            serverbound::ServerboundConfirmTransactionPacket::ID,
+            serverbound::ServerboundCreativeInventoryActionPacket::ID,
+            serverbound::ServerboundEnchantItemPacket::ID, serverbound::ServerboundUpdateSignPacket::ID,
+            serverbound::ServerboundPlayerAbilitiesPacket::ID, serverbound::ServerboundTabCompletePacket::ID,
+            serverbound::ServerboundClientSettingsPacket::ID, serverbound::ServerboundClientStatusPacket::ID,
+            serverbound::ServerboundPluginMessagePacket::ID, serverbound::ServerboundSpectatePacket::ID,
+            serverbound::ServerboundResourcePackStatusPacket::ID
+        ];
+        assert_eq!(serverbound.to_vec(), (0x00..=0x19).collect::<Vec<i32>>());
+        let clientbound = [
+            clientbound::ClientboundKeepAlivePacket::ID, clientbound::ClientboundJoinGamePacket::ID,
+            clientbound::ClientboundChatMessagePacket::ID, clientbound::ClientboundTimeUpdatePacket::ID,
+            clientbound::ClientboundEntityEquipmentPacket::ID, clientbound::ClientboundSpawnPositionPacket::ID,
+            clientbound::ClientboundUpdateHealthPacket::ID, clientbound::ClientboundRespawnPacket::ID,
+            clientbound::ClientboundPlayerPositionAndLookPacket::ID,
+            clientbound::ClientboundHeldItemChangePacket::ID, clientbound::ClientboundUseBedPacket::ID,
+            clientbound::ClientboundAnimationPacket::ID, clientbound::ClientboundSpawnPlayerPacket::ID,
+            clientbound::ClientboundCollectItemPacket::ID, clientbound::ClientboundSpawnObjectPacket::ID,
+            clientbound::ClientboundSpawnMobPacket::ID, clientbound::ClientboundSpawnPaintingPacket::ID,
+            clientbound::ClientboundSpawnExperienceOrbPacket::ID,
+            clientbound::ClientboundEntityVelocityPacket::ID, clientbound::ClientboundDestroyEntitiesPacket::ID,
+            clientbound::ClientboundEntityPacket::ID, clientbound::ClientboundEntityRelativeMovePacket::ID,
+            clientbound::ClientboundEntityLookPacket::ID,
+            clientbound::ClientboundEntityLookAndRelativeMovePacket::ID,
+            clientbound::ClientboundEntityTeleportPacket::ID, clientbound::ClientboundEntityHeadLookPacket::ID,
+            clientbound::ClientboundEntityStatusPacket::ID, clientbound::ClientboundAttachEntityPacket::ID,
+            clientbound::ClientboundEntityMetadataPacket::ID, clientbound::ClientboundEntityEffectPacket::ID,
+            clientbound::ClientboundRemoveEntityEffectPacket::ID,
+            clientbound::ClientboundSetExperiencePacket::ID, clientbound::ClientboundEntityPropertiesPacket::ID,
+            clientbound::ClientboundChunkDataPacket::ID, clientbound::ClientboundMultiBlockChangePacket::ID,
+            clientbound::ClientboundBlockChangePacket::ID, clientbound::ClientboundBlockActionPacket::ID,
+            clientbound::ClientboundBlockBreakAnimationPacket::ID,
+            clientbound::ClientboundMapChunkBulkPacket::ID, clientbound::ClientboundExplosionPacket::ID,
+            clientbound::ClientboundEffectPacket::ID, clientbound::ClientboundSoundEffectPacket::ID,
+            clientbound::ClientboundParticlePacket::ID, clientbound::ClientboundChangeGameStatePacket::ID,
+            clientbound::ClientboundSpawnGlobalEntityPacket::ID, clientbound::ClientboundOpenWindowPacket::ID,
+            clientbound::ClientboundCloseWindowPacket::ID, clientbound::ClientboundSetSlotPacket::ID,
+            clientbound::ClientboundWindowItemsPacket::ID, clientbound::ClientboundWindowPropertyPacket::ID,
+            clientbound::ClientboundConfirmTransactionPacket::ID, clientbound::ClientboundUpdateSignPacket::ID,
+            clientbound::ClientboundMapPacket::ID, clientbound::ClientboundUpdateBlockEntityPacket::ID,
+            clientbound::ClientboundOpenSignEditorPacket::ID, clientbound::ClientboundStatisticsPacket::ID,
+            clientbound::ClientboundPlayerListItemPacket::ID, clientbound::ClientboundPlayerAbilitiesPacket::ID,
+            clientbound::ClientboundTabCompletePacket::ID,
+            clientbound::ClientboundScoreboardObjectivePacket::ID,
+            clientbound::ClientboundUpdateScorePacket::ID, clientbound::ClientboundDisplayScoreboardPacket::ID,
+            clientbound::ClientboundTeamsPacket::ID, clientbound::ClientboundPluginMessagePacket::ID,
+            clientbound::ClientboundPlayDisconnectPacket::ID, clientbound::ClientboundDifficultyPacket::ID,
+            clientbound::ClientboundCombatEventPacket::ID, clientbound::ClientboundCameraPacket::ID,
+            clientbound::ClientboundWorldBorderPacket::ID, clientbound::ClientboundTitlePacket::ID,
+            clientbound::ClientboundPlaySetCompressionPacket::ID,
+            clientbound::ClientboundPlayerListHeaderAndFooterPacket::ID,
+            clientbound::ClientboundResourcePackSendPacket::ID,
+            clientbound::ClientboundUpdateEntityNbtPacket::ID
+        ];
+        assert_eq!(clientbound.to_vec(), (0x00..=0x49).collect::<Vec<i32>>());
+        assert_eq!(clientbound::ClientboundMapChunkBulkPacket::STATE, State::PLAY);
+        assert_eq!(serverbound::ServerboundPlayerPacket::DIRECTION, Direction::Serverbound);
+    }
+
+    #[test]
+    fn open_window_sends_the_entity_only_for_horses() {
+        use super::clientbound::ClientboundOpenWindowPacket;
+
+        let chest = ClientboundOpenWindowPacket{
+            window_id: 1,
+            window_type: "minecraft:chest".to_owned(),
+            window_title: "{\"text\":\"Chest\"}".to_owned(),
+            number_of_slots: 27,
+            entity_id: None,
+        };
+        let (_, fields) = serialization::split_packet_id(&chest.serialize_payload()).unwrap();
+        assert_eq!(ClientboundOpenWindowPacket::new(fields).unwrap().entity_id, None);
+
+        let horse = ClientboundOpenWindowPacket{
+            window_type: "EntityHorse".to_owned(),
+            entity_id: Some(7),
+            ..chest
+        };
+        let data = horse.serialize_payload();
+        assert_eq!(data[data.len() - 4..], [0, 0, 0, 7]);
+        let (_, fields) = serialization::split_packet_id(&data).unwrap();
+        assert_eq!(ClientboundOpenWindowPacket::new(fields).unwrap().entity_id, Some(7));
+    }
+}
@@ -0,0 +1,396 @@
+//! The server configuration, read from a `server.properties` file like vanilla's.
+//! Every key can be overridden by an `MC_` environment variable (`MC_SERVER_PORT`) and those
+//! by a command line argument (`--server-port 25566` or `--server-port=25566`).
+
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+pub const DEFAULT_PATH: &str = "server.properties";
+/// Prefix of the environment variables overriding the file
+const ENV_PREFIX: &str = "MC_";
+/// Names the file to load instead of [`DEFAULT_PATH`], only as an argument
+const CONFIG_ARG: &str = "config";
+const LEVEL_TYPES: [&str; 5] = ["default", "flat", "largeBiomes", "amplified", "default_1_1"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl GameMode {
+    const ALL: [GameMode; 4] = [GameMode::Survival, GameMode::Creative, GameMode::Adventure, GameMode::Spectator];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Survival => "survival",
+            GameMode::Creative => "creative",
+            GameMode::Adventure => "adventure",
+            GameMode::Spectator => "spectator",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Peaceful,
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    const ALL: [Difficulty; 4] = [Difficulty::Peaceful, Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Peaceful => "peaceful",
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    /// A value that doesn't fit its key, holds the key, the value and what was expected
+    InvalidValue(String, String, &'static str),
+    /// An argument that isn't a known key
+    UnknownOption(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            ConfigError::InvalidValue(key, value, expected) => write!(f, "Invalid value {value:?} for {key}, expected {expected}"),
+            ConfigError::UnknownOption(option) => write!(f, "Unknown option {option}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The keys this server understands, vanilla's other keys are ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerProperties {
+    /// Empty listens on every interface, like vanilla
+    pub server_ip: String,
+    pub server_port: u16,
+    /// Legacy formatted text, § starts a color code
+    pub motd: String,
+    pub max_players: u32,
+    /// Authenticate players against Mojang's session server instead of using offline UUIDs
+    pub online_mode: bool,
+    pub gamemode: GameMode,
+    pub hardcore: bool,
+    pub difficulty: Difficulty,
+    /// One of the 1.8 world types, like `default` or `flat`
+    pub level_type: String,
+    pub reduced_debug_info: bool,
+    /// Packets with a payload of at least this many bytes are zlib compressed, negative disables compression
+    pub network_compression_threshold: i32,
+}
+
+impl Default for ServerProperties {
+    fn default() -> Self {
+        ServerProperties{
+            server_ip: String::new(),
+            server_port: 25565,
+            motd: "A Minecraft Server".to_owned(),
+            max_players: 20,
+            online_mode: false,
+            gamemode: GameMode::Survival,
+            hardcore: false,
+            difficulty: Difficulty::Easy,
+            level_type: "default".to_owned(),
+            reduced_debug_info: false,
+            network_compression_threshold: 256,
+        }
+    }
+}
+
+impl ServerProperties {
+    /// Loads the file named by `--config` (or [`DEFAULT_PATH`]) and applies the overrides.
+    /// A missing file is created with the defaults, so there is something to edit.
+    pub fn load(args: impl IntoIterator<Item = String>, vars: impl IntoIterator<Item = (String, String)>) -> Result<ServerProperties, ConfigError> {
+        let args = parse_args(args)?;
+        // Bad arguments are reported before a missing file gets created
+        let mut checked = ServerProperties::default();
+        for (key, value) in args.iter().filter(|(key, _)| key != CONFIG_ARG) {
+            checked.set(key, value)?;
+        }
+        let path = args.iter()
+            .rev()
+            .find(|(key, _)| key == CONFIG_ARG)
+            .map_or_else(|| PathBuf::from(DEFAULT_PATH), |(_, path)| PathBuf::from(path));
+        let mut properties = ServerProperties::load_or_create(&path)?;
+        for (key, value) in vars {
+            let Some(key) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let key = key.to_lowercase().replace('_', "-");
+            // Other programs may use the prefix too
+            match properties.set(&key, &value) {
+                Err(ConfigError::UnknownOption(_)) => {}
+                result => result?,
+            }
+        }
+        for (key, value) in args.iter().filter(|(key, _)| key != CONFIG_ARG) {
+            properties.set(key, value)?;
+        }
+        Ok(properties)
+    }
+
+    pub fn load_or_create(path: &Path) -> Result<ServerProperties, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => ServerProperties::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let properties = ServerProperties::default();
+                fs::write(path, properties.to_properties()).map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+                tracing::info!("Created {} with the default settings", path.display());
+                Ok(properties)
+            }
+            Err(err) => Err(ConfigError::Io(path.to_owned(), err)),
+        }
+    }
+
+    /// Parses the contents of a properties file, missing keys keep their defaults
+    pub fn parse(text: &str) -> Result<ServerProperties, ConfigError> {
+        let mut properties = ServerProperties::default();
+        for (key, value) in parse_properties(text) {
+            match properties.set(&key, &value) {
+                Err(ConfigError::UnknownOption(_)) => {}
+                result => result?,
+            }
+        }
+        Ok(properties)
+    }
+
+    /// Sets a key as it is named in the file
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = |expected| ConfigError::InvalidValue(key.to_owned(), value.to_owned(), expected);
+        match key {
+            "server-ip" => self.server_ip = value.to_owned(),
+            "server-port" => self.server_port = value.parse().map_err(|_| invalid("a port"))?,
+            "motd" => self.motd = value.to_owned(),
+            "max-players" => self.max_players = value.parse().map_err(|_| invalid("a positive number"))?,
+            "online-mode" => self.online_mode = parse_bool(value).ok_or_else(|| invalid("true or false"))?,
+            // Vanilla used to write the ids, so they are still accepted
+            "gamemode" => self.gamemode = GameMode::ALL.into_iter()
+                .find(|mode| mode.name().eq_ignore_ascii_case(value) || mode.id().to_string() == value)
+                .ok_or_else(|| invalid("survival, creative, adventure or spectator"))?,
+            "hardcore" => self.hardcore = parse_bool(value).ok_or_else(|| invalid("true or false"))?,
+            "difficulty" => self.difficulty = Difficulty::ALL.into_iter()
+                .find(|difficulty| difficulty.name().eq_ignore_ascii_case(value) || difficulty.id().to_string() == value)
+                .ok_or_else(|| invalid("peaceful, easy, normal or hard"))?,
+            "level-type" => self.level_type = LEVEL_TYPES.into_iter()
+                .find(|level_type| level_type.eq_ignore_ascii_case(value))
+                .ok_or_else(|| invalid("default, flat, largeBiomes, amplified or default_1_1"))?
+                .to_owned(),
+            "reduced-debug-info" => self.reduced_debug_info = parse_bool(value).ok_or_else(|| invalid("true or false"))?,
+            "network-compression-threshold" => self.network_compression_threshold = value.parse().map_err(|_| invalid("a number"))?,
+            _ => return Err(ConfigError::UnknownOption(key.to_owned())),
+        }
+        Ok(())
+    }
+
+    /// The address to listen on
+    pub fn address(&self) -> String {
+        let ip = if self.server_ip.is_empty() { "0.0.0.0" } else { &self.server_ip };
+        format!("{}:{}", ip, self.server_port)
+    }
+
+    /// The gamemode byte of the Join Game packet, hardcore is its fourth bit
+    pub fn gamemode_id(&self) -> u8 {
+        self.gamemode.id() | if self.hardcore { 0x08 } else { 0 }
+    }
+
+    /// The file contents, in the same layout vanilla writes
+    pub fn to_properties(&self) -> String {
+        let entries = [
+            ("difficulty", self.difficulty.name().to_owned()),
+            ("gamemode", self.gamemode.name().to_owned()),
+            ("hardcore", self.hardcore.to_string()),
+            ("level-type", self.level_type.clone()),
+            ("max-players", self.max_players.to_string()),
+            ("motd", self.motd.clone()),
+            ("network-compression-threshold", self.network_compression_threshold.to_string()),
+            ("online-mode", self.online_mode.to_string()),
+            ("reduced-debug-info", self.reduced_debug_info.to_string()),
+            ("server-ip", self.server_ip.clone()),
+            ("server-port", self.server_port.to_string()),
+        ];
+        let mut text = "#Minecraft server properties\n".to_owned();
+        for (key, value) in entries {
+            text.push_str(&format!("{}={}\n", key, escape(&value)));
+        }
+        text
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Turns `--key value` and `--key=value` into pairs
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut args = args.into_iter();
+    let mut pairs = Vec::new();
+    while let Some(arg) = args.next() {
+        let Some(option) = arg.strip_prefix("--") else {
+            return Err(ConfigError::UnknownOption(arg));
+        };
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key.to_owned(), value.to_owned()),
+            None => {
+                let value = args.next()
+                    .ok_or_else(|| ConfigError::InvalidValue(option.to_owned(), String::new(), "a value"))?;
+                (option.to_owned(), value)
+            }
+        };
+        pairs.push((key, value));
+    }
+    Ok(pairs)
+}
+
+/// The key value pairs of a Java properties file. Supports comments, `=` and `:` as separators
+/// and the escapes Java writes, but not values continued on the next line.
+fn parse_properties(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(str::trim_start)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .map(|line| {
+            let mut key = String::new();
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => key.push(unescape(&mut chars)),
+                    '=' | ':' => break,
+                    c if c.is_whitespace() => {
+                        // The separator may be surrounded by whitespace or be whitespace itself
+                        let rest = chars.as_str().trim_start();
+                        chars = rest.strip_prefix(['=', ':']).unwrap_or(rest).chars();
+                        break;
+                    }
+                    c => key.push(c),
+                }
+            }
+            let mut value = String::new();
+            let mut chars = chars.as_str().trim_start().chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.push(unescape(&mut chars)),
+                    c => value.push(c),
+                }
+            }
+            (key, value)
+        })
+        .collect()
+}
+
+/// Reads the rest of an escape sequence after the backslash
+fn unescape(chars: &mut std::str::Chars) -> char {
+    match chars.next() {
+        Some('t') => '\t',
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('f') => '\u{c}',
+        Some('u') => {
+            let hex: String = chars.by_ref().take(4).collect();
+            u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+        }
+        Some(c) => c,
+        None => '\\',
+    }
+}
+
+/// Escapes a value the way Java does, so vanilla tools read it back the same
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '\\' | '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' ' if i == 0 => escaped.push_str("\\ "),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if !(' '..='~').contains(&c) => escaped.push_str(&format!("\\u{:04X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_vanilla_files() {
+        let text = "#Minecraft server properties\n\
+            #Sat Oct 17 12:00:00 CEST 2026\n\
+            gamemode=1\n\
+            difficulty = hard\n\
+            motd=\\u00A7aA colorful\\: server\n\
+            level-type=FLAT\n\
+            hardcore=true\n\
+            spawn-protection=16\n\
+            server-port 25570\n";
+        let properties = ServerProperties::parse(text).unwrap();
+        assert_eq!(properties.gamemode, GameMode::Creative);
+        assert_eq!(properties.gamemode_id(), 0x09);
+        assert_eq!(properties.difficulty, Difficulty::Hard);
+        assert_eq!(properties.motd, "§aA colorful: server");
+        assert_eq!(properties.level_type, "flat");
+        assert_eq!(properties.address(), "0.0.0.0:25570");
+        assert_eq!(properties.max_players, 20);
+
+        // What is written is read back the same
+        assert_eq!(ServerProperties::parse(&properties.to_properties()).unwrap(), properties);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let err = ServerProperties::parse("server-port=65536").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid value \"65536\" for server-port, expected a port");
+        assert!(ServerProperties::parse("online-mode=yes").is_err());
+        assert!(ServerProperties::parse("gamemode=hardcore").is_err());
+    }
+
+    #[test]
+    fn arguments_override_the_environment() {
+        let path = std::env::temp_dir().join(format!("server-{}.properties", std::process::id()));
+        fs::write(&path, "server-port=25570\nmax-players=5\nmotd=From the file\n").unwrap();
+        let args = ["--config", path.to_str().unwrap(), "--server-port", "25571", "--motd=From the arguments"].map(str::to_owned);
+        let vars = [("MC_SERVER_PORT", "25572"), ("MC_MAX_PLAYERS", "10"), ("MC_UNRELATED", "x"), ("PATH", "/bin")]
+            .map(|(key, value)| (key.to_owned(), value.to_owned()));
+        let properties = ServerProperties::load(args, vars).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(properties.server_port, 25571);
+        assert_eq!(properties.max_players, 10);
+        assert_eq!(properties.motd, "From the arguments");
+
+        let unknown = ServerProperties::load(["--no-such-key=1".to_owned()], []);
+        assert!(matches!(unknown, Err(ConfigError::UnknownOption(_))));
+    }
+}
@@ -0,0 +1,1086 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use super::anvil;
+use super::configuration::{self, Configuration};
+use super::error::ProtocolError;
+use super::nbt::{Compound, Tag};
+use super::other::State;
+use super::packets::clientbound::{
+    ClientboundAnimationPacket, ClientboundBlockActionPacket, ClientboundBlockBreakAnimationPacket, ClientboundBlockChangePacket,
+    ClientboundCameraPacket, ClientboundChangeGameStatePacket, ClientboundChunkDataPacket, ClientboundMapChunkBulkPacket, ClientboundChatMessagePacket, ClientboundCloseWindowPacket,
+    ClientboundCollectItemPacket, ClientboundCombatEventPacket, ClientboundConfirmTransactionPacket, ClientboundDestroyEntitiesPacket,
+    ClientboundDifficultyPacket, ClientboundDisconnectPacket, ClientboundDisplayScoreboardPacket, ClientboundEffectPacket,
+    ClientboundEncryptionRequestPacket, ClientboundEntityEffectPacket, ClientboundEntityEquipmentPacket, ClientboundEntityHeadLookPacket,
+    ClientboundEntityLookPacket, ClientboundEntityPacket, ClientboundEntityPropertiesPacket, ClientboundEntityStatusPacket,
+    ClientboundEntityVelocityPacket, ClientboundExplosionPacket, ClientboundHeldItemChangePacket, ClientboundJoinGamePacket,
+    ClientboundKeepAlivePacket, ClientboundLoginSuccesPacket, ClientboundMultiBlockChangePacket, ClientboundOpenSignEditorPacket,
+    ClientboundOpenWindowPacket, ClientboundParticlePacket, ClientboundPlayDisconnectPacket, ClientboundPlayerAbilitiesPacket,
+    ClientboundPlayerListHeaderAndFooterPacket, ClientboundPlayerListItemPacket, ClientboundPlayerPositionAndLookPacket,
+    ClientboundPluginMessagePacket, ClientboundRemoveEntityEffectPacket, ClientboundResourcePackSendPacket, ClientboundRespawnPacket,
+    ClientboundScoreboardObjectivePacket, ClientboundSetCompressionPacket, ClientboundSetExperiencePacket, ClientboundSetSlotPacket,
+    ClientboundSpawnPositionPacket, ClientboundStatisticsPacket, ClientboundTabCompletePacket, ClientboundTeamsPacket,
+    ClientboundTimeUpdatePacket, ClientboundTitlePacket, ClientboundUpdateBlockEntityPacket, ClientboundUpdateHealthPacket,
+    ClientboundUpdateScorePacket, ClientboundUseBedPacket, ClientboundWindowItemsPacket, ClientboundWindowPropertyPacket,
+    ClientboundWorldBorderPacket, ClientboundConfigPluginMessagePacket, ClientboundConfigDisconnectPacket,
+    ClientboundFinishConfigurationPacket, ClientboundConfigKeepAlivePacket, ClientboundPingPacket,
+    ClientboundRegistryDataPacket, ClientboundFeatureFlagsPacket, ClientboundKnownPacksPacket,
+};
+use super::packets::serialization::{self, Decode, FixedEncoded, Position, Serializable, VarIntEncoded};
+use super::packets::serverbound::{
+    ServerboundAnimationPacket, ServerboundChatMessagePacket, ServerboundClickWindowPacket, ServerboundClientSettingsPacket,
+    ServerboundClientStatusPacket, ServerboundCloseWindowPacket, ServerboundConfirmTransactionPacket,
+    ServerboundCreativeInventoryActionPacket, ServerboundEncryptionResponsePacket, ServerboundEnchantItemPacket,
+    ServerboundEntityActionPacket, ServerboundHeldItemChangePacket, ServerboundKeepAlivePacket, ServerboundLoginStartPacket,
+    ServerboundPlayerAbilitiesPacket, ServerboundPlayerBlockPlacementPacket, ServerboundPlayerDiggingPacket, ServerboundPlayerLookPacket,
+    ServerboundPlayerPacket, ServerboundPlayerPositionAndLookPacket, ServerboundPlayerPositionPacket, ServerboundPluginMessagePacket,
+    ServerboundResourcePackStatusPacket, ServerboundSpectatePacket, ServerboundSteerVehiclePacket, ServerboundTabCompletePacket,
+    ServerboundUpdateSignPacket, ServerboundUseEntityPacket, ServerboundLoginAcknowledgedPacket,
+    ServerboundClientInformationPacket, ServerboundConfigPluginMessagePacket,
+    ServerboundAcknowledgeFinishConfigurationPacket, ServerboundConfigKeepAlivePacket, ServerboundPongPacket,
+    ServerboundConfigResourcePackResponsePacket, ServerboundKnownPacksPacket,
+};
+use super::packets::{Packet, PacketInfo};
+use super::smpmap::{self, Block, NibbleArray, SECTIONS_PER_COLUMN, SECTION_WIDTH};
+use super::types::{NetworkNbt, Slot, TeamAction, TitleAction, UseEntityAction};
+
+/// A protocol version as sent in the handshake, with the game version players know it by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    pub protocol: i32,
+    pub name: &'static str,
+}
+
+/// 1.8 to 1.8.9, the version the packet structs are written for
+pub const V1_8: ProtocolVersion = ProtocolVersion{protocol: 47, name: "1.8.9"};
+pub const V1_12_2: ProtocolVersion = ProtocolVersion{protocol: 340, name: "1.12.2"};
+pub const V1_20_5: ProtocolVersion = ProtocolVersion{protocol: 766, name: "1.20.6"};
+pub const V1_21: ProtocolVersion = ProtocolVersion{protocol: 767, name: "1.21.1"};
+
+/// Turns the fields of a packet from one version's layout into another's
+type Rewrite = fn(&[u8]) -> Result<Vec<u8>, ProtocolError>;
+/// Turns the fields of a packet into other canonical packets (id + fields) carrying the same
+type Split = fn(&[u8]) -> Result<Vec<Vec<u8>>, ProtocolError>;
+
+struct Mapping {
+    id: i32,
+    rewrite: Option<Rewrite>,
+}
+
+/// The packet ids of one protocol version.
+/// The packet structs and the server only know the 1.8 ids and layouts (the canonical ones),
+/// connections translate everything they read and write through the registry of their client's version.
+/// Handshake and status are the same in every version and are never translated.
+pub struct PacketRegistry {
+    pub version: ProtocolVersion,
+    /// Set for versions that go through the configuration state after login
+    pub configuration: Option<&'static Configuration>,
+    /// The canonical version is passed through untouched
+    canonical: bool,
+    /// By canonical id
+    clientbound: HashMap<(State, i32), Mapping>,
+    /// By canonical id, packets the version doesn't have but can be sent as others
+    clientbound_splits: HashMap<(State, i32), Split>,
+    /// By the version's id, `None` for packets the version has but the server doesn't understand
+    serverbound: HashMap<(State, i32), Option<Mapping>>,
+}
+
+impl PacketRegistry {
+    pub fn new(version: ProtocolVersion) -> PacketRegistry {
+        PacketRegistry{
+            version,
+            configuration: None,
+            canonical: false,
+            clientbound: HashMap::new(),
+            clientbound_splits: HashMap::new(),
+            serverbound: HashMap::new(),
+        }
+    }
+
+    fn canonical(version: ProtocolVersion) -> PacketRegistry {
+        PacketRegistry{
+            canonical: true,
+            ..PacketRegistry::new(version)
+        }
+    }
+
+    /// Sends `P` with `id` and the canonical layout
+    pub fn clientbound<P: PacketInfo>(&mut self, id: i32) -> &mut Self {
+        self.clientbound.insert((P::STATE, P::ID), Mapping{id, rewrite: None});
+        self
+    }
+
+    /// Sends `P` with `id` after rewriting its fields for this version
+    pub fn clientbound_rewritten<P: PacketInfo>(&mut self, id: i32, rewrite: Rewrite) -> &mut Self {
+        self.clientbound.insert((P::STATE, P::ID), Mapping{id, rewrite: Some(rewrite)});
+        self
+    }
+
+    /// Sends `P` as the packets `split` turns it into, each translated on its own
+    pub fn clientbound_split<P: PacketInfo>(&mut self, split: Split) -> &mut Self {
+        self.clientbound_splits.insert((P::STATE, P::ID), split);
+        self
+    }
+
+    /// Reads packets with `id` as `P` with the canonical layout
+    pub fn serverbound<P: PacketInfo>(&mut self, id: i32) -> &mut Self {
+        self.serverbound.insert((P::STATE, id), Some(Mapping{id: P::ID, rewrite: None}));
+        self
+    }
+
+    /// Reads packets with `id` as `P` after rewriting their fields to the canonical layout
+    pub fn serverbound_rewritten<P: PacketInfo>(&mut self, id: i32, rewrite: Rewrite) -> &mut Self {
+        self.serverbound.insert((P::STATE, id), Some(Mapping{id: P::ID, rewrite: Some(rewrite)}));
+        self
+    }
+
+    /// Drops packets with `id`, which only exist in newer versions
+    pub fn serverbound_ignored(&mut self, state: State, id: i32) -> &mut Self {
+        self.serverbound.insert((state, id), None);
+        self
+    }
+
+    fn passes_through(&self, state: State) -> bool {
+        self.canonical || matches!(state, State::HANDSHAKE | State::STATUS)
+    }
+
+    /// Translates a canonical packet (id + fields) into this version.
+    /// Returns `None` if the version has no such packet, then it can't be sent.
+    pub fn encode(&self, state: State, payload: Vec<u8>) -> Result<Option<Vec<u8>>, ProtocolError> {
+        if self.passes_through(state) {
+            return Ok(Some(payload));
+        }
+        let (id, fields) = serialization::split_packet_id(&payload)?;
+        let Some(mapping) = self.clientbound.get(&(state, id)) else {
+            return Ok(None);
+        };
+        translate(mapping, &fields).map(Some)
+    }
+
+    /// Like [`PacketRegistry::encode`], but packets the version only has in parts are split up.
+    /// Returns every packet to send, which may be none.
+    pub fn encode_all(&self, state: State, payload: Vec<u8>) -> Result<Vec<Vec<u8>>, ProtocolError> {
+        if !self.passes_through(state) {
+            let (id, fields) = serialization::split_packet_id(&payload)?;
+            if let Some(split) = self.clientbound_splits.get(&(state, id)) {
+                let mut packets = Vec::new();
+                for payload in split(&fields)? {
+                    packets.extend(self.encode(state, payload)?);
+                }
+                return Ok(packets);
+            }
+        }
+        Ok(self.encode(state, payload)?.into_iter().collect())
+    }
+
+    /// Translates a packet of this version into the canonical one.
+    /// Returns `None` for packets that are known but ignored.
+    pub fn decode(&self, state: State, data: Vec<u8>) -> Result<Option<Vec<u8>>, ProtocolError> {
+        if self.passes_through(state) {
+            return Ok(Some(data));
+        }
+        let (id, fields) = serialization::split_packet_id(&data)?;
+        match self.serverbound.get(&(state, id)) {
+            Some(Some(mapping)) => translate(mapping, &fields).map(Some),
+            Some(None) => Ok(None),
+            None => Err(ProtocolError::UnknownPacket{state, id}),
+        }
+    }
+}
+
+fn translate(mapping: &Mapping, fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let mut data = mapping.id.serialize();
+    match mapping.rewrite {
+        Some(rewrite) => data.extend(rewrite(fields)?),
+        None => data.extend(fields),
+    }
+    Ok(data)
+}
+
+/// Every supported version, the one to use is picked from the handshake
+pub struct Registries {
+    /// Sorted by protocol version
+    registries: Vec<Arc<PacketRegistry>>,
+}
+
+impl Registries {
+    pub fn new(mut registries: Vec<PacketRegistry>) -> Registries {
+        registries.sort_by_key(|registry| registry.version.protocol);
+        Registries{
+            registries: registries.into_iter().map(Arc::new).collect(),
+        }
+    }
+
+    /// The registry for `protocol`, or the error to disconnect the client with
+    pub fn find(&self, protocol: i32) -> Result<Arc<PacketRegistry>, ProtocolError> {
+        if let Some(registry) = self.registries.iter().find(|registry| registry.version.protocol == protocol) {
+            return Ok(registry.clone());
+        }
+        // Like vanilla, point clients to the closest newer version and newer clients to the newest version
+        match self.registries.iter().find(|registry| registry.version.protocol > protocol) {
+            Some(registry) => Err(ProtocolError::OutdatedClient(registry.version.name)),
+            None => Err(ProtocolError::OutdatedServer(self.newest().version.name)),
+        }
+    }
+
+    pub fn newest(&self) -> &Arc<PacketRegistry> {
+        self.registries.last().expect("at least one protocol version is supported")
+    }
+}
+
+impl Registries {
+    /// Every supported version. Newer clients are told in the Encryption Request whether
+    /// the server authenticates them, which it only does in online mode.
+    ///
+    /// 1.20.2 to 1.20.4 are left to a separate request: they have no known packs, so every
+    /// registry would have to be sent with all of its contents.
+    pub fn supported(online_mode: bool) -> Registries {
+        Registries::new(vec![PacketRegistry::canonical(V1_8), v1_12_2(), v1_20_5(online_mode), v1_21(online_mode)])
+    }
+}
+
+/// The versions of an online mode server, like vanilla's default
+impl Default for Registries {
+    fn default() -> Self {
+        Registries::supported(true)
+    }
+}
+
+/// 1.12.2. Only packets that still exist with a compatible layout or can be rewritten are mapped,
+/// the others (spawning and moving entities, ...) aren't sent to these clients.
+fn v1_12_2() -> PacketRegistry {
+    let mut registry = PacketRegistry::new(V1_12_2);
+    registry
+        .clientbound::<ClientboundDisconnectPacket>(0x00)
+        .clientbound::<ClientboundEncryptionRequestPacket>(0x01)
+        .clientbound::<ClientboundLoginSuccesPacket>(0x02)
+        .clientbound::<ClientboundSetCompressionPacket>(0x03)
+        .serverbound::<ServerboundLoginStartPacket>(0x00)
+        .serverbound::<ServerboundEncryptionResponsePacket>(0x01);
+
+    registry
+        .clientbound::<ClientboundAnimationPacket>(0x06)
+        .clientbound::<ClientboundStatisticsPacket>(0x07)
+        .clientbound::<ClientboundBlockBreakAnimationPacket>(0x08)
+        .clientbound::<ClientboundUpdateBlockEntityPacket>(0x09)
+        .clientbound::<ClientboundBlockActionPacket>(0x0A)
+        .clientbound::<ClientboundBlockChangePacket>(0x0B)
+        .clientbound::<ClientboundDifficultyPacket>(0x0D)
+        .clientbound::<ClientboundTabCompletePacket>(0x0E)
+        .clientbound::<ClientboundChatMessagePacket>(0x0F)
+        .clientbound::<ClientboundMultiBlockChangePacket>(0x10)
+        .clientbound::<ClientboundConfirmTransactionPacket>(0x11)
+        .clientbound::<ClientboundCloseWindowPacket>(0x12)
+        .clientbound::<ClientboundOpenWindowPacket>(0x13)
+        .clientbound::<ClientboundWindowItemsPacket>(0x14)
+        .clientbound::<ClientboundWindowPropertyPacket>(0x15)
+        .clientbound::<ClientboundSetSlotPacket>(0x16)
+        .clientbound::<ClientboundPluginMessagePacket>(0x18)
+        .clientbound::<ClientboundPlayDisconnectPacket>(0x1A)
+        .clientbound::<ClientboundEntityStatusPacket>(0x1B)
+        .clientbound::<ClientboundExplosionPacket>(0x1C)
+        .clientbound::<ClientboundChangeGameStatePacket>(0x1E)
+        .clientbound_rewritten::<ClientboundKeepAlivePacket>(0x1F, keep_alive_to_long)
+        .clientbound_rewritten::<ClientboundChunkDataPacket>(0x20, chunk_data_with_palette)
+        .clientbound_split::<ClientboundMapChunkBulkPacket>(map_chunk_bulk_to_chunk_data)
+        .clientbound::<ClientboundEffectPacket>(0x21)
+        .clientbound::<ClientboundParticlePacket>(0x22)
+        .clientbound_rewritten::<ClientboundJoinGamePacket>(0x23, join_game_with_int_dimension)
+        .clientbound::<ClientboundEntityPacket>(0x25)
+        .clientbound::<ClientboundEntityLookPacket>(0x28)
+        .clientbound::<ClientboundOpenSignEditorPacket>(0x2A)
+        .clientbound::<ClientboundPlayerAbilitiesPacket>(0x2C)
+        .clientbound::<ClientboundCombatEventPacket>(0x2D)
+        .clientbound::<ClientboundPlayerListItemPacket>(0x2E)
+        .clientbound_rewritten::<ClientboundPlayerPositionAndLookPacket>(0x2F, position_and_look_with_teleport_id)
+        .clientbound::<ClientboundUseBedPacket>(0x30)
+        .clientbound::<ClientboundDestroyEntitiesPacket>(0x32)
+        .clientbound::<ClientboundRemoveEntityEffectPacket>(0x33)
+        .clientbound::<ClientboundResourcePackSendPacket>(0x34)
+        .clientbound::<ClientboundRespawnPacket>(0x35)
+        .clientbound::<ClientboundEntityHeadLookPacket>(0x36)
+        .clientbound::<ClientboundWorldBorderPacket>(0x38)
+        .clientbound::<ClientboundCameraPacket>(0x39)
+        .clientbound::<ClientboundHeldItemChangePacket>(0x3A)
+        .clientbound::<ClientboundDisplayScoreboardPacket>(0x3B)
+        .clientbound::<ClientboundEntityVelocityPacket>(0x3E)
+        .clientbound_rewritten::<ClientboundEntityEquipmentPacket>(0x3F, equipment_with_hands)
+        .clientbound::<ClientboundSetExperiencePacket>(0x40)
+        .clientbound::<ClientboundUpdateHealthPacket>(0x41)
+        .clientbound::<ClientboundScoreboardObjectivePacket>(0x42)
+        .clientbound_rewritten::<ClientboundTeamsPacket>(0x44, teams_with_collision_rule)
+        .clientbound::<ClientboundUpdateScorePacket>(0x45)
+        .clientbound::<ClientboundSpawnPositionPacket>(0x46)
+        .clientbound::<ClientboundTimeUpdatePacket>(0x47)
+        .clientbound_rewritten::<ClientboundTitlePacket>(0x48, title_with_action_bar)
+        .clientbound::<ClientboundPlayerListHeaderAndFooterPacket>(0x4A)
+        .clientbound_rewritten::<ClientboundCollectItemPacket>(0x4B, collect_item_with_count)
+        .clientbound::<ClientboundEntityPropertiesPacket>(0x4E)
+        .clientbound_rewritten::<ClientboundEntityEffectPacket>(0x4F, entity_effect_with_flags);
+
+    registry
+        .serverbound_ignored(State::PLAY, 0x00) // Teleport Confirm
+        .serverbound_rewritten::<ServerboundTabCompletePacket>(0x01, tab_complete_without_command_flag)
+        .serverbound::<ServerboundChatMessagePacket>(0x02)
+        .serverbound::<ServerboundClientStatusPacket>(0x03)
+        .serverbound_rewritten::<ServerboundClientSettingsPacket>(0x04, client_settings_without_main_hand)
+        .serverbound::<ServerboundConfirmTransactionPacket>(0x05)
+        .serverbound::<ServerboundEnchantItemPacket>(0x06)
+        // The mode is a VarInt now, which is the same byte for all modes
+        .serverbound::<ServerboundClickWindowPacket>(0x07)
+        .serverbound::<ServerboundCloseWindowPacket>(0x08)
+        .serverbound::<ServerboundPluginMessagePacket>(0x09)
+        .serverbound_rewritten::<ServerboundUseEntityPacket>(0x0A, use_entity_without_hand)
+        .serverbound_rewritten::<ServerboundKeepAlivePacket>(0x0B, keep_alive_to_varint)
+        .serverbound::<ServerboundPlayerPacket>(0x0C)
+        .serverbound::<ServerboundPlayerPositionPacket>(0x0D)
+        .serverbound::<ServerboundPlayerPositionAndLookPacket>(0x0E)
+        .serverbound::<ServerboundPlayerLookPacket>(0x0F)
+        .serverbound_ignored(State::PLAY, 0x10) // Vehicle Move
+        .serverbound_ignored(State::PLAY, 0x11) // Steer Boat
+        .serverbound_ignored(State::PLAY, 0x12) // Craft Recipe Request
+        .serverbound::<ServerboundPlayerAbilitiesPacket>(0x13)
+        // Same for the status, which is a VarInt now
+        .serverbound::<ServerboundPlayerDiggingPacket>(0x14)
+        .serverbound::<ServerboundEntityActionPacket>(0x15)
+        .serverbound::<ServerboundSteerVehiclePacket>(0x16)
+        .serverbound_ignored(State::PLAY, 0x17) // Crafting Book Data
+        .serverbound_rewritten::<ServerboundResourcePackStatusPacket>(0x18, resource_pack_status_without_hash)
+        .serverbound_ignored(State::PLAY, 0x19) // Advancement Tab
+        .serverbound::<ServerboundHeldItemChangePacket>(0x1A)
+        .serverbound::<ServerboundCreativeInventoryActionPacket>(0x1B)
+        .serverbound::<ServerboundUpdateSignPacket>(0x1C)
+        .serverbound_rewritten::<ServerboundAnimationPacket>(0x1D, |_| Ok(Vec::new()))
+        .serverbound::<ServerboundSpectatePacket>(0x1E)
+        .serverbound_rewritten::<ServerboundPlayerBlockPlacementPacket>(0x1F, block_placement_with_cursor_floats)
+        .serverbound_ignored(State::PLAY, 0x20); // Use Item
+    registry
+}
+
+/// 1.20.5 and 1.20.6, which only differ from 1.21 in their registries
+fn v1_20_5(online_mode: bool) -> PacketRegistry {
+    with_configuration(V1_20_5, &configuration::V1_20_5, online_mode)
+}
+
+fn v1_21(online_mode: bool) -> PacketRegistry {
+    with_configuration(V1_21, &configuration::V1_21, online_mode)
+}
+
+/// 1.20.5 to 1.21.1. They log in through the configuration state, in play they get the packets
+/// to join, chat and get kicked, the chunks and block changes. Blocks they don't have a
+/// translation for show as stone. The player list isn't sent and everything they send
+/// besides chat, movement and keep alives is dropped.
+fn with_configuration(version: ProtocolVersion, configuration: &'static Configuration, online_mode: bool) -> PacketRegistry {
+    let mut registry = PacketRegistry::new(version);
+    registry.configuration = Some(configuration);
+    let encryption_request = if online_mode { encryption_request_with_authentication } else { encryption_request_without_authentication };
+    registry
+        .clientbound::<ClientboundDisconnectPacket>(0x00)
+        .clientbound_rewritten::<ClientboundEncryptionRequestPacket>(0x01, encryption_request)
+        .clientbound_rewritten::<ClientboundLoginSuccesPacket>(0x02, login_success_with_properties)
+        .clientbound::<ClientboundSetCompressionPacket>(0x03)
+        .serverbound_rewritten::<ServerboundLoginStartPacket>(0x00, login_start_without_uuid)
+        .serverbound::<ServerboundEncryptionResponsePacket>(0x01)
+        .serverbound_ignored(State::LOGIN, 0x02) // Login Plugin Response
+        .serverbound::<ServerboundLoginAcknowledgedPacket>(0x03)
+        .serverbound_ignored(State::LOGIN, 0x04); // Cookie Response
+
+    // The configuration packets are written for 1.21 already
+    registry
+        .clientbound::<ClientboundConfigPluginMessagePacket>(ClientboundConfigPluginMessagePacket::ID)
+        .clientbound::<ClientboundConfigDisconnectPacket>(ClientboundConfigDisconnectPacket::ID)
+        .clientbound::<ClientboundFinishConfigurationPacket>(ClientboundFinishConfigurationPacket::ID)
+        .clientbound::<ClientboundConfigKeepAlivePacket>(ClientboundConfigKeepAlivePacket::ID)
+        .clientbound::<ClientboundPingPacket>(ClientboundPingPacket::ID)
+        .clientbound::<ClientboundRegistryDataPacket>(ClientboundRegistryDataPacket::ID)
+        .clientbound::<ClientboundFeatureFlagsPacket>(ClientboundFeatureFlagsPacket::ID)
+        .clientbound::<ClientboundKnownPacksPacket>(ClientboundKnownPacksPacket::ID)
+        .serverbound::<ServerboundClientInformationPacket>(ServerboundClientInformationPacket::ID)
+        .serverbound_ignored(State::CONFIGURATION, 0x01) // Cookie Response
+        .serverbound::<ServerboundConfigPluginMessagePacket>(ServerboundConfigPluginMessagePacket::ID)
+        .serverbound::<ServerboundAcknowledgeFinishConfigurationPacket>(ServerboundAcknowledgeFinishConfigurationPacket::ID)
+        .serverbound::<ServerboundConfigKeepAlivePacket>(ServerboundConfigKeepAlivePacket::ID)
+        .serverbound::<ServerboundPongPacket>(ServerboundPongPacket::ID)
+        .serverbound::<ServerboundConfigResourcePackResponsePacket>(ServerboundConfigResourcePackResponsePacket::ID)
+        .serverbound::<ServerboundKnownPacksPacket>(ServerboundKnownPacksPacket::ID);
+
+    // Everything else the client sends in play is dropped
+    for id in 0x00..=0x39 {
+        registry.serverbound_ignored(State::PLAY, id);
+    }
+    registry
+        .clientbound_rewritten::<ClientboundBlockChangePacket>(0x09, block_change_with_flattened_state)
+        .clientbound_split::<ClientboundMultiBlockChangePacket>(multi_block_change_to_block_changes)
+        .clientbound_rewritten::<ClientboundPluginMessagePacket>(0x19, plugin_message_with_namespaced_channel)
+        .clientbound_rewritten::<ClientboundPlayDisconnectPacket>(0x1D, disconnect_with_nbt_reason)
+        .clientbound::<ClientboundChangeGameStatePacket>(0x22)
+        .clientbound_rewritten::<ClientboundKeepAlivePacket>(0x26, keep_alive_to_long)
+        .clientbound_rewritten::<ClientboundChunkDataPacket>(0x27, chunk_data_with_light)
+        .clientbound_split::<ClientboundMapChunkBulkPacket>(map_chunk_bulk_to_chunk_data)
+        .clientbound_rewritten::<ClientboundJoinGamePacket>(0x2B, join_game_with_dimension_registry)
+        .clientbound_split::<ClientboundJoinGamePacket>(join_game_and_wait_for_chunks)
+        .clientbound_rewritten::<ClientboundPlayerPositionAndLookPacket>(0x40, position_and_look_with_teleport_id)
+        .clientbound_rewritten::<ClientboundChatMessagePacket>(0x6C, chat_message_to_system_chat)
+        .serverbound_rewritten::<ServerboundChatMessagePacket>(0x06, chat_message_without_signature)
+        .serverbound_rewritten::<ServerboundPluginMessagePacket>(0x12, plugin_message_with_legacy_channel)
+        .serverbound_rewritten::<ServerboundKeepAlivePacket>(0x18, keep_alive_to_varint)
+        .serverbound::<ServerboundPlayerPositionPacket>(0x1A)
+        .serverbound::<ServerboundPlayerPositionAndLookPacket>(0x1B)
+        .serverbound::<ServerboundPlayerLookPacket>(0x1C)
+        .serverbound::<ServerboundPlayerPacket>(0x1D);
+    registry
+}
+
+fn fields_of(packet: impl Packet) -> Result<Vec<u8>, ProtocolError> {
+    let (_, fields) = serialization::split_packet_id(&packet.serialize_payload())?;
+    Ok(fields)
+}
+
+fn keep_alive_to_long(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundKeepAlivePacket::new(fields.to_vec())?;
+    Ok((packet.id as i64).serialize())
+}
+
+fn keep_alive_to_varint(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let id = i64::decode(&mut Cursor::new(fields))?;
+    Ok((id as i32).serialize_varint())
+}
+
+fn join_game_with_int_dimension(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundJoinGamePacket::new(fields.to_vec())?;
+    Ok([
+        packet.id.serialize_fixed(),
+        packet.gamemode.serialize(),
+        (packet.dimension as i32).serialize_fixed(),
+        packet.difficulty.serialize(),
+        packet.max_players.serialize(),
+        packet.level_type.serialize(),
+        packet.reduced_debug_info.serialize(),
+    ].concat())
+}
+
+fn chunk_data_with_palette(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundChunkDataPacket::new(fields.to_vec())?;
+    let mut data = Vec::new();
+    packet.column.write_paletted_sections(packet.primary_bit_mask, packet.sky_light_sent, packet.ground_up_continuous, &mut data);
+    Ok([
+        packet.chunk_x.serialize_fixed(),
+        packet.chunk_z.serialize_fixed(),
+        packet.ground_up_continuous.serialize(),
+        (packet.primary_bit_mask as i32).serialize_varint(),
+        (data.len() as i32).serialize_varint(),
+        data,
+        // No block entities
+        0.serialize_varint(),
+    ].concat())
+}
+
+/// Map Chunk Bulk is gone, every column gets its own Chunk Data
+fn map_chunk_bulk_to_chunk_data(fields: &[u8]) -> Result<Vec<Vec<u8>>, ProtocolError> {
+    let packet = ClientboundMapChunkBulkPacket::new(fields.to_vec())?;
+    Ok(packet.chunk_meta.iter().zip(packet.columns)
+        .map(|(meta, column)| ClientboundChunkDataPacket{
+            chunk_x: meta.chunk_x,
+            chunk_z: meta.chunk_z,
+            ground_up_continuous: true,
+            primary_bit_mask: meta.primary_bit_mask,
+            sky_light_sent: packet.sky_light_sent,
+            column,
+        }.serialize_payload())
+        .collect())
+}
+
+fn position_and_look_with_teleport_id(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    ClientboundPlayerPositionAndLookPacket::new(fields.to_vec())?;
+    // The client confirms the teleport with this id, which is ignored
+    Ok([fields, &0.serialize_varint()].concat())
+}
+
+fn equipment_with_hands(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundEntityEquipmentPacket::new(fields.to_vec())?;
+    // The off hand got slot 1, the armor slots moved up by one
+    let slot = if packet.slot == 0 { 0 } else { packet.slot as i32 + 1 };
+    Ok([packet.entity_id.serialize_varint(), slot.serialize_varint(), packet.item.serialize()].concat())
+}
+
+fn teams_with_collision_rule(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundTeamsPacket::new(fields.to_vec())?;
+    let info = match &packet.action {
+        TeamAction::Create{info, ..} | TeamAction::UpdateInfo(info) => info,
+        _ => return Ok(fields.to_vec()),
+    };
+    let mut data = packet.team_name.serialize();
+    data.extend(packet.action.serialize()[..1].to_vec());
+    data.extend(info.serialize());
+    data.extend("always".to_owned().serialize());
+    if let TeamAction::Create{players, ..} = &packet.action {
+        data.extend(players.serialize());
+    }
+    Ok(data)
+}
+
+fn title_with_action_bar(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundTitlePacket::new(fields.to_vec())?;
+    let data = packet.action.serialize();
+    // Action 2 became the action bar, the later actions moved up by one
+    Ok(match packet.action {
+        TitleAction::SetTimes(..) | TitleAction::Hide | TitleAction::Reset => {
+            [(data[0] as i32 + 1).serialize_varint(), data[1..].to_vec()].concat()
+        }
+        _ => data,
+    })
+}
+
+fn collect_item_with_count(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    ClientboundCollectItemPacket::new(fields.to_vec())?;
+    Ok([fields, &1.serialize_varint()].concat())
+}
+
+fn entity_effect_with_flags(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundEntityEffectPacket::new(fields.to_vec())?;
+    let flags: u8 = if packet.hide_particles { 0x00 } else { 0x02 };
+    Ok([
+        packet.entity_id.serialize_varint(),
+        packet.effect_id.serialize(),
+        packet.amplifier.serialize(),
+        packet.duration.serialize_varint(),
+        flags.serialize(),
+    ].concat())
+}
+
+fn tab_complete_without_command_flag(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let cursor = &mut Cursor::new(fields);
+    let text = String::decode(cursor)?;
+    let _assume_command = bool::decode(cursor)?;
+    fields_of(ServerboundTabCompletePacket{
+        text,
+        looked_at_block: Option::<Position>::decode(cursor)?,
+    })
+}
+
+fn client_settings_without_main_hand(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let cursor = &mut Cursor::new(fields);
+    fields_of(ServerboundClientSettingsPacket{
+        locale: String::decode(cursor)?,
+        view_distance: i8::decode(cursor)?,
+        chat_mode: i32::decode_varint(cursor)? as i8,
+        chat_colors: bool::decode(cursor)?,
+        displayed_skin_parts: u8::decode(cursor)?,
+    })
+}
+
+fn use_entity_without_hand(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let cursor = &mut Cursor::new(fields);
+    fields_of(ServerboundUseEntityPacket{
+        target: i32::decode_varint(cursor)?,
+        action: UseEntityAction::decode(cursor)?,
+    })
+}
+
+fn resource_pack_status_without_hash(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    fields_of(ServerboundResourcePackStatusPacket{
+        hash: String::new(),
+        result: i32::decode_varint(&mut Cursor::new(fields))?,
+    })
+}
+
+fn block_placement_with_cursor_floats(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let cursor = &mut Cursor::new(fields);
+    let location = Position::decode(cursor)?;
+    let face = i32::decode_varint(cursor)? as i8;
+    let _hand = i32::decode_varint(cursor)?;
+    // The cursor used to be sent in sixteenths of a block
+    let mut cursor_position = [0i8; 3];
+    for value in &mut cursor_position {
+        *value = (f32::decode(cursor)? * 16.0) as i8;
+    }
+    fields_of(ServerboundPlayerBlockPlacementPacket{
+        location,
+        face,
+        held_item: Slot::default(),
+        cursor_x: cursor_position[0],
+        cursor_y: cursor_position[1],
+        cursor_z: cursor_position[2],
+    })
+}
+
+/// Clients before 1.20.5 always ask the session server once they got an Encryption Request
+fn encryption_request_with_authentication(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    ClientboundEncryptionRequestPacket::new(fields.to_vec())?;
+    Ok([fields, &true.serialize()].concat())
+}
+
+fn encryption_request_without_authentication(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    ClientboundEncryptionRequestPacket::new(fields.to_vec())?;
+    Ok([fields, &false.serialize()].concat())
+}
+
+fn login_success_with_properties(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundLoginSuccesPacket::new(fields.to_vec())?;
+    let uuid = Uuid::parse_str(&packet.uuid).map_err(|err| ProtocolError::InvalidField(format!("uuid: {err}")))?;
+    Ok([
+        uuid.serialize(),
+        packet.username.serialize(),
+        // No properties and no strict error handling
+        0.serialize_varint(),
+        false.serialize(),
+    ].concat())
+}
+
+fn login_start_without_uuid(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let cursor = &mut Cursor::new(fields);
+    let name = String::decode(cursor)?;
+    let _uuid = Uuid::decode(cursor)?;
+    fields_of(ServerboundLoginStartPacket{name})
+}
+
+fn join_game_with_dimension_registry(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundJoinGamePacket::new(fields.to_vec())?;
+    let dimension = match packet.dimension {
+        -1 => "minecraft:the_nether",
+        1 => "minecraft:the_end",
+        _ => "minecraft:overworld",
+    };
+    // The dimension types are the same in every version with the configuration state
+    let dimension_type = configuration::V1_21.index_of("minecraft:dimension_type", dimension)
+        .ok_or_else(|| ProtocolError::InvalidField(format!("dimension {dimension}")))?;
+    let dimensions = ["minecraft:overworld", "minecraft:the_nether", "minecraft:the_end"].map(str::to_owned).to_vec();
+    Ok([
+        packet.id.serialize_fixed(),
+        (packet.gamemode & 0x08 != 0).serialize(),
+        (dimensions.len() as i32).serialize_varint(),
+        dimensions.iter().flat_map(Serializable::serialize).collect(),
+        (packet.max_players as i32).serialize_varint(),
+        // View and simulation distance
+        10.serialize_varint(),
+        10.serialize_varint(),
+        packet.reduced_debug_info.serialize(),
+        // Respawn screen, limited crafting
+        true.serialize(),
+        false.serialize(),
+        (dimension_type as i32).serialize_varint(),
+        dimension.to_owned().serialize(),
+        // Hashed seed
+        0i64.serialize(),
+        (packet.gamemode & 0x07).serialize(),
+        // No previous game mode
+        (-1i8).serialize(),
+        // Debug, flat
+        false.serialize(),
+        (packet.level_type == "flat").serialize(),
+        // No death location, portal cooldown, no secure chat
+        false.serialize(),
+        0.serialize_varint(),
+        false.serialize(),
+    ].concat())
+}
+
+fn disconnect_with_nbt_reason(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundPlayDisconnectPacket::new(fields.to_vec())?;
+    Ok(NetworkNbt::from_json(&packet.reason).serialize())
+}
+
+fn chat_message_to_system_chat(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundChatMessagePacket::new(fields.to_vec())?;
+    let above_hotbar = packet.position == 2;
+    Ok([NetworkNbt::from_json(&packet.json_data).serialize(), above_hotbar.serialize()].concat())
+}
+
+fn chat_message_without_signature(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    // Followed by the timestamp, salt, signature and acknowledged messages, which are ignored
+    let message = String::decode(&mut Cursor::new(fields))?;
+    fields_of(ServerboundChatMessagePacket{message})
+}
+
+/// Channels got namespaced identifiers in 1.13
+const RENAMED_CHANNELS: [(&str, &str); 3] = [
+    ("MC|Brand", "minecraft:brand"),
+    ("REGISTER", "minecraft:register"),
+    ("UNREGISTER", "minecraft:unregister"),
+];
+
+fn plugin_message_with_namespaced_channel(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundPluginMessagePacket::new(fields.to_vec())?;
+    let channel = match RENAMED_CHANNELS.iter().find(|(legacy, _)| *legacy == packet.channel) {
+        Some((_, channel)) => channel.to_string(),
+        None if packet.channel.contains(':') => packet.channel,
+        None => return Err(ProtocolError::InvalidField(format!("channel {} has no namespaced name", packet.channel))),
+    };
+    Ok([channel.serialize(), packet.data].concat())
+}
+
+fn plugin_message_with_legacy_channel(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let cursor = &mut Cursor::new(fields);
+    let channel = String::decode(cursor)?;
+    let channel = match RENAMED_CHANNELS.iter().find(|(_, namespaced)| *namespaced == channel) {
+        Some((legacy, _)) => legacy.to_string(),
+        None => channel,
+    };
+    fields_of(ServerboundPluginMessagePacket{
+        channel,
+        data: fields[cursor.position() as usize..].to_vec(),
+    })
+}
+
+/// Since 1.20.3 the client shows the loading screen until it's told to wait for chunks
+fn join_game_and_wait_for_chunks(fields: &[u8]) -> Result<Vec<Vec<u8>>, ProtocolError> {
+    let packet = ClientboundJoinGamePacket::new(fields.to_vec())?;
+    Ok(vec![packet.serialize_payload(), ClientboundChangeGameStatePacket{reason: 13, value: 0.0}.serialize_payload()])
+}
+
+/// Block states of 1.20.5 and 1.21 for the natural blocks of 1.8, everything else shows as stone
+fn flattened_state(block: Block) -> u32 {
+    match (block.id, block.metadata) {
+        (0, _) => 0,
+        // Stone, granite, polished granite, diorite, polished diorite, andesite, polished andesite
+        (1, variant @ 0..=6) => 1 + variant as u32,
+        (2, _) => 9,
+        (3, 1) => 11,
+        (3, 2) => 13,
+        (3, _) => 10,
+        (4, _) => 14,
+        // Oak to acacia planks, cherry planks came before dark oak
+        (5, variant @ 0..=4) => 15 + variant as u32,
+        (5, 5) => 21,
+        (7, _) => 79,
+        (8 | 9, level) => 80 + level as u32,
+        (10 | 11, level) => 96 + level as u32,
+        (12, 1) => 117,
+        (12, _) => 112,
+        (13, _) => 118,
+        (14, _) => 123,
+        (15, _) => 125,
+        (16, _) => 127,
+        _ => 1,
+    }
+}
+
+/// Bits of a block state of 1.20.5 and 1.21 that isn't in a palette
+const FLATTENED_STATE_BITS: usize = 15;
+/// Since 1.18 the overworld goes from y -64 to 320, 1.8 columns are y 0 to 256 of it
+const SECTIONS_BELOW: usize = 4;
+const SECTIONS_ABOVE: usize = 4;
+/// The only biome in the registries sent to the client
+const PLAINS: u32 = 0;
+
+/// Position packs y into the lowest 12 bits since 1.14
+fn position_with_y_last(position: &Position) -> Vec<u8> {
+    let Position{x, y, z} = *position;
+    (((x as i64 & 0x3FFFFFF) << 38) | ((z as i64 & 0x3FFFFFF) << 12) | (y as i64 & 0xFFF)).serialize()
+}
+
+fn block_change_with_flattened_state(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundBlockChangePacket::new(fields.to_vec())?;
+    let state = flattened_state(Block::from_state(packet.block_id as u16));
+    Ok([position_with_y_last(&packet.location), (state as i32).serialize_varint()].concat())
+}
+
+/// Multi Block Change only covers a section since 1.16, the blocks are sent one by one instead
+fn multi_block_change_to_block_changes(fields: &[u8]) -> Result<Vec<Vec<u8>>, ProtocolError> {
+    let packet = ClientboundMultiBlockChangePacket::new(fields.to_vec())?;
+    Ok(packet.records.iter()
+        .map(|record| ClientboundBlockChangePacket{
+            location: Position{
+                x: packet.chunk_x * SECTION_WIDTH as i32 + (record.horizontal_position >> 4) as i32,
+                y: record.y as i32,
+                z: packet.chunk_z * SECTION_WIDTH as i32 + (record.horizontal_position & 0x0F) as i32,
+            },
+            block_id: record.block_id,
+        }.serialize_payload())
+        .collect())
+}
+
+/// One bit per section, as a length prefixed array of longs
+fn bit_set(bits: u64) -> Vec<u8> {
+    if bits == 0 {
+        return 0.serialize_varint();
+    }
+    [1.serialize_varint(), (bits as i64).serialize()].concat()
+}
+
+/// Since 1.18 every column is sent whole with its height map, and the light came along again in 1.20
+fn chunk_data_with_light(fields: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let packet = ClientboundChunkDataPacket::new(fields.to_vec())?;
+    if !packet.ground_up_continuous {
+        return Err(ProtocolError::InvalidField("chunk data for 1.18+ has to be ground-up continuous".to_owned()));
+    }
+    let column = &packet.column;
+    // Counted from the bottom of the world, 0 where it's air all the way down
+    let heights: Vec<u64> = anvil::height_map(column).into_iter()
+        .map(|height| if height == 0 { 0 } else { (height as usize + SECTIONS_BELOW * SECTION_WIDTH) as u64 })
+        .collect();
+    let world_height = (SECTIONS_BELOW + SECTIONS_PER_COLUMN + SECTIONS_ABOVE) * SECTION_WIDTH;
+    let height_bits = (usize::BITS - world_height.leading_zeros()) as usize;
+    let longs = smpmap::pack_aligned(&heights, height_bits).into_iter().map(|long| long as i64).collect();
+    let height_maps = Tag::Compound(Compound::from([("MOTION_BLOCKING".to_owned(), Tag::LongArray(longs))]));
+
+    let mut data = Vec::new();
+    column.write_container_sections(SECTIONS_BELOW, SECTIONS_ABOVE, flattened_state, FLATTENED_STATE_BITS, PLAINS, &mut data);
+
+    // There is a light section below and above the world
+    let (mut sky_mask, mut block_mask, mut empty_sky_mask, mut empty_block_mask) = (0u64, 0u64, 0u64, 0u64);
+    let (mut sky_light, mut block_light) = (Vec::new(), Vec::new());
+    let full_sky = NibbleArray::filled(15);
+    for index in 0..SECTIONS_BELOW + SECTIONS_PER_COLUMN + SECTIONS_ABOVE + 2 {
+        let y = index.checked_sub(SECTIONS_BELOW + 1);
+        let section = y.and_then(|y| column.section(y));
+        let sky = match (y, section) {
+            _ if !packet.sky_light_sent => None,
+            (None, _) => None,
+            (Some(_), Some(section)) => Some(&section.sky_light),
+            (Some(_), None) => Some(&full_sky),
+        };
+        match sky {
+            Some(light) => {
+                sky_mask |= 1 << index;
+                sky_light.push(light.as_bytes());
+            }
+            None => empty_sky_mask |= 1 << index,
+        }
+        match section {
+            Some(section) => {
+                block_mask |= 1 << index;
+                block_light.push(section.block_light.as_bytes());
+            }
+            None => empty_block_mask |= 1 << index,
+        }
+    }
+    let light_arrays = |arrays: Vec<&[u8]>| -> Vec<u8> {
+        let mut data = (arrays.len() as i32).serialize_varint();
+        for array in arrays {
+            data.extend((array.len() as i32).serialize_varint());
+            data.extend(array);
+        }
+        data
+    };
+
+    Ok([
+        packet.chunk_x.serialize_fixed(),
+        packet.chunk_z.serialize_fixed(),
+        NetworkNbt::from_tag(&height_maps).serialize(),
+        (data.len() as i32).serialize_varint(),
+        data,
+        // No block entities
+        0.serialize_varint(),
+        bit_set(sky_mask),
+        bit_set(block_mask),
+        bit_set(empty_sky_mask),
+        bit_set(empty_block_mask),
+        light_arrays(sky_light),
+        light_arrays(block_light),
+    ].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_versions_are_outdated() {
+        let registries = Registries::default();
+        assert_eq!(registries.find(47).unwrap().version, V1_8);
+        assert_eq!(registries.find(340).unwrap().version, V1_12_2);
+        assert!(matches!(registries.find(5), Err(ProtocolError::OutdatedClient("1.8.9"))));
+        assert!(matches!(registries.find(210), Err(ProtocolError::OutdatedClient("1.12.2"))));
+        assert!(registries.find(767).unwrap().configuration.is_some());
+        assert!(registries.find(766).unwrap().configuration.is_some());
+        assert!(matches!(registries.find(100), Err(ProtocolError::OutdatedClient("1.12.2"))));
+        assert!(matches!(registries.find(768), Err(ProtocolError::OutdatedServer("1.21.1"))));
+        assert_eq!(registries.find(768).err().unwrap().to_string(), "Outdated server! I'm still on 1.21.1");
+    }
+
+    #[test]
+    fn canonical_version_passes_through() {
+        let registry = Registries::default().find(47).unwrap();
+        let payload = ClientboundKeepAlivePacket{id: 5}.serialize_payload();
+        assert_eq!(registry.encode(State::PLAY, payload.clone()).unwrap(), Some(payload));
+        assert_eq!(registry.decode(State::PLAY, vec![0x7F]).unwrap(), Some(vec![0x7F]));
+    }
+
+    #[test]
+    fn newer_versions_translate_ids_and_layouts() {
+        let registry = v1_12_2();
+        let join = ClientboundJoinGamePacket{id: 1, dimension: -1, level_type: "flat".to_owned(), ..Default::default()};
+        let data = registry.encode(State::PLAY, join.serialize_payload()).unwrap().unwrap();
+        assert_eq!(data[..10], [0x23, 0, 0, 0, 1, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let keep_alive = registry.encode(State::PLAY, ClientboundKeepAlivePacket{id: 7}.serialize_payload()).unwrap();
+        assert_eq!(keep_alive, Some(vec![0x1F, 0, 0, 0, 0, 0, 0, 0, 7]));
+        let reply = registry.decode(State::PLAY, vec![0x0B, 0, 0, 0, 0, 0, 0, 0, 7]).unwrap().unwrap();
+        assert_eq!(reply, [ServerboundKeepAlivePacket::ID as u8, 7]);
+
+        let chat = registry.decode(State::PLAY, [vec![0x02], "hi".to_owned().serialize()].concat()).unwrap().unwrap();
+        assert_eq!(chat[0] as i32, ServerboundChatMessagePacket::ID);
+        // Login is unchanged
+        let login = ServerboundLoginStartPacket{name: "Tester".to_owned()}.serialize_payload();
+        assert_eq!(registry.decode(State::LOGIN, login.clone()).unwrap(), Some(login));
+    }
+
+    #[test]
+    fn configuration_versions_translate_login_and_play() {
+        let registry = v1_21(true);
+        let uuid = Uuid::from_u128(1);
+        let login = [vec![0x00], "Tester".to_owned().serialize(), uuid.serialize()].concat();
+        let data = registry.decode(State::LOGIN, login).unwrap().unwrap();
+        assert_eq!(data, ServerboundLoginStartPacket{name: "Tester".to_owned()}.serialize_payload());
+
+        let request = ClientboundEncryptionRequestPacket{server_id: String::new(), public_key: vec![1], verify_token: vec![2]}.serialize_payload();
+        assert_eq!(registry.encode(State::LOGIN, request.clone()).unwrap().unwrap().last(), Some(&0x01));
+        assert_eq!(v1_21(false).encode(State::LOGIN, request).unwrap().unwrap().last(), Some(&0x00));
+
+        let success = ClientboundLoginSuccesPacket{uuid: uuid.hyphenated().to_string(), username: "Tester".to_owned()};
+        let data = registry.encode(State::LOGIN, success.serialize_payload()).unwrap().unwrap();
+        assert_eq!(data, [vec![0x02], uuid.serialize(), "Tester".to_owned().serialize(), vec![0x00, 0x00]].concat());
+
+        let finish = ClientboundFinishConfigurationPacket{}.serialize_payload();
+        assert_eq!(registry.encode(State::CONFIGURATION, finish.clone()).unwrap(), Some(finish));
+        assert_eq!(registry.decode(State::CONFIGURATION, vec![0x03]).unwrap(), Some(vec![0x03]));
+
+        let join = ClientboundJoinGamePacket{id: 1, gamemode: 0x09, ..Default::default()};
+        let data = registry.encode(State::PLAY, join.serialize_payload()).unwrap().unwrap();
+        assert_eq!(data[..7], [0x2B, 0, 0, 0, 1, 0x01, 3]);
+
+        let brand = ClientboundPluginMessagePacket{channel: "MC|Brand".to_owned(), data: vec![1]};
+        let data = registry.encode(State::PLAY, brand.serialize_payload()).unwrap().unwrap();
+        assert_eq!(data, [vec![0x19], "minecraft:brand".to_owned().serialize(), vec![1]].concat());
+        let unnamed = ClientboundPluginMessagePacket{channel: "Legacy".to_owned(), data: Vec::new()};
+        assert!(registry.encode(State::PLAY, unnamed.serialize_payload()).is_err());
+
+        let kick = ClientboundPlayDisconnectPacket{reason: "oops".to_owned()};
+        let data = registry.encode(State::PLAY, kick.serialize_payload()).unwrap().unwrap();
+        assert_eq!(data, [0x1D, 0x08, 0x00, 0x04, b'o', b'o', b'p', b's']);
+        assert_eq!(registry.decode(State::PLAY, vec![0x0B]).unwrap(), None);
+    }
+
+    #[test]
+    fn newer_versions_ignore_or_reject_unknown_packets() {
+        let registry = v1_12_2();
+        assert_eq!(registry.decode(State::PLAY, vec![0x00, 0x01]).unwrap(), None);
+        assert!(matches!(registry.decode(State::PLAY, vec![0x30]), Err(ProtocolError::UnknownPacket{id: 0x30, ..})));
+        // Spawn Player changed too much to be rewritten
+        assert_eq!(registry.encode(State::PLAY, vec![0x0C]).unwrap(), None);
+    }
+
+    #[test]
+    fn chunks_are_split_and_paletted_for_1_12() {
+        use crate::utils::smpmap::{Block, ChunkColumn};
+
+        let mut column = ChunkColumn::default();
+        column.set_block(0, 0, 0, Block::new(1, 0));
+        let bulk = ClientboundMapChunkBulkPacket::from_columns(true, [(1, -2, column.clone()), (3, 4, ChunkColumn::default())]);
+        let packets = v1_12_2().encode_all(State::PLAY, bulk.serialize_payload()).unwrap();
+        assert_eq!(packets.len(), 2);
+
+        let cursor = &mut Cursor::new(&packets[0][..]);
+        assert_eq!(i32::decode_varint(cursor).unwrap(), 0x20);
+        assert_eq!((i32::decode_fixed(cursor).unwrap(), i32::decode_fixed(cursor).unwrap()), (1, -2));
+        assert!(bool::decode(cursor).unwrap());
+        assert_eq!(i32::decode_varint(cursor).unwrap(), 0x01);
+        let mut sections = Vec::new();
+        column.write_paletted_sections(0x01, true, true, &mut sections);
+        assert_eq!(Vec::<u8>::decode(cursor).unwrap(), sections);
+        // No block entities
+        assert_eq!(i32::decode_varint(cursor).unwrap(), 0);
+        assert_eq!(cursor.position() as usize, packets[0].len());
+
+        // Only the biomes of the empty column
+        assert_eq!(packets[1].len(), 1 + 8 + 1 + 1 + 2 + 256 + 1);
+        // The canonical version still gets the bulk
+        let canonical = Registries::default().find(47).unwrap();
+        assert_eq!(canonical.encode_all(State::PLAY, bulk.serialize_payload()).unwrap(), [bulk.serialize_payload()]);
+    }
+
+    #[test]
+    fn configuration_versions_get_the_world() {
+        use crate::utils::smpmap::ChunkColumn;
+        use crate::utils::types::BlockChangeRecord;
+
+        let registry = v1_20_5(true);
+        let join = ClientboundJoinGamePacket{id: 1, ..Default::default()};
+        let packets = registry.encode_all(State::PLAY, join.serialize_payload()).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0][0], 0x2B);
+        assert_eq!(packets[1], [0x22, 13, 0, 0, 0, 0]);
+
+        let mut column = ChunkColumn::default();
+        column.set_block(0, 0, 0, Block::new(1, 0));
+        column.set_block(1, 2, 1, Block::new(35, 14));
+        let bulk = ClientboundMapChunkBulkPacket::from_columns(true, [(1, -2, column.clone())]);
+        let packets = registry.encode_all(State::PLAY, bulk.serialize_payload()).unwrap();
+        assert_eq!(packets.len(), 1);
+        let cursor = &mut Cursor::new(&packets[0][..]);
+        assert_eq!(i32::decode_varint(cursor).unwrap(), 0x27);
+        assert_eq!((i32::decode_fixed(cursor).unwrap(), i32::decode_fixed(cursor).unwrap()), (1, -2));
+        // A nameless compound with a long array of 37 longs, 7 heights of 9 bits each
+        let mut height_maps = vec![0x0A, 0x0C, 0x00, 15];
+        height_maps.extend(b"MOTION_BLOCKING");
+        height_maps.extend(37i32.to_be_bytes());
+        let cursor_at = cursor.position() as usize;
+        assert_eq!(packets[0][cursor_at..cursor_at + height_maps.len()], height_maps);
+        let first = i64::from_be_bytes(packets[0][cursor_at + height_maps.len()..][..8].try_into().unwrap());
+        assert_eq!(first, 65);
+        cursor.set_position((cursor_at + height_maps.len() + 37 * 8 + 1) as u64);
+        let mut sections = Vec::new();
+        column.write_container_sections(4, 4, flattened_state, 15, 0, &mut sections);
+        assert_eq!(Vec::<u8>::decode(cursor).unwrap(), sections);
+        // The wool shows as stone
+        assert_eq!(flattened_state(Block::new(35, 14)), 1);
+        // No block entities
+        assert_eq!(i32::decode_varint(cursor).unwrap(), 0);
+        // Sky light above y 0, but only the bottom section has block light
+        let mut masks = Vec::new();
+        for _ in 0..4 {
+            assert_eq!(i32::decode_varint(cursor).unwrap(), 1);
+            masks.push(i64::decode_fixed(cursor).unwrap());
+        }
+        assert_eq!(masks, [0x3FF_FFE0, 0x20, 0x1F, 0x3FF_FFDF]);
+        assert_eq!(i32::decode_varint(cursor).unwrap(), 21);
+        cursor.set_position(cursor.position() + 21 * 2050);
+        assert_eq!(i32::decode_varint(cursor).unwrap(), 1);
+        assert_eq!(cursor.position() as usize + 2050, packets[0].len());
+
+        let change = ClientboundMultiBlockChangePacket{chunk_x: -1, chunk_z: 1, records: vec![
+            BlockChangeRecord{horizontal_position: 0xF2, y: 64, block_id: 3 << 4 | 1},
+            BlockChangeRecord{horizontal_position: 0x00, y: 0, block_id: 0},
+        ]};
+        let packets = registry.encode_all(State::PLAY, change.serialize_payload()).unwrap();
+        // x -1 is 26 bits set, z 18 and y 64
+        let position = (0x3FFFFFFi64 << 38 | 18 << 12 | 64).to_be_bytes();
+        assert_eq!(packets[0], [&[0x09][..], &position, &[11]].concat());
+        assert_eq!(packets[1][9], 0);
+        assert!(registry.encode(State::PLAY, ClientboundChunkDataPacket{ground_up_continuous: false, ..Default::default()}.serialize_payload()).is_err());
+
+        let on_ground = registry.decode(State::PLAY, vec![0x1D, 0x01]).unwrap().unwrap();
+        assert_eq!(on_ground, ServerboundPlayerPacket{on_ground: true}.serialize_payload());
+        let look = [vec![0x1C], 90f32.serialize(), 0f32.serialize(), vec![0x00]].concat();
+        assert_eq!(registry.decode(State::PLAY, look).unwrap().unwrap()[0] as i32, ServerboundPlayerLookPacket::ID);
+        let players = ClientboundPlayerListItemPacket{action: crate::utils::types::PlayerListAction::RemovePlayer(Vec::new())};
+        assert_eq!(registry.encode(State::PLAY, players.serialize_payload()).unwrap(), None);
+    }
+
+    #[test]
+    fn rewritten_serverbound_packets_decode_canonically() {
+        let registry = v1_12_2();
+        let mut settings = vec![0x04];
+        settings.extend("en_us".to_owned().serialize());
+        settings.extend([12, 0x01, 0x01, 0x7F, 0x01]);
+        let data = registry.decode(State::PLAY, settings).unwrap().unwrap();
+        let (_, fields) = serialization::split_packet_id(&data).unwrap();
+        let packet = ServerboundClientSettingsPacket::new(fields).unwrap();
+        assert_eq!((packet.view_distance, packet.chat_mode, packet.displayed_skin_parts), (12, 1, 0x7F));
+
+        let mut placement = vec![0x1F];
+        placement.extend(Position{x: 1, y: 64, z: -1}.serialize());
+        placement.extend([0x01, 0x00]);
+        for value in [0.5f32, 1.0, 0.0] {
+            placement.extend(value.serialize());
+        }
+        let data = registry.decode(State::PLAY, placement).unwrap().unwrap();
+        let (_, fields) = serialization::split_packet_id(&data).unwrap();
+        let packet = ServerboundPlayerBlockPlacementPacket::new(fields).unwrap();
+        assert_eq!((packet.location, packet.face, packet.cursor_x, packet.cursor_y), (Position{x: 1, y: 64, z: -1}, 1, 8, 16));
+    }
+}
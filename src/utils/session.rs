@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use num_bigint::BigInt;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use uuid::Uuid;
+
+pub const MOJANG_SESSION_SERVER: &str = "https://sessionserver.mojang.com";
+
+/// The profile of an authenticated player as returned by `hasJoined`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GameProfile {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<ProfileProperty>,
+}
+
+/// Signed profile data like the `textures` property holding the skin
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Http(reqwest::Error),
+    InvalidResponse(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Http(err) => write!(f, "Session server request failed: {err}"),
+            SessionError::InvalidResponse(msg) => write!(f, "Invalid session server response: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<reqwest::Error> for SessionError {
+    fn from(err: reqwest::Error) -> Self {
+        SessionError::Http(err)
+    }
+}
+
+#[async_trait]
+pub trait SessionService: Send + Sync {
+    /// Checks that `username` joined the server identified by `server_hash`.
+    /// Returns `None` if the session server doesn't know about the join.
+    async fn has_joined(&self, username: &str, server_hash: &str) -> Result<Option<GameProfile>, SessionError>;
+}
+
+/// Computes the hash the client sent to the session server when joining:
+/// a SHA-1 digest printed as a signed (two's complement) hex number.
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(server_id.as_bytes());
+    hasher.update(shared_secret);
+    hasher.update(public_key);
+    BigInt::from_signed_bytes_be(&hasher.finalize()).to_str_radix(16)
+}
+
+/// Asks a Yggdrasil compatible session server, by default Mojang's.
+pub struct HttpSessionService {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpSessionService {
+    pub fn new(base_url: &str) -> HttpSessionService {
+        HttpSessionService{
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        }
+    }
+}
+
+impl Default for HttpSessionService {
+    fn default() -> Self {
+        HttpSessionService::new(MOJANG_SESSION_SERVER)
+    }
+}
+
+#[async_trait]
+impl SessionService for HttpSessionService {
+    async fn has_joined(&self, username: &str, server_hash: &str) -> Result<Option<GameProfile>, SessionError> {
+        let response = self.client
+            .get(format!("{}/session/minecraft/hasJoined", self.base_url))
+            .query(&[("username", username), ("serverId", server_hash)])
+            .send()
+            .await?
+            .error_for_status()?;
+        // 204 No Content means the player didn't join
+        if response.status() == reqwest::StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        let body = response.bytes().await?;
+        serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(|err| SessionError::InvalidResponse(err.to_string()))
+    }
+}
+
+/// In-process session service for tests and offline development.
+/// Players are registered with [`MockSessionService::join`], the counterpart of the client's join request.
+#[derive(Default)]
+pub struct MockSessionService {
+    joins: Mutex<HashMap<String, (String, GameProfile)>>,
+}
+
+impl MockSessionService {
+    pub fn new() -> MockSessionService {
+        Default::default()
+    }
+
+    pub fn join(&self, profile: GameProfile, server_hash: &str) {
+        self.joins.lock().unwrap().insert(profile.name.clone(), (server_hash.to_owned(), profile));
+    }
+}
+
+#[async_trait]
+impl SessionService for MockSessionService {
+    async fn has_joined(&self, username: &str, server_hash: &str) -> Result<Option<GameProfile>, SessionError> {
+        Ok(self.joins.lock().unwrap().get(username)
+            .filter(|(hash, _)| hash == server_hash)
+            .map(|(_, profile)| profile.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str) -> GameProfile {
+        GameProfile{
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            properties: vec![ProfileProperty{
+                name: "textures".to_owned(),
+                value: "e30=".to_owned(),
+                signature: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn server_hash_matches_vanilla_digests() {
+        // Known values from wiki.vg, hashing only the name
+        assert_eq!(server_hash("Notch", &[], &[]), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(server_hash("jeb_", &[], &[]), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(server_hash("simon", &[], &[]), "88e16a1019277b15d58faf0541e11910eb756f6");
+    }
+
+    #[tokio::test]
+    async fn mock_returns_profile_for_matching_hash() {
+        let service = MockSessionService::new();
+        let hash = server_hash("", &[1; 16], &[2; 162]);
+        let player = profile("Tester");
+        service.join(player.clone(), &hash);
+
+        assert_eq!(service.has_joined("Tester", &hash).await.unwrap(), Some(player));
+    }
+
+    #[tokio::test]
+    async fn mock_rejects_unknown_player_and_wrong_hash() {
+        let service = MockSessionService::new();
+        service.join(profile("Tester"), &server_hash("", &[1; 16], &[2; 162]));
+
+        assert_eq!(service.has_joined("Other", "abc").await.unwrap(), None);
+        assert_eq!(service.has_joined("Tester", &server_hash("", &[3; 16], &[2; 162])).await.unwrap(), None);
+    }
+
+    #[test]
+    fn profile_parses_session_server_json() {
+        let json = r#"{"id":"4566e69fc90748ee8d71d7ba5aa00d20","name":"thinkofdeath","properties":[{"name":"textures","value":"abc","signature":"sig"}]}"#;
+        let profile: GameProfile = serde_json::from_str(json).unwrap();
+        assert_eq!(profile.id, Uuid::parse_str("4566e69f-c907-48ee-8d71-d7ba5aa00d20").unwrap());
+        assert_eq!(profile.properties[0].signature.as_deref(), Some("sig"));
+    }
+}
@@ -1,13 +1,499 @@
-pub struct ChunkMeta {
-    chunk_x: Int,
-    chunk_y: Int,
-    primary_bit_mask: u16
+//! Chunk storage, laid out like the `World`/`ChunkColumn`/`Chunk` classes of the bundled `smpmap.py`:
+//! a column is 16 sections stacked on top of each other, sections that were never written to are `None`.
+
+use std::fmt;
+use std::io::Cursor;
+
+use super::error::ProtocolError;
+use super::packets::serialization::{self, VarIntEncoded};
+
+/// Blocks along each side of a section
+pub const SECTION_WIDTH: usize = 16;
+pub const SECTION_VOLUME: usize = SECTION_WIDTH * SECTION_WIDTH * SECTION_WIDTH;
+pub const SECTIONS_PER_COLUMN: usize = 16;
+pub const COLUMN_HEIGHT: usize = SECTION_WIDTH * SECTIONS_PER_COLUMN;
+pub const BIOME_AREA: usize = SECTION_WIDTH * SECTION_WIDTH;
+
+/// A block type with its 4 bit metadata
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Block {
+    pub id: u16,
+    pub metadata: u8,
+}
+
+impl Block {
+    pub const AIR: Block = Block{id: 0, metadata: 0};
+
+    pub fn new(id: u16, metadata: u8) -> Block {
+        Block{id, metadata: metadata & 0x0F}
+    }
+
+    /// Id in the upper 12 bits and metadata in the lower 4, how 1.8 sends blocks
+    pub fn from_state(state: u16) -> Block {
+        Block{id: state >> 4, metadata: (state & 0x0F) as u8}
+    }
+
+    pub fn state(&self) -> u16 {
+        self.id << 4 | (self.metadata & 0x0F) as u16
+    }
+}
+
+/// 4 bits per entry, the even index in the low nibble like vanilla's `NibbleArray`
+#[derive(Clone, PartialEq, Eq)]
+pub struct NibbleArray {
+    data: Vec<u8>,
+}
+
+impl NibbleArray {
+    pub fn filled(value: u8) -> NibbleArray {
+        let value = value & 0x0F;
+        NibbleArray{data: vec![value << 4 | value; SECTION_VOLUME / 2]}
+    }
+
+    /// Wraps 2048 bytes as stored in chunk packets and region files
+    pub fn from_bytes(data: Vec<u8>) -> Option<NibbleArray> {
+        (data.len() == SECTION_VOLUME / 2).then_some(NibbleArray{data})
+    }
+
+    pub fn get(&self, index: usize) -> u8 {
+        let byte = self.data[index >> 1];
+        if index & 1 == 0 { byte & 0x0F } else { byte >> 4 }
+    }
+
+    pub fn set(&mut self, index: usize, value: u8) {
+        let byte = &mut self.data[index >> 1];
+        if index & 1 == 0 {
+            *byte = (*byte & 0xF0) | (value & 0x0F);
+        } else {
+            *byte = (*byte & 0x0F) | (value & 0x0F) << 4;
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl fmt::Debug for NibbleArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NibbleArray({} bytes)", self.data.len())
+    }
+}
+
+/// Index of a block inside a section, y major then z then x
+pub fn section_index(x: usize, y: usize, z: usize) -> usize {
+    (y << 8) | (z << 4) | x
+}
+
+/// A 16x16x16 cube of blocks with its light
+#[derive(Clone, PartialEq, Eq)]
+pub struct ChunkSection {
+    /// Block states as in [`Block::state`]
+    blocks: Vec<u16>,
+    pub block_light: NibbleArray,
+    pub sky_light: NibbleArray,
+}
+
+impl Default for ChunkSection {
+    /// All air, lit by the sky
+    fn default() -> Self {
+        ChunkSection{
+            blocks: vec![0; SECTION_VOLUME],
+            block_light: NibbleArray::filled(0),
+            sky_light: NibbleArray::filled(15),
+        }
+    }
+}
+
+impl ChunkSection {
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> Block {
+        Block::from_state(self.blocks[section_index(x, y, z)])
+    }
+
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: Block) {
+        self.blocks[section_index(x, y, z)] = block.state();
+    }
+
+    /// Block states indexed by [`section_index`]
+    pub fn states(&self) -> &[u16] {
+        &self.blocks
+    }
+
+    pub fn states_mut(&mut self) -> &mut [u16] {
+        &mut self.blocks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|&state| state == 0)
+    }
+}
+
+impl fmt::Debug for ChunkSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let non_air = self.blocks.iter().filter(|&&state| state != 0).count();
+        f.debug_struct("ChunkSection").field("non_air", &non_air).finish()
+    }
+}
+
+/// A full height 16x256x16 column of sections with its biomes.
+/// Coordinates are local to the column, y goes from 0 to 255.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ChunkColumn {
+    pub sections: [Option<Box<ChunkSection>>; SECTIONS_PER_COLUMN],
+    /// One biome id per x/z, indexed by `z * 16 + x`
+    pub biomes: Vec<u8>,
+}
+
+impl Default for ChunkColumn {
+    fn default() -> Self {
+        ChunkColumn{
+            sections: Default::default(),
+            biomes: vec![0; BIOME_AREA],
+        }
+    }
 }
 
-pub struct ChunkData {
+impl ChunkColumn {
+    pub fn section(&self, y: usize) -> Option<&ChunkSection> {
+        self.sections.get(y)?.as_deref()
+    }
+
+    /// The section at this height, created if it doesn't exist yet
+    pub fn section_mut(&mut self, y: usize) -> &mut ChunkSection {
+        self.sections[y].get_or_insert_with(Default::default)
+    }
+
+    /// One bit per section that exists, the lowest bit being the bottom section
+    pub fn primary_bit_mask(&self) -> u16 {
+        self.sections.iter().enumerate()
+            .filter(|(_, section)| section.is_some())
+            .fold(0, |mask, (y, _)| mask | 1 << y)
+    }
+
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> Block {
+        self.section(y / SECTION_WIDTH)
+            .map_or(Block::AIR, |section| section.get_block(x, y % SECTION_WIDTH, z))
+    }
+
+    /// Setting air where there is no section doesn't create one
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: Block) {
+        if block == Block::AIR && self.section(y / SECTION_WIDTH).is_none() {
+            return;
+        }
+        self.section_mut(y / SECTION_WIDTH).set_block(x, y % SECTION_WIDTH, z, block);
+    }
+
+    pub fn get_block_light(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.section(y / SECTION_WIDTH)
+            .map_or(0, |section| section.block_light.get(section_index(x, y % SECTION_WIDTH, z)))
+    }
+
+    pub fn set_block_light(&mut self, x: usize, y: usize, z: usize, light: u8) {
+        self.section_mut(y / SECTION_WIDTH).block_light.set(section_index(x, y % SECTION_WIDTH, z), light);
+    }
+
+    /// Full sky light where there is no section
+    pub fn get_sky_light(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.section(y / SECTION_WIDTH)
+            .map_or(15, |section| section.sky_light.get(section_index(x, y % SECTION_WIDTH, z)))
+    }
+
+    pub fn set_sky_light(&mut self, x: usize, y: usize, z: usize, light: u8) {
+        self.section_mut(y / SECTION_WIDTH).sky_light.set(section_index(x, y % SECTION_WIDTH, z), light);
+    }
+
+    pub fn get_biome(&self, x: usize, z: usize) -> u8 {
+        self.biomes[z * SECTION_WIDTH + x]
+    }
+
+    pub fn set_biome(&mut self, x: usize, z: usize, biome: u8) {
+        self.biomes[z * SECTION_WIDTH + x] = biome;
+    }
+}
+
+/// Bytes a section takes in chunk packets: little endian block states and block light, then sky light if sent
+const SECTION_BYTES: usize = SECTION_VOLUME * 2 + SECTION_VOLUME / 2;
+const SKY_LIGHT_BYTES: usize = SECTION_VOLUME / 2;
+
+impl ChunkColumn {
+    /// Size of the data written by [`ChunkColumn::write_sections`]
+    pub fn data_size(mask: u16, sky_light: bool, biomes: bool) -> usize {
+        let sections = mask.count_ones() as usize;
+        sections * SECTION_BYTES
+            + if sky_light { sections * SKY_LIGHT_BYTES } else { 0 }
+            + if biomes { BIOME_AREA } else { 0 }
+    }
+
+    /// Appends the sections in `mask` in the 1.8 layout: the block states of every section,
+    /// then every section's block light, then every section's sky light and at last the biomes.
+    /// Sections of the mask that don't exist are sent as air.
+    pub fn write_sections(&self, mask: u16, sky_light: bool, biomes: bool, buffer: &mut Vec<u8>) {
+        let empty = ChunkSection::default();
+        let sections: Vec<&ChunkSection> = (0..SECTIONS_PER_COLUMN)
+            .filter(|y| mask & 1 << y != 0)
+            .map(|y| self.section(y).unwrap_or(&empty))
+            .collect();
+        buffer.reserve(Self::data_size(mask, sky_light, biomes));
+        for section in &sections {
+            for state in section.states() {
+                buffer.extend(state.to_le_bytes());
+            }
+        }
+        for section in &sections {
+            buffer.extend(section.block_light.as_bytes());
+        }
+        if sky_light {
+            for section in &sections {
+                buffer.extend(section.sky_light.as_bytes());
+            }
+        }
+        if biomes {
+            buffer.extend(&self.biomes);
+        }
+    }
+
+    /// Reads what [`ChunkColumn::write_sections`] wrote, replacing the sections in `mask`
+    pub fn read_sections(&mut self, cursor: &mut Cursor<&[u8]>, mask: u16, sky_light: bool, biomes: bool) -> Result<(), ProtocolError> {
+        let heights: Vec<usize> = (0..SECTIONS_PER_COLUMN).filter(|y| mask & 1 << y != 0).collect();
+        for &y in &heights {
+            let section = self.sections[y].insert(Default::default());
+            let data = serialization::take(cursor, SECTION_VOLUME * 2)?;
+            for (state, bytes) in section.blocks.iter_mut().zip(data.chunks_exact(2)) {
+                *state = u16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+        }
+        let read_light = |cursor: &mut Cursor<&[u8]>| {
+            serialization::take(cursor, SECTION_VOLUME / 2).map(|data| NibbleArray{data: data.to_vec()})
+        };
+        for &y in &heights {
+            self.section_mut(y).block_light = read_light(cursor)?;
+        }
+        if sky_light {
+            for &y in &heights {
+                self.section_mut(y).sky_light = read_light(cursor)?;
+            }
+        }
+        if biomes {
+            self.biomes = serialization::take(cursor, BIOME_AREA)?.to_vec();
+        }
+        Ok(())
+    }
+
+    /// Appends the sections in `mask` in the layout of 1.9 to 1.12: every section on its own
+    /// with its block states packed into longs, followed by its light. The biomes come last.
+    pub fn write_paletted_sections(&self, mask: u16, sky_light: bool, biomes: bool, buffer: &mut Vec<u8>) {
+        let empty = ChunkSection::default();
+        for y in (0..SECTIONS_PER_COLUMN).filter(|y| mask & 1 << y != 0) {
+            let section = self.section(y).unwrap_or(&empty);
+            // The global palette is the block id and metadata, like the states of 1.8
+            buffer.push(GLOBAL_PALETTE_BITS as u8);
+            buffer.extend(0.serialize_varint());
+            let longs = pack_states(section.states());
+            buffer.extend((longs.len() as i32).serialize_varint());
+            for long in longs {
+                buffer.extend(long.to_be_bytes());
+            }
+            buffer.extend(section.block_light.as_bytes());
+            if sky_light {
+                buffer.extend(section.sky_light.as_bytes());
+            }
+        }
+        if biomes {
+            buffer.extend(&self.biomes);
+        }
+    }
+
+    /// Appends every section in the layout of 1.18+, where the world reaches `below` sections under
+    /// y 0 and `above` sections over the top. A section is its count of blocks that aren't air, then its
+    /// block states and biomes as paletted containers. `flatten` turns 1.8 states into the version's,
+    /// which are written with `state_bits` once they don't fit a palette. The light is sent on its own.
+    pub fn write_container_sections(&self, below: usize, above: usize, flatten: impl Fn(Block) -> u32, state_bits: usize, biome: u32, buffer: &mut Vec<u8>) {
+        let air = flatten(Block::AIR);
+        let sections = (0..below).map(|_| None)
+            .chain(self.sections.iter().map(Option::as_deref))
+            .chain((0..above).map(|_| None));
+        for section in sections {
+            let states: Vec<u32> = match section {
+                Some(section) => section.states().iter().map(|&state| flatten(Block::from_state(state))).collect(),
+                None => vec![air],
+            };
+            let count = states.iter().filter(|&&state| state != air).count();
+            buffer.extend((count as i16).to_be_bytes());
+            write_container(&states, 4, state_bits, buffer);
+            write_container(&[biome], 1, 0, buffer);
+        }
+    }
+}
+
+/// Writes the values as a paletted container of 1.18+: a single value without data, palette
+/// indices of at least `min_bits` bits, or the values themselves with `direct_bits` if the palette
+/// would need more than 8 bits
+fn write_container(values: &[u32], min_bits: usize, direct_bits: usize, buffer: &mut Vec<u8>) {
+    let mut palette = Vec::new();
+    for value in values {
+        if !palette.contains(value) {
+            palette.push(*value);
+        }
+    }
+    if let [value] = palette[..] {
+        buffer.push(0);
+        buffer.extend((value as i32).serialize_varint());
+        buffer.extend(0.serialize_varint());
+        return;
+    }
+    let bits = (usize::BITS - (palette.len() - 1).leading_zeros()) as usize;
+    let longs = if bits <= 8 {
+        let bits = bits.max(min_bits);
+        buffer.push(bits as u8);
+        buffer.extend((palette.len() as i32).serialize_varint());
+        for value in &palette {
+            buffer.extend((*value as i32).serialize_varint());
+        }
+        let indices: Vec<u64> = values.iter().map(|value| palette.iter().position(|entry| entry == value).unwrap() as u64).collect();
+        pack_aligned(&indices, bits)
+    } else {
+        buffer.push(direct_bits as u8);
+        let values: Vec<u64> = values.iter().map(|&value| value as u64).collect();
+        pack_aligned(&values, direct_bits)
+    };
+    buffer.extend((longs.len() as i32).serialize_varint());
+    for long in longs {
+        buffer.extend(long.to_be_bytes());
+    }
 }
 
-pub struct ChunkBulkArray {
-    chunk_meta: Vec<ChunkMeta>,
-    chunks_data: Vec<ChunkData>
-}
\ No newline at end of file
+/// Packs the values starting at the lowest bits like 1.16+, a value that doesn't fit in the rest
+/// of a long starts the next one
+pub fn pack_aligned(values: &[u64], bits: usize) -> Vec<u64> {
+    let per_long = 64 / bits;
+    let mut longs = vec![0u64; values.len().div_ceil(per_long)];
+    for (i, value) in values.iter().enumerate() {
+        longs[i / per_long] |= value << (i % per_long * bits);
+    }
+    longs
+}
+
+/// Bits per block state when sending the states themselves instead of palette indices
+const GLOBAL_PALETTE_BITS: usize = 13;
+
+/// Packs the states back to back starting at the lowest bits, states may span two longs
+fn pack_states(states: &[u16]) -> Vec<u64> {
+    let mask = (1 << GLOBAL_PALETTE_BITS) - 1;
+    let mut longs = vec![0u64; states.len() * GLOBAL_PALETTE_BITS / 64];
+    for (i, &state) in states.iter().enumerate() {
+        let value = state as u64 & mask;
+        let bit = i * GLOBAL_PALETTE_BITS;
+        let (index, offset) = (bit / 64, bit % 64);
+        longs[index] |= value << offset;
+        if offset + GLOBAL_PALETTE_BITS > 64 {
+            longs[index + 1] |= value >> (64 - offset);
+        }
+    }
+    longs
+}
+
+impl fmt::Debug for ChunkColumn {
+    // The block data is far too big for the packet trace
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkColumn")
+            .field("primary_bit_mask", &format_args!("{:#06x}", self.primary_bit_mask()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nibbles_keep_the_even_index_in_the_low_bits() {
+        let mut nibbles = NibbleArray::filled(0);
+        nibbles.set(0, 0x0A);
+        nibbles.set(1, 0x13);
+        nibbles.set(4095, 7);
+        assert_eq!(nibbles.as_bytes()[0], 0x3A);
+        assert_eq!((nibbles.get(0), nibbles.get(1), nibbles.get(2)), (0x0A, 0x03, 0));
+        assert_eq!(nibbles.as_bytes()[2047], 0x70);
+        assert!(NibbleArray::from_bytes(vec![0; 100]).is_none());
+    }
+
+    #[test]
+    fn blocks_are_stored_by_local_coordinates() {
+        let mut column = ChunkColumn::default();
+        assert_eq!(column.get_block(3, 70, 9), Block::AIR);
+        column.set_block(0, 200, 0, Block::AIR);
+        assert_eq!(column.primary_bit_mask(), 0);
+
+        let stone = Block::new(1, 0);
+        let wool = Block::new(35, 14);
+        column.set_block(3, 70, 9, wool);
+        column.set_block(15, 0, 15, stone);
+        assert_eq!(column.get_block(3, 70, 9), wool);
+        assert_eq!(column.get_block(15, 0, 15), stone);
+        assert_eq!(column.get_block(9, 70, 3), Block::AIR);
+        assert_eq!(column.primary_bit_mask(), 0b10001);
+        assert_eq!(column.section(4).unwrap().states()[section_index(3, 6, 9)], 35 << 4 | 14);
+        assert_eq!(Block::from_state(wool.state()), wool);
+    }
+
+    #[test]
+    fn light_and_biomes() {
+        let mut column = ChunkColumn::default();
+        assert_eq!((column.get_block_light(1, 2, 3), column.get_sky_light(1, 2, 3)), (0, 15));
+        column.set_block_light(1, 255, 3, 14);
+        column.set_sky_light(1, 255, 3, 4);
+        assert_eq!((column.get_block_light(1, 255, 3), column.get_sky_light(1, 255, 3)), (14, 4));
+        assert_eq!(column.get_sky_light(2, 255, 3), 15);
+        assert_eq!(column.primary_bit_mask(), 0x8000);
+
+        column.set_biome(15, 1, 21);
+        assert_eq!(column.get_biome(15, 1), 21);
+        assert_eq!(column.biomes[31], 21);
+    }
+
+    #[test]
+    fn paletted_sections_pack_states_across_longs() {
+        let mut states = vec![0; SECTION_VOLUME];
+        states[0] = 1 << 4;
+        // Bits 52 to 64, the last bit lands in the second long
+        states[4] = 0x1FFF;
+        let longs = pack_states(&states);
+        assert_eq!(longs.len(), 832);
+        assert_eq!(longs[0], 0x10 | 0xFFF << 52);
+        assert_eq!(longs[1], 0x01);
+
+        let mut column = ChunkColumn::default();
+        column.set_block(0, 16, 0, Block::new(1, 0));
+        let mut data = Vec::new();
+        column.write_paletted_sections(column.primary_bit_mask(), true, true, &mut data);
+        assert_eq!(data[..4], [13, 0x00, 0xC0, 0x06]);
+        assert_eq!(data[4..12], [0, 0, 0, 0, 0, 0, 0, 0x10]);
+        assert_eq!(data.len(), 4 + 832 * 8 + 2048 + 2048 + 256);
+    }
+
+    #[test]
+    fn container_sections_use_the_smallest_palette() {
+        let mut column = ChunkColumn::default();
+        column.set_block(1, 0, 0, Block::new(1, 0));
+        column.set_block(2, 0, 0, Block::new(1, 0));
+        column.set_block(3, 0, 0, Block::new(3, 0));
+        let mut data = Vec::new();
+        column.write_container_sections(1, 0, |block| block.state() as u32, 15, 0, &mut data);
+
+        // The air section below has a single value and neither does the biome need data
+        let single_air = [0x00, 0x00, 0, 0x00, 0x00, 0, 0x00, 0x00];
+        assert_eq!(data[..8], single_air);
+        // 3 blocks of stone and dirt, the palette is in the order the states come in
+        assert_eq!(data[8..15], [0x00, 0x03, 4, 3, 0x00, 0x10, 0x30]);
+        assert_eq!(data[15..17], [0x80, 0x02]);
+        assert_eq!(data[17..25], 0x2110u64.to_be_bytes());
+        let rest = &data[17 + 256 * 8..];
+        assert_eq!(rest[..3], [0, 0x00, 0x00]);
+        // 15 more sections without blocks
+        assert_eq!(rest[3..], single_air.repeat(15)[..]);
+
+        // 7 values of 9 bits fit a long, the 8th starts the next one
+        let first = (0..7).fold(0, |long, i| long | (i + 1) << (9 * i));
+        assert_eq!(pack_aligned(&[1, 2, 3, 4, 5, 6, 7, 8], 9), [first, 8]);
+    }
+}
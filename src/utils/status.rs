@@ -0,0 +1,149 @@
+use std::fmt;
+use std::sync::Arc;
+
+use base64::Engine;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Players listed when hovering over the player count, like vanilla
+pub const MAX_SAMPLE_PLAYERS: usize = 12;
+
+/// The server list entry, sent as JSON in the Status Response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusResponse {
+    pub version: StatusVersion,
+    pub players: StatusPlayers,
+    /// The MOTD as a chat component
+    pub description: serde_json::Value,
+    /// A data URL made by [`favicon_from_png`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    pub enforces_secure_chat: bool,
+}
+
+/// Clients with another protocol version are shown the name in red
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatusVersion {
+    pub name: String,
+    pub protocol: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatusPlayers {
+    pub max: u32,
+    pub online: u32,
+    pub sample: Vec<PlayerSample>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: Uuid,
+}
+
+/// The players the server reports for a status response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OnlinePlayers {
+    pub count: usize,
+    /// At most [`MAX_SAMPLE_PLAYERS`]
+    pub sample: Vec<PlayerSample>,
+}
+
+impl StatusResponse {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("status responses are valid JSON")
+    }
+}
+
+/// What the client sent in its handshake before asking for the status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusRequest {
+    pub protocol_version: i32,
+    /// The address the player typed in, e.g. to answer differently per virtual host
+    pub server_address: String,
+    pub server_port: u16,
+}
+
+/// Lets embedding code change the response to every status request
+pub type StatusHook = Arc<dyn Fn(&StatusRequest, &mut StatusResponse) + Send + Sync>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FaviconError {
+    NotPng,
+    /// Holds the actual width and height
+    WrongSize(u32, u32),
+}
+
+impl fmt::Display for FaviconError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaviconError::NotPng => write!(f, "Favicon is not a PNG"),
+            FaviconError::WrongSize(width, height) => write!(f, "Favicon must be 64x64 pixels, not {width}x{height}"),
+        }
+    }
+}
+
+impl std::error::Error for FaviconError {}
+
+/// Turns a 64x64 PNG (vanilla's `server-icon.png`) into the data URL clients expect
+pub fn favicon_from_png(png: &[u8]) -> Result<String, FaviconError> {
+    const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+    // The signature is followed by the IHDR chunk: length, type, width, height
+    if png.len() < 24 || png[..8] != SIGNATURE || &png[12..16] != b"IHDR" {
+        return Err(FaviconError::NotPng);
+    }
+    let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+    let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]);
+    if (width, height) != (64, 64) {
+        return Err(FaviconError::WrongSize(width, height));
+    }
+    Ok(format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', 0, 0, 0, 13];
+        png.extend(b"IHDR");
+        png.extend(width.to_be_bytes());
+        png.extend(height.to_be_bytes());
+        png
+    }
+
+    #[test]
+    fn responses_use_the_vanilla_json_layout() {
+        let response = StatusResponse{
+            version: StatusVersion{name: "1.8.9".to_owned(), protocol: 47},
+            players: StatusPlayers{
+                max: 20,
+                online: 1,
+                sample: vec![PlayerSample{name: "Tester".to_owned(), id: Uuid::from_u128(1)}],
+            },
+            description: crate::utils::other::chat_text_value("Hello world"),
+            favicon: None,
+            enforces_secure_chat: false,
+        };
+        let json: serde_json::Value = serde_json::from_str(&response.to_json()).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "version": {"name": "1.8.9", "protocol": 47},
+            "players": {
+                "max": 20,
+                "online": 1,
+                "sample": [{"name": "Tester", "id": "00000000-0000-0000-0000-000000000001"}],
+            },
+            "description": {"text": "Hello world"},
+            "enforcesSecureChat": false,
+        }));
+    }
+
+    #[test]
+    fn favicons_must_be_64x64_pngs() {
+        let favicon = favicon_from_png(&png_header(64, 64)).unwrap();
+        assert!(favicon.starts_with("data:image/png;base64,iVBORw0KGgo"));
+        assert_eq!(favicon_from_png(&png_header(128, 64)), Err(FaviconError::WrongSize(128, 64)));
+        assert_eq!(favicon_from_png(b"GIF89a"), Err(FaviconError::NotPng));
+    }
+}
@@ -0,0 +1,1038 @@
+use std::io::Cursor;
+
+use uuid::Uuid;
+
+use super::error::ProtocolError;
+use super::nbt::{self, NbtError, Tag};
+use super::packets::serialization::{self, Decode, FixedEncoded, Serializable, VarIntEncoded, VarLong};
+
+/// NBT tags nested deeper than this are rejected instead of overflowing the stack
+const MAX_NBT_DEPTH: usize = 512;
+
+/// A named NBT compound kept in its binary form, packets only pass it along.
+/// Sent as a single 0x00 (TAG_End) when there is none, which `data` being empty stands for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RawNbt {
+    pub data: Vec<u8>,
+}
+
+impl RawNbt {
+    pub fn from_tag(name: &str, tag: &Tag) -> RawNbt {
+        RawNbt{data: nbt::write_named(name, tag)}
+    }
+
+    /// The root's name and tag, `None` if there is none
+    pub fn to_tag(&self) -> Result<Option<(String, Tag)>, NbtError> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+        nbt::read_named(&self.data).map(Some)
+    }
+}
+
+impl Serializable for RawNbt {
+    fn serialize(&self) -> Vec<u8> {
+        if self.data.is_empty() {
+            vec![0x00]
+        } else {
+            self.data.clone()
+        }
+    }
+}
+
+impl Decode for RawNbt {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        let start = cursor.position() as usize;
+        let tag = u8::decode(cursor)?;
+        if tag == 0 {
+            return Ok(RawNbt::default());
+        }
+        skip_nbt_string(cursor)?;
+        skip_nbt_payload(cursor, tag, 0)?;
+        let end = cursor.position() as usize;
+        Ok(RawNbt{
+            data: cursor.get_ref()[start..end].to_vec(),
+        })
+    }
+}
+
+fn skip_nbt_string(cursor: &mut Cursor<&[u8]>) -> Result<(), ProtocolError> {
+    let len = u16::decode(cursor)?;
+    serialization::take(cursor, len as usize)?;
+    Ok(())
+}
+
+/// Walks over the payload of a tag to find where it ends
+fn skip_nbt_payload(cursor: &mut Cursor<&[u8]>, tag: u8, depth: usize) -> Result<(), ProtocolError> {
+    if depth > MAX_NBT_DEPTH {
+        return Err(ProtocolError::InvalidField("NBT is nested too deep".to_owned()));
+    }
+    let array_length = |cursor: &mut Cursor<&[u8]>| {
+        serialization::read_length(cursor, serialization::LengthPrefix::Int)
+    };
+    match tag {
+        1 => { serialization::take(cursor, 1)?; }
+        2 => { serialization::take(cursor, 2)?; }
+        3 | 5 => { serialization::take(cursor, 4)?; }
+        4 | 6 => { serialization::take(cursor, 8)?; }
+        7 => {
+            let len = array_length(cursor)?;
+            serialization::take(cursor, len)?;
+        }
+        8 => skip_nbt_string(cursor)?,
+        9 => {
+            let element = u8::decode(cursor)?;
+            let len = array_length(cursor)?;
+            for _ in 0..len {
+                skip_nbt_payload(cursor, element, depth + 1)?;
+            }
+        }
+        10 => loop {
+            let tag = u8::decode(cursor)?;
+            if tag == 0 {
+                break;
+            }
+            skip_nbt_string(cursor)?;
+            skip_nbt_payload(cursor, tag, depth + 1)?;
+        },
+        11 => {
+            let len = array_length(cursor)?;
+            serialization::take(cursor, len.checked_mul(4).ok_or(ProtocolError::UnexpectedEnd)?)?;
+        }
+        12 => {
+            let len = array_length(cursor)?;
+            serialization::take(cursor, len.checked_mul(8).ok_or(ProtocolError::UnexpectedEnd)?)?;
+        }
+        tag => return Err(ProtocolError::InvalidField(format!("unknown NBT tag {tag}"))),
+    }
+    Ok(())
+}
+
+/// An NBT tag without a root name, which is how 1.20.2+ clients send NBT.
+/// Like [`RawNbt`] it stays binary, empty `data` stands for a single 0x00 (TAG_End).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkNbt {
+    pub data: Vec<u8>,
+}
+
+impl NetworkNbt {
+    pub fn from_tag(tag: &Tag) -> NetworkNbt {
+        // A root named "" without the two bytes of its name length
+        let mut data = nbt::write_named("", tag);
+        data.drain(1..3);
+        NetworkNbt{data}
+    }
+
+    /// Converts a JSON chat component, 1.20.3+ clients only take them as NBT.
+    /// Anything that isn't JSON is sent as plain text.
+    pub fn from_json(json: &str) -> NetworkNbt {
+        let value = serde_json::from_str(json).unwrap_or_else(|_| serde_json::Value::String(json.to_owned()));
+        let mut data = Vec::new();
+        let tag = json_tag(&value).unwrap_or(8);
+        data.push(tag);
+        write_json_payload(&mut data, &value);
+        NetworkNbt{data}
+    }
+}
+
+impl Serializable for NetworkNbt {
+    fn serialize(&self) -> Vec<u8> {
+        if self.data.is_empty() {
+            vec![0x00]
+        } else {
+            self.data.clone()
+        }
+    }
+}
+
+impl Decode for NetworkNbt {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        let start = cursor.position() as usize;
+        let tag = u8::decode(cursor)?;
+        if tag == 0 {
+            return Ok(NetworkNbt::default());
+        }
+        skip_nbt_payload(cursor, tag, 0)?;
+        let end = cursor.position() as usize;
+        Ok(NetworkNbt{
+            data: cursor.get_ref()[start..end].to_vec(),
+        })
+    }
+}
+
+/// The NBT tag a JSON value becomes, `None` for null which is left out
+fn json_tag(value: &serde_json::Value) -> Option<u8> {
+    use serde_json::Value;
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(_) => 1,
+        Value::Number(number) => match number.as_i64() {
+            Some(number) if i32::try_from(number).is_ok() => 3,
+            Some(_) => 4,
+            None => 6,
+        },
+        Value::String(_) => 8,
+        Value::Array(_) => 9,
+        Value::Object(_) => 10,
+    })
+}
+
+fn write_nbt_string(data: &mut Vec<u8>, text: &str) {
+    // Java's modified UTF-8 only differs for NUL and characters outside the BMP
+    let bytes = &text.as_bytes()[..text.len().min(u16::MAX as usize)];
+    data.extend((bytes.len() as u16).serialize());
+    data.extend(bytes);
+}
+
+fn write_json_payload(data: &mut Vec<u8>, value: &serde_json::Value) {
+    use serde_json::Value;
+    match value {
+        Value::Null => {}
+        Value::Bool(value) => data.push(*value as u8),
+        Value::Number(number) => match (number.as_i64(), json_tag(value)) {
+            (Some(number), Some(3)) => data.extend((number as i32).serialize_fixed()),
+            (Some(number), _) => data.extend(number.serialize()),
+            (None, _) => data.extend(number.as_f64().unwrap_or_default().to_be_bytes()),
+        },
+        Value::String(text) => write_nbt_string(data, text),
+        Value::Array(elements) => {
+            let tags: Vec<u8> = elements.iter().filter_map(json_tag).collect();
+            let tag = tags.first().copied().unwrap_or(0);
+            if tags.iter().all(|element| *element == tag) {
+                data.push(tag);
+                data.extend((tags.len() as i32).serialize_fixed());
+                for element in elements.iter().filter(|element| !element.is_null()) {
+                    write_json_payload(data, element);
+                }
+            } else {
+                // Lists hold a single tag type, so mixed components become {text: ..} compounds
+                data.push(10);
+                data.extend((tags.len() as i32).serialize_fixed());
+                for element in elements.iter().filter(|element| !element.is_null()) {
+                    match element {
+                        Value::Object(_) => write_json_payload(data, element),
+                        Value::String(text) => write_json_payload(data, &serde_json::json!({"text": text})),
+                        _ => write_json_payload(data, &serde_json::json!({"text": element.to_string()})),
+                    }
+                }
+            }
+        }
+        Value::Object(entries) => {
+            for (key, value) in entries {
+                let Some(tag) = json_tag(value) else {
+                    continue;
+                };
+                data.push(tag);
+                write_nbt_string(data, key);
+                write_json_payload(data, value);
+            }
+            data.push(0);
+        }
+    }
+}
+
+/// A data pack both sides have, registries from it are sent without their contents
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct KnownPack {
+    pub namespace: String,
+    pub id: String,
+    pub version: String,
+}
+
+/// One entry of a Registry Data packet, the data is left out if it comes from a known pack
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct RegistryEntry {
+    pub id: String,
+    #[option]
+    pub data: Option<NetworkNbt>,
+}
+
+/// An inventory slot, empty slots are sent as item id -1
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Slot {
+    pub item: Option<ItemStack>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemStack {
+    pub id: i16,
+    pub count: i8,
+    pub damage: i16,
+    pub nbt: RawNbt,
+}
+
+impl Serializable for Slot {
+    fn serialize(&self) -> Vec<u8> {
+        let Some(item) = &self.item else {
+            return (-1i16).serialize();
+        };
+        let mut data = item.id.serialize();
+        data.extend(item.count.serialize());
+        data.extend(item.damage.serialize());
+        data.extend(item.nbt.serialize());
+        data
+    }
+}
+
+impl Decode for Slot {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        let id = i16::decode(cursor)?;
+        if id == -1 {
+            return Ok(Slot::default());
+        }
+        Ok(Slot{
+            item: Some(ItemStack{
+                id,
+                count: i8::decode(cursor)?,
+                damage: i16::decode(cursor)?,
+                nbt: RawNbt::decode(cursor)?,
+            }),
+        })
+    }
+}
+
+/// The entity metadata list, terminated by 0x7F on the wire
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntityMetadata {
+    pub entries: Vec<MetadataEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataEntry {
+    /// 0 to 31
+    pub index: u8,
+    pub value: MetadataValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Float(f32),
+    String(String),
+    Slot(Slot),
+    Position(i32, i32, i32),
+    Rotation(f32, f32, f32),
+}
+
+impl Serializable for EntityMetadata {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for entry in &self.entries {
+            let (kind, value) = match &entry.value {
+                MetadataValue::Byte(value) => (0, value.serialize()),
+                MetadataValue::Short(value) => (1, value.serialize()),
+                MetadataValue::Int(value) => (2, value.serialize_fixed()),
+                MetadataValue::Float(value) => (3, value.serialize()),
+                MetadataValue::String(value) => (4, value.serialize()),
+                MetadataValue::Slot(value) => (5, value.serialize()),
+                MetadataValue::Position(x, y, z) => (6, [x.serialize_fixed(), y.serialize_fixed(), z.serialize_fixed()].concat()),
+                MetadataValue::Rotation(x, y, z) => (7, [x.serialize(), y.serialize(), z.serialize()].concat()),
+            };
+            // The type is in the upper 3 bits of the key, the index in the lower 5
+            data.push(kind << 5 | (entry.index & 0x1F));
+            data.extend(value);
+        }
+        data.push(0x7F);
+        data
+    }
+}
+
+impl Decode for EntityMetadata {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        let mut entries = Vec::new();
+        loop {
+            let key = u8::decode(cursor)?;
+            if key == 0x7F {
+                return Ok(EntityMetadata{entries});
+            }
+            let value = match key >> 5 {
+                0 => MetadataValue::Byte(i8::decode(cursor)?),
+                1 => MetadataValue::Short(i16::decode(cursor)?),
+                2 => MetadataValue::Int(i32::decode_fixed(cursor)?),
+                3 => MetadataValue::Float(f32::decode(cursor)?),
+                4 => MetadataValue::String(String::decode(cursor)?),
+                5 => MetadataValue::Slot(Slot::decode(cursor)?),
+                6 => MetadataValue::Position(i32::decode_fixed(cursor)?, i32::decode_fixed(cursor)?, i32::decode_fixed(cursor)?),
+                _ => MetadataValue::Rotation(f32::decode(cursor)?, f32::decode(cursor)?, f32::decode(cursor)?),
+            };
+            entries.push(MetadataEntry{index: key & 0x1F, value});
+        }
+    }
+}
+
+/// The data field of Spawn Object, followed by a velocity unless it is 0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjectData {
+    pub data: i32,
+    pub velocity: [i16; 3],
+}
+
+impl Serializable for ObjectData {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = self.data.serialize_fixed();
+        if self.data != 0 {
+            for velocity in self.velocity {
+                data.extend(velocity.serialize());
+            }
+        }
+        data
+    }
+}
+
+impl Decode for ObjectData {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        let data = i32::decode_fixed(cursor)?;
+        let mut velocity = [0; 3];
+        if data != 0 {
+            for velocity in &mut velocity {
+                *velocity = i16::decode(cursor)?;
+            }
+        }
+        Ok(ObjectData{data, velocity})
+    }
+}
+
+/// Position and sent sections of a column in a Map Chunk Bulk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct ChunkMeta {
+    #[fixed]
+    pub chunk_x: i32,
+    #[fixed]
+    pub chunk_z: i32,
+    pub primary_bit_mask: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct BlockChangeRecord {
+    /// x in the upper 4 bits, z in the lower 4 bits, relative to the chunk
+    pub horizontal_position: u8,
+    pub y: u8,
+    #[varint]
+    pub block_id: i32,
+}
+
+/// Offset of a destroyed block from the center of an explosion
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct ExplosionRecord {
+    pub x: i8,
+    pub y: i8,
+    pub z: i8,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct MapIcon {
+    /// Direction in the upper 4 bits, type in the lower 4 bits
+    pub direction_and_type: i8,
+    pub x: i8,
+    pub z: i8,
+}
+
+/// The updated part of a map. Only the column count is sent if it is 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapUpdate {
+    pub columns: u8,
+    pub rows: u8,
+    pub x: u8,
+    pub z: u8,
+    pub data: Vec<u8>,
+}
+
+impl Serializable for MapUpdate {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![self.columns];
+        if self.columns > 0 {
+            data.extend([self.rows, self.x, self.z]);
+            data.extend(self.data.serialize());
+        }
+        data
+    }
+}
+
+impl Decode for MapUpdate {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        let columns = u8::decode(cursor)?;
+        if columns == 0 {
+            return Ok(MapUpdate::default());
+        }
+        Ok(MapUpdate{
+            columns,
+            rows: u8::decode(cursor)?,
+            x: u8::decode(cursor)?,
+            z: u8::decode(cursor)?,
+            data: Vec::decode(cursor)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct Statistic {
+    pub name: String,
+    #[varint]
+    pub value: i32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serializable, Decode)]
+pub struct EntityProperty {
+    pub key: String,
+    pub value: f64,
+    #[length_prefixed]
+    pub modifiers: Vec<AttributeModifier>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serializable, Decode)]
+pub struct AttributeModifier {
+    pub uuid: Uuid,
+    pub amount: f64,
+    pub operation: i8,
+}
+
+/// What a Player List Item packet does to the listed players
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerListAction {
+    AddPlayer(Vec<PlayerListAdd>),
+    UpdateGamemode(Vec<PlayerListGamemode>),
+    UpdateLatency(Vec<PlayerListLatency>),
+    UpdateDisplayName(Vec<PlayerListDisplayName>),
+    RemovePlayer(Vec<Uuid>),
+}
+
+impl Default for PlayerListAction {
+    fn default() -> Self {
+        PlayerListAction::RemovePlayer(Vec::new())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct PlayerListAdd {
+    pub uuid: Uuid,
+    pub name: String,
+    #[length_prefixed]
+    pub properties: Vec<PlayerListProperty>,
+    #[varint]
+    pub gamemode: i32,
+    #[varint]
+    pub ping: i32,
+    #[option]
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct PlayerListProperty {
+    pub name: String,
+    pub value: String,
+    #[option]
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct PlayerListGamemode {
+    pub uuid: Uuid,
+    #[varint]
+    pub gamemode: i32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct PlayerListLatency {
+    pub uuid: Uuid,
+    #[varint]
+    pub ping: i32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct PlayerListDisplayName {
+    pub uuid: Uuid,
+    #[option]
+    pub display_name: Option<String>,
+}
+
+impl Serializable for PlayerListAction {
+    fn serialize(&self) -> Vec<u8> {
+        let (action, players) = match self {
+            PlayerListAction::AddPlayer(players) => (0, players.serialize()),
+            PlayerListAction::UpdateGamemode(players) => (1, players.serialize()),
+            PlayerListAction::UpdateLatency(players) => (2, players.serialize()),
+            PlayerListAction::UpdateDisplayName(players) => (3, players.serialize()),
+            PlayerListAction::RemovePlayer(players) => (4, players.serialize()),
+        };
+        let mut data = action.serialize_varint();
+        data.extend(players);
+        data
+    }
+}
+
+impl Decode for PlayerListAction {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        match i32::decode_varint(cursor)? {
+            0 => Ok(PlayerListAction::AddPlayer(Vec::decode(cursor)?)),
+            1 => Ok(PlayerListAction::UpdateGamemode(Vec::decode(cursor)?)),
+            2 => Ok(PlayerListAction::UpdateLatency(Vec::decode(cursor)?)),
+            3 => Ok(PlayerListAction::UpdateDisplayName(Vec::decode(cursor)?)),
+            4 => Ok(PlayerListAction::RemovePlayer(Vec::decode(cursor)?)),
+            action => Err(ProtocolError::InvalidField(format!("player list action {action}"))),
+        }
+    }
+}
+
+/// Mode of a Scoreboard Objective packet, only creating and updating send the display text
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ObjectiveAction {
+    Create {
+        value: String,
+        /// "integer" or "hearts"
+        kind: String,
+    },
+    #[default]
+    Remove,
+    Update {
+        value: String,
+        kind: String,
+    },
+}
+
+impl Serializable for ObjectiveAction {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            ObjectiveAction::Create{value, kind} => [0i8.serialize(), value.serialize(), kind.serialize()].concat(),
+            ObjectiveAction::Remove => 1i8.serialize(),
+            ObjectiveAction::Update{value, kind} => [2i8.serialize(), value.serialize(), kind.serialize()].concat(),
+        }
+    }
+}
+
+impl Decode for ObjectiveAction {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        match i8::decode(cursor)? {
+            0 => Ok(ObjectiveAction::Create{value: String::decode(cursor)?, kind: String::decode(cursor)?}),
+            1 => Ok(ObjectiveAction::Remove),
+            2 => Ok(ObjectiveAction::Update{value: String::decode(cursor)?, kind: String::decode(cursor)?}),
+            mode => Err(ProtocolError::InvalidField(format!("objective mode {mode}"))),
+        }
+    }
+}
+
+/// Action and objective of an Update Score packet, the value is only sent when updating
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScoreAction {
+    Update {
+        objective: String,
+        value: i32,
+    },
+    Remove {
+        objective: String,
+    },
+}
+
+impl Default for ScoreAction {
+    fn default() -> Self {
+        ScoreAction::Remove{objective: String::new()}
+    }
+}
+
+impl Serializable for ScoreAction {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            ScoreAction::Update{objective, value} => [0i8.serialize(), objective.serialize(), value.serialize_varint()].concat(),
+            ScoreAction::Remove{objective} => [1i8.serialize(), objective.serialize()].concat(),
+        }
+    }
+}
+
+impl Decode for ScoreAction {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        match i8::decode(cursor)? {
+            0 => Ok(ScoreAction::Update{objective: String::decode(cursor)?, value: i32::decode_varint(cursor)?}),
+            1 => Ok(ScoreAction::Remove{objective: String::decode(cursor)?}),
+            action => Err(ProtocolError::InvalidField(format!("score action {action}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serializable, Decode)]
+pub struct TeamInfo {
+    pub display_name: String,
+    pub prefix: String,
+    pub suffix: String,
+    pub friendly_fire: i8,
+    /// "always", "hideForOtherTeams", "hideForOwnTeam" or "never"
+    pub name_tag_visibility: String,
+    pub color: i8,
+}
+
+/// Mode of a Teams packet with the fields it sends
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TeamAction {
+    Create {
+        info: TeamInfo,
+        players: Vec<String>,
+    },
+    #[default]
+    Remove,
+    UpdateInfo(TeamInfo),
+    AddPlayers(Vec<String>),
+    RemovePlayers(Vec<String>),
+}
+
+impl Serializable for TeamAction {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            TeamAction::Create{info, players} => [0i8.serialize(), info.serialize(), players.serialize()].concat(),
+            TeamAction::Remove => 1i8.serialize(),
+            TeamAction::UpdateInfo(info) => [2i8.serialize(), info.serialize()].concat(),
+            TeamAction::AddPlayers(players) => [3i8.serialize(), players.serialize()].concat(),
+            TeamAction::RemovePlayers(players) => [4i8.serialize(), players.serialize()].concat(),
+        }
+    }
+}
+
+impl Decode for TeamAction {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        match i8::decode(cursor)? {
+            0 => Ok(TeamAction::Create{info: TeamInfo::decode(cursor)?, players: Vec::decode(cursor)?}),
+            1 => Ok(TeamAction::Remove),
+            2 => Ok(TeamAction::UpdateInfo(TeamInfo::decode(cursor)?)),
+            3 => Ok(TeamAction::AddPlayers(Vec::decode(cursor)?)),
+            4 => Ok(TeamAction::RemovePlayers(Vec::decode(cursor)?)),
+            mode => Err(ProtocolError::InvalidField(format!("team mode {mode}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TitleAction {
+    SetTitle(String),
+    SetSubtitle(String),
+    /// Fade in, stay and fade out in ticks
+    SetTimes(i32, i32, i32),
+    Hide,
+    #[default]
+    Reset,
+}
+
+impl Serializable for TitleAction {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            TitleAction::SetTitle(text) => [0.serialize_varint(), text.serialize()].concat(),
+            TitleAction::SetSubtitle(text) => [1.serialize_varint(), text.serialize()].concat(),
+            TitleAction::SetTimes(fade_in, stay, fade_out) => {
+                [2.serialize_varint(), fade_in.serialize_fixed(), stay.serialize_fixed(), fade_out.serialize_fixed()].concat()
+            }
+            TitleAction::Hide => 3.serialize_varint(),
+            TitleAction::Reset => 4.serialize_varint(),
+        }
+    }
+}
+
+impl Decode for TitleAction {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        match i32::decode_varint(cursor)? {
+            0 => Ok(TitleAction::SetTitle(String::decode(cursor)?)),
+            1 => Ok(TitleAction::SetSubtitle(String::decode(cursor)?)),
+            2 => Ok(TitleAction::SetTimes(i32::decode_fixed(cursor)?, i32::decode_fixed(cursor)?, i32::decode_fixed(cursor)?)),
+            3 => Ok(TitleAction::Hide),
+            4 => Ok(TitleAction::Reset),
+            action => Err(ProtocolError::InvalidField(format!("title action {action}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldBorderAction {
+    SetSize {
+        diameter: f64,
+    },
+    LerpSize {
+        old_diameter: f64,
+        new_diameter: f64,
+        /// Milliseconds until the new diameter is reached
+        speed: i64,
+    },
+    SetCenter {
+        x: f64,
+        z: f64,
+    },
+    Initialize {
+        x: f64,
+        z: f64,
+        old_diameter: f64,
+        new_diameter: f64,
+        speed: i64,
+        portal_teleport_boundary: i32,
+        warning_time: i32,
+        warning_blocks: i32,
+    },
+    SetWarningTime(i32),
+    SetWarningBlocks(i32),
+}
+
+impl Default for WorldBorderAction {
+    fn default() -> Self {
+        WorldBorderAction::SetSize{diameter: 0.0}
+    }
+}
+
+impl Serializable for WorldBorderAction {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            WorldBorderAction::SetSize{diameter} => [0.serialize_varint(), diameter.serialize()].concat(),
+            WorldBorderAction::LerpSize{old_diameter, new_diameter, speed} => {
+                [1.serialize_varint(), old_diameter.serialize(), new_diameter.serialize(), VarLong{value: *speed}.serialize()].concat()
+            }
+            WorldBorderAction::SetCenter{x, z} => [2.serialize_varint(), x.serialize(), z.serialize()].concat(),
+            WorldBorderAction::Initialize{x, z, old_diameter, new_diameter, speed, portal_teleport_boundary, warning_time, warning_blocks} => [
+                3.serialize_varint(),
+                x.serialize(),
+                z.serialize(),
+                old_diameter.serialize(),
+                new_diameter.serialize(),
+                VarLong{value: *speed}.serialize(),
+                portal_teleport_boundary.serialize_varint(),
+                warning_time.serialize_varint(),
+                warning_blocks.serialize_varint(),
+            ].concat(),
+            WorldBorderAction::SetWarningTime(time) => [4.serialize_varint(), time.serialize_varint()].concat(),
+            WorldBorderAction::SetWarningBlocks(blocks) => [5.serialize_varint(), blocks.serialize_varint()].concat(),
+        }
+    }
+}
+
+impl Decode for WorldBorderAction {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        match i32::decode_varint(cursor)? {
+            0 => Ok(WorldBorderAction::SetSize{diameter: f64::decode(cursor)?}),
+            1 => Ok(WorldBorderAction::LerpSize{
+                old_diameter: f64::decode(cursor)?,
+                new_diameter: f64::decode(cursor)?,
+                speed: VarLong::decode(cursor)?.value,
+            }),
+            2 => Ok(WorldBorderAction::SetCenter{x: f64::decode(cursor)?, z: f64::decode(cursor)?}),
+            3 => Ok(WorldBorderAction::Initialize{
+                x: f64::decode(cursor)?,
+                z: f64::decode(cursor)?,
+                old_diameter: f64::decode(cursor)?,
+                new_diameter: f64::decode(cursor)?,
+                speed: VarLong::decode(cursor)?.value,
+                portal_teleport_boundary: i32::decode_varint(cursor)?,
+                warning_time: i32::decode_varint(cursor)?,
+                warning_blocks: i32::decode_varint(cursor)?,
+            }),
+            4 => Ok(WorldBorderAction::SetWarningTime(i32::decode_varint(cursor)?)),
+            5 => Ok(WorldBorderAction::SetWarningBlocks(i32::decode_varint(cursor)?)),
+            action => Err(ProtocolError::InvalidField(format!("world border action {action}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CombatEvent {
+    #[default]
+    EnterCombat,
+    EndCombat {
+        duration: i32,
+        entity_id: i32,
+    },
+    EntityDead {
+        player_id: i32,
+        entity_id: i32,
+        message: String,
+    },
+}
+
+impl Serializable for CombatEvent {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            CombatEvent::EnterCombat => 0.serialize_varint(),
+            CombatEvent::EndCombat{duration, entity_id} => {
+                [1.serialize_varint(), duration.serialize_varint(), entity_id.serialize_fixed()].concat()
+            }
+            CombatEvent::EntityDead{player_id, entity_id, message} => {
+                [2.serialize_varint(), player_id.serialize_varint(), entity_id.serialize_fixed(), message.serialize()].concat()
+            }
+        }
+    }
+}
+
+impl Decode for CombatEvent {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        match i32::decode_varint(cursor)? {
+            0 => Ok(CombatEvent::EnterCombat),
+            1 => Ok(CombatEvent::EndCombat{duration: i32::decode_varint(cursor)?, entity_id: i32::decode_fixed(cursor)?}),
+            2 => Ok(CombatEvent::EntityDead{
+                player_id: i32::decode_varint(cursor)?,
+                entity_id: i32::decode_fixed(cursor)?,
+                message: String::decode(cursor)?,
+            }),
+            event => Err(ProtocolError::InvalidField(format!("combat event {event}"))),
+        }
+    }
+}
+
+/// Type of a Use Entity packet, interacting at a point also sends the target position
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UseEntityAction {
+    #[default]
+    Interact,
+    Attack,
+    InteractAt {
+        x: f32,
+        y: f32,
+        z: f32,
+    },
+}
+
+impl Serializable for UseEntityAction {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            UseEntityAction::Interact => 0.serialize_varint(),
+            UseEntityAction::Attack => 1.serialize_varint(),
+            UseEntityAction::InteractAt{x, y, z} => [2.serialize_varint(), x.serialize(), y.serialize(), z.serialize()].concat(),
+        }
+    }
+}
+
+impl Decode for UseEntityAction {
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, ProtocolError> {
+        match i32::decode_varint(cursor)? {
+            0 => Ok(UseEntityAction::Interact),
+            1 => Ok(UseEntityAction::Attack),
+            2 => Ok(UseEntityAction::InteractAt{x: f32::decode(cursor)?, y: f32::decode(cursor)?, z: f32::decode(cursor)?}),
+            kind => Err(ProtocolError::InvalidField(format!("use entity type {kind}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Serializable + Decode>(value: &T) -> T {
+        let data = value.serialize();
+        let mut cursor = Cursor::new(data.as_slice());
+        let decoded = T::decode(&mut cursor).unwrap();
+        assert_eq!(serialization::remaining(&cursor), 0);
+        decoded
+    }
+
+    #[test]
+    fn chat_components_become_network_nbt() {
+        let text = NetworkNbt::from_json(r#"{"text":"Hi","bold":true,"extra":["a",{"text":"b"}]}"#);
+        let mut expected = vec![0x0A];
+        expected.extend([0x01, 0x00, 0x04, b'b', b'o', b'l', b'd', 0x01]);
+        expected.extend([0x09, 0x00, 0x05, b'e', b'x', b't', b'r', b'a', 0x0A, 0x00, 0x00, 0x00, 0x02]);
+        expected.extend([0x08, 0x00, 0x04, b't', b'e', b'x', b't', 0x00, 0x01, b'a', 0x00]);
+        expected.extend([0x08, 0x00, 0x04, b't', b'e', b'x', b't', 0x00, 0x01, b'b', 0x00]);
+        expected.extend([0x08, 0x00, 0x04, b't', b'e', b'x', b't', 0x00, 0x02, b'H', b'i']);
+        expected.push(0x00);
+        assert_eq!(text.data, expected);
+        assert_eq!(round_trip(&text), text);
+
+        assert_eq!(NetworkNbt::from_json("plain").data, [0x08, 0x00, 0x05, b'p', b'l', b'a', b'i', b'n']);
+        assert_eq!(NetworkNbt::default().serialize(), [0x00]);
+    }
+
+    #[test]
+    fn registry_entries_from_known_packs_have_no_data() {
+        let entry = RegistryEntry{id: "minecraft:overworld".to_owned(), data: None};
+        assert_eq!(entry.serialize(), [&"minecraft:overworld".to_owned().serialize()[..], &[0x00]].concat());
+        assert_eq!(round_trip(&entry), entry);
+    }
+
+    #[test]
+    fn slots_keep_their_nbt() {
+        // A stone sword named "A": {display: {Name: "A"}}
+        let nbt = vec![
+            0x0A, 0x00, 0x00,
+            0x0A, 0x00, 0x07, b'd', b'i', b's', b'p', b'l', b'a', b'y',
+            0x08, 0x00, 0x04, b'N', b'a', b'm', b'e', 0x00, 0x01, b'A',
+            0x00,
+            0x00,
+        ];
+        let mut data = vec![0x01, 0x10, 0x01, 0x00, 0x05];
+        data.extend(&nbt);
+        data.push(0xFF);
+        let cursor = &mut Cursor::new(data.as_slice());
+        let slot = Slot::decode(cursor).unwrap();
+        assert_eq!(slot, Slot{item: Some(ItemStack{id: 272, count: 1, damage: 5, nbt: RawNbt{data: nbt}})});
+        assert_eq!(serialization::remaining(cursor), 1);
+        assert_eq!(slot.serialize(), data[..data.len() - 1]);
+        let (_, tag) = slot.item.as_ref().unwrap().nbt.to_tag().unwrap().unwrap();
+        assert_eq!(tag.to_string(), r#"{display:{Name:"A"}}"#);
+        assert_eq!(RawNbt::from_tag("", &tag), slot.item.unwrap().nbt);
+
+        assert_eq!(Slot::default().serialize(), [0xFF, 0xFF]);
+        let plain = Slot{item: Some(ItemStack{id: 1, count: 64, damage: 0, nbt: RawNbt::default()})};
+        assert_eq!(plain.serialize(), [0x00, 0x01, 0x40, 0x00, 0x00, 0x00]);
+        assert_eq!(round_trip(&plain), plain);
+    }
+
+    #[test]
+    fn broken_nbt_is_an_error() {
+        assert!(RawNbt::decode(&mut Cursor::new(&[0x0A, 0x00, 0x00, 0x01, 0x00][..])).is_err());
+        assert!(RawNbt::decode(&mut Cursor::new(&[0x0C, 0x00, 0x00][..])).is_err());
+        let mut deep = vec![0x09, 0x00, 0x00];
+        for _ in 0..1000 {
+            deep.extend([0x09, 0x00, 0x00, 0x00, 0x01]);
+        }
+        assert!(RawNbt::decode(&mut Cursor::new(deep.as_slice())).is_err());
+    }
+
+    #[test]
+    fn entity_metadata_packs_type_and_index() {
+        let metadata = EntityMetadata{
+            entries: vec![
+                MetadataEntry{index: 0, value: MetadataValue::Byte(0x02)},
+                MetadataEntry{index: 6, value: MetadataValue::Float(20.0)},
+                MetadataEntry{index: 2, value: MetadataValue::String("Tester".to_owned())},
+                MetadataEntry{index: 10, value: MetadataValue::Position(1, -2, 3)},
+            ],
+        };
+        let data = metadata.serialize();
+        assert_eq!(&data[..4], [0x00, 0x02, 0x66, 0x41]);
+        assert_eq!(data.last(), Some(&0x7F));
+        assert_eq!(round_trip(&metadata), metadata);
+    }
+
+    #[test]
+    fn conditional_fields_follow_their_flags() {
+        assert_eq!(ObjectData{data: 0, velocity: [1, 2, 3]}.serialize(), [0, 0, 0, 0]);
+        let object = ObjectData{data: 1, velocity: [1, -2, 3]};
+        assert_eq!(round_trip(&object), object);
+
+        assert_eq!(MapUpdate::default().serialize(), [0]);
+        let update = MapUpdate{columns: 2, rows: 1, x: 3, z: 4, data: vec![5, 6]};
+        assert_eq!(round_trip(&update), update);
+
+        let action = UseEntityAction::InteractAt{x: 0.5, y: 1.0, z: -0.5};
+        assert_eq!(round_trip(&action), action);
+        assert_eq!(UseEntityAction::Attack.serialize(), [1]);
+        assert!(UseEntityAction::decode(&mut Cursor::new(&[3][..])).is_err());
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        let players = PlayerListAction::AddPlayer(vec![PlayerListAdd{
+            uuid: Uuid::new_v4(),
+            name: "Tester".to_owned(),
+            properties: vec![PlayerListProperty{name: "textures".to_owned(), value: "e30=".to_owned(), signature: None}],
+            gamemode: 1,
+            ping: 42,
+            display_name: Some("{\"text\":\"Tester\"}".to_owned()),
+        }]);
+        assert_eq!(round_trip(&players), players);
+        let removed = PlayerListAction::RemovePlayer(vec![Uuid::new_v4(), Uuid::new_v4()]);
+        assert_eq!(round_trip(&removed), removed);
+
+        let team = TeamAction::Create{info: TeamInfo::default(), players: vec!["Tester".to_owned()]};
+        assert_eq!(round_trip(&team), team);
+        let score = ScoreAction::Update{objective: "kills".to_owned(), value: 3};
+        assert_eq!(round_trip(&score), score);
+        assert_eq!(round_trip(&ObjectiveAction::Remove), ObjectiveAction::Remove);
+        let title = TitleAction::SetTimes(10, 70, 20);
+        assert_eq!(round_trip(&title), title);
+        let border = WorldBorderAction::LerpSize{old_diameter: 10.0, new_diameter: 20.0, speed: 1000};
+        assert_eq!(round_trip(&border), border);
+        let combat = CombatEvent::EntityDead{player_id: 1, entity_id: -1, message: "{}".to_owned()};
+        assert_eq!(round_trip(&combat), combat);
+    }
+}
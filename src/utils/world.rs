@@ -0,0 +1,219 @@
+//! The loaded chunks of a world, addressed by world coordinates like `World` in the bundled `smpmap.py`
+
+use std::collections::{HashMap, HashSet};
+
+use super::smpmap::{Block, ChunkColumn, COLUMN_HEIGHT, SECTION_WIDTH};
+
+/// Chunk x and z of a column
+pub type ChunkPos = (i32, i32);
+
+/// The column a block is in and the block's coordinates inside of it
+fn split(x: i32, z: i32) -> (ChunkPos, usize, usize) {
+    let width = SECTION_WIDTH as i32;
+    ((x.div_euclid(width), z.div_euclid(width)), x.rem_euclid(width) as usize, z.rem_euclid(width) as usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightType {
+    Block,
+    Sky,
+}
+
+/// A block that changed since the players were last told
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockChange {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub block: Block,
+}
+
+/// The block changes of a single column, what a Multi Block Change packet holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkChanges {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    /// At most one change per block, the latest one
+    pub changes: Vec<BlockChange>,
+}
+
+#[derive(Debug, Default)]
+pub struct World {
+    columns: HashMap<ChunkPos, ChunkColumn>,
+    /// Columns changed since they were loaded or last saved
+    dirty: HashSet<ChunkPos>,
+    /// Changes not taken by [`World::take_changes`] yet
+    changes: HashMap<ChunkPos, Vec<BlockChange>>,
+}
+
+impl World {
+    pub fn new() -> World {
+        World::default()
+    }
+
+    pub fn column(&self, chunk_x: i32, chunk_z: i32) -> Option<&ChunkColumn> {
+        self.columns.get(&(chunk_x, chunk_z))
+    }
+
+    /// Marks the column dirty since it can be changed through the reference
+    pub fn column_mut(&mut self, chunk_x: i32, chunk_z: i32) -> Option<&mut ChunkColumn> {
+        let column = self.columns.get_mut(&(chunk_x, chunk_z))?;
+        self.dirty.insert((chunk_x, chunk_z));
+        Some(column)
+    }
+
+    /// Loads a column, it isn't dirty until it is changed
+    pub fn insert_column(&mut self, chunk_x: i32, chunk_z: i32, column: ChunkColumn) {
+        self.columns.insert((chunk_x, chunk_z), column);
+        self.dirty.remove(&(chunk_x, chunk_z));
+    }
+
+    /// Unloads a column, pending changes of it are dropped
+    pub fn remove_column(&mut self, chunk_x: i32, chunk_z: i32) -> Option<ChunkColumn> {
+        self.dirty.remove(&(chunk_x, chunk_z));
+        self.changes.remove(&(chunk_x, chunk_z));
+        self.columns.remove(&(chunk_x, chunk_z))
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = (ChunkPos, &ChunkColumn)> {
+        self.columns.iter().map(|(pos, column)| (*pos, column))
+    }
+
+    /// Air outside of the loaded columns and below or above the world
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Block {
+        let (pos, x, z) = split(x, z);
+        match (self.columns.get(&pos), usize::try_from(y)) {
+            (Some(column), Ok(y)) if y < COLUMN_HEIGHT => column.get_block(x, y, z),
+            _ => Block::AIR,
+        }
+    }
+
+    /// Creates the column if it isn't loaded, blocks below or above the world are ignored.
+    /// Returns whether the block changed.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) -> bool {
+        if !(0..COLUMN_HEIGHT as i32).contains(&y) || self.get_block(x, y, z) == block {
+            return false;
+        }
+        let (pos, local_x, local_z) = split(x, z);
+        self.columns.entry(pos).or_default().set_block(local_x, y as usize, local_z, block);
+        self.dirty.insert(pos);
+        let changes = self.changes.entry(pos).or_default();
+        changes.retain(|change| (change.x, change.y, change.z) != (x, y, z));
+        changes.push(BlockChange{x, y, z, block});
+        true
+    }
+
+    /// Dark below the world and in unloaded columns, lit by the sky above it
+    pub fn get_light(&self, light: LightType, x: i32, y: i32, z: i32) -> u8 {
+        let (pos, x, z) = split(x, z);
+        let Some(column) = self.columns.get(&pos) else {
+            return 0;
+        };
+        let Ok(y) = usize::try_from(y) else {
+            return 0;
+        };
+        match light {
+            _ if y >= COLUMN_HEIGHT => if light == LightType::Sky { 15 } else { 0 },
+            LightType::Block => column.get_block_light(x, y, z),
+            LightType::Sky => column.get_sky_light(x, y, z),
+        }
+    }
+
+    /// Light isn't sent as block changes, only the column is marked dirty
+    pub fn set_light(&mut self, light: LightType, x: i32, y: i32, z: i32, value: u8) {
+        if !(0..COLUMN_HEIGHT as i32).contains(&y) {
+            return;
+        }
+        let (pos, x, z) = split(x, z);
+        let column = self.columns.entry(pos).or_default();
+        match light {
+            LightType::Block => column.set_block_light(x, y as usize, z, value),
+            LightType::Sky => column.set_sky_light(x, y as usize, z, value),
+        }
+        self.dirty.insert(pos);
+    }
+
+    pub fn get_biome(&self, x: i32, z: i32) -> u8 {
+        let (pos, x, z) = split(x, z);
+        self.columns.get(&pos).map_or(0, |column| column.get_biome(x, z))
+    }
+
+    pub fn set_biome(&mut self, x: i32, z: i32, biome: u8) {
+        let (pos, x, z) = split(x, z);
+        self.columns.entry(pos).or_default().set_biome(x, z, biome);
+        self.dirty.insert(pos);
+    }
+
+    pub fn is_dirty(&self, chunk_x: i32, chunk_z: i32) -> bool {
+        self.dirty.contains(&(chunk_x, chunk_z))
+    }
+
+    /// The columns changed since they were last saved, they count as saved afterwards
+    pub fn take_dirty(&mut self) -> Vec<ChunkPos> {
+        self.dirty.drain().collect()
+    }
+
+    /// The block changes since the last call, grouped by column
+    pub fn take_changes(&mut self) -> Vec<ChunkChanges> {
+        self.changes.drain()
+            .map(|((chunk_x, chunk_z), changes)| ChunkChanges{chunk_x, chunk_z, changes})
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_addressed_by_world_coordinates() {
+        let mut world = World::new();
+        let stone = Block::new(1, 0);
+        assert_eq!(world.get_block(-1, 64, -1), Block::AIR);
+        assert!(world.set_block(-1, 64, -17, stone));
+        assert!(!world.set_block(-1, 64, -17, stone));
+        assert!(!world.set_block(0, 256, 0, stone));
+        assert!(!world.set_block(0, -1, 0, stone));
+
+        assert_eq!(world.get_block(-1, 64, -17), stone);
+        assert_eq!(world.column(-1, -2).unwrap().get_block(15, 64, 15), stone);
+        assert_eq!(world.get_block(-1, 64, -1), Block::AIR);
+        assert_eq!(world.get_block(-1, -1, -17), Block::AIR);
+
+        world.set_biome(33, -1, 4);
+        assert_eq!(world.get_biome(33, -1), 4);
+        assert_eq!(world.column(2, -1).unwrap().get_biome(1, 15), 4);
+
+        world.set_light(LightType::Block, 5, 10, 5, 12);
+        assert_eq!(world.get_light(LightType::Block, 5, 10, 5), 12);
+        assert_eq!(world.get_light(LightType::Sky, 5, 300, 5), 15);
+        assert_eq!(world.get_light(LightType::Sky, 500, 10, 5), 0);
+    }
+
+    #[test]
+    fn changes_are_tracked_per_column() {
+        let mut world = World::new();
+        world.insert_column(0, 0, ChunkColumn::default());
+        assert!(!world.is_dirty(0, 0));
+
+        world.set_block(1, 2, 3, Block::new(1, 0));
+        world.set_block(1, 2, 3, Block::new(35, 4));
+        world.set_block(4, 40, 5, Block::new(3, 0));
+        world.set_block(16, 0, 0, Block::new(3, 0));
+        assert!(world.is_dirty(0, 0) && world.is_dirty(1, 0));
+
+        let mut changes = world.take_changes();
+        changes.sort_by_key(|changes| changes.chunk_x);
+        assert_eq!(changes[0].changes, [
+            BlockChange{x: 1, y: 2, z: 3, block: Block::new(35, 4)},
+            BlockChange{x: 4, y: 40, z: 5, block: Block::new(3, 0)},
+        ]);
+        assert_eq!((changes[1].chunk_x, changes[1].changes.len()), (1, 1));
+        assert!(world.take_changes().is_empty());
+
+        let mut dirty = world.take_dirty();
+        dirty.sort();
+        assert_eq!(dirty, [(0, 0), (1, 0)]);
+        assert!(!world.is_dirty(0, 0));
+    }
+}